@@ -0,0 +1,504 @@
+//! The bot module contains the `Bot` type which routes commands such as `!roll 2d6`,
+//! sent to a channel or in a private query, to the handlers registered for them.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use irc::casemap;
+use irc::error::Result;
+use irc::event::{Context, Event, Handler, Propagation};
+use irc::isupport::CaseMapping;
+use irc::message::Message;
+use irc::tag::Account;
+
+// How long a user refused a command has to wait before being told so again, for
+// commands without a cooldown of their own.
+const REFUSAL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Who may run a bot command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Permission {
+    /// Anyone may run the command.
+    Anyone,
+    /// Only users whose `nick!user@host` mask matches one of the given masks may run the
+    /// command. The masks may contain the `*` and `?` wildcards.
+    Hostmasks(Vec<String>),
+    /// Only users logged in to one of the given accounts may run the command.
+    Accounts(Vec<String>),
+}
+
+/// A command being run, as passed to the handler of a `BotCommand`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invocation<'a> {
+    /// The nickname of the user running the command.
+    pub nick: &'a str,
+    /// The channel or nickname the message was sent to.
+    pub target: &'a str,
+    /// The name of the command, without the prefix.
+    pub command: &'a str,
+    /// The arguments of the command, with quotes removed.
+    pub args: Vec<String>,
+}
+
+type CommandHandler = Box<dyn FnMut(&Invocation, &mut Context) -> Result<()>>;
+
+/// A command the bot responds to.
+///
+/// ```ignore
+/// let roll = BotCommand::new("roll", "Rolls a die.", |invocation, context| {
+///     context.reply(&format!("{} rolled a 4", invocation.nick))
+/// }).cooldown(Duration::from_secs(10));
+/// ```
+pub struct BotCommand {
+    name: String,
+    help: String,
+    permission: Permission,
+    cooldown: Option<Duration>,
+    handler: CommandHandler,
+}
+
+impl BotCommand {
+    /// Create a new command with the given name and help text, which anyone may run.
+    ///
+    /// An error returned by the handler is replied to the user running the command.
+    pub fn new<F>(name: &str, help: &str, handler: F) -> BotCommand
+    where
+        F: FnMut(&Invocation, &mut Context) -> Result<()> + 'static,
+    {
+        BotCommand {
+            name: name.to_owned(),
+            help: help.to_owned(),
+            permission: Permission::Anyone,
+            cooldown: None,
+            handler: Box::new(handler),
+        }
+    }
+
+    /// Restricts who may run the command.
+    pub fn permission(mut self, permission: Permission) -> BotCommand {
+        self.permission = permission;
+        self
+    }
+
+    /// Sets how long a user has to wait before running the command again. Commands run
+    /// before the cooldown has passed are ignored. Users are told apart by their host,
+    /// so that changing nicknames doesn't get around the cooldown.
+    pub fn cooldown(mut self, cooldown: Duration) -> BotCommand {
+        self.cooldown = Some(cooldown);
+        self
+    }
+}
+
+/// A `Handler` that runs the registered commands when a message starts with the command
+/// prefix, as in `!roll 2d6`, or with the bot's nickname, as in `RustBot: roll 2d6`.
+///
+/// A `help` command listing the commands the user may run is provided, unless a command
+/// with that name is registered. Replies go to the channel the command was sent to, or to
+/// the user when it was sent in a private query.
+pub struct Bot {
+    prefix: String,
+    commands: Vec<BotCommand>,
+    last_run: HashMap<(String, String), Instant>,
+}
+
+impl Bot {
+    /// Create a new `Bot` with the given command prefix, such as `!`.
+    pub fn new(prefix: &str) -> Bot {
+        Bot {
+            prefix: prefix.to_owned(),
+            commands: Vec::new(),
+            last_run: HashMap::new(),
+        }
+    }
+
+    /// Registers a command. A command registered with the same name as an earlier one replaces it.
+    pub fn command(mut self, command: BotCommand) -> Bot {
+        self.commands.retain(|existing| !existing.name.eq_ignore_ascii_case(&command.name));
+        self.commands.push(command);
+        self
+    }
+
+    // Returns the command line of a message addressed to the bot, without the prefix.
+    fn command_line<'a>(&self, text: &'a str, own_nick: Option<&str>, casemapping: &CaseMapping) -> Option<&'a str> {
+        if !self.prefix.is_empty() && text.starts_with(self.prefix.as_str()) {
+            return Some(&text[self.prefix.len()..]);
+        }
+
+        let own_nick = own_nick?;
+        let addressee = text.split(&[':', ','][..]).next()?;
+
+        if addressee.len() < text.len() && casemap::eq(casemapping, addressee, own_nick) {
+            Some(text[addressee.len() + 1..].trim_start())
+        } else {
+            None
+        }
+    }
+
+    fn help(&self, invocation: &Invocation, context: &mut Context) -> Result<()> {
+        let prefix = self.prefix.clone();
+
+        match invocation.args.first() {
+            Some(name) => match self.commands.iter().find(|command| command.name.eq_ignore_ascii_case(name)) {
+                Some(command) => context.reply(&format!("{}{}: {}", prefix, command.name, command.help)),
+                None => context.reply(&format!("There is no command named {}.", name)),
+            },
+            None => {
+                let names: Vec<String> = self.commands
+                    .iter()
+                    .filter(|command| is_permitted(&command.permission, context))
+                    .map(|command| format!("{}{}", prefix, command.name))
+                    .collect();
+
+                context.reply(&format!("Commands: {}", names.join(", ")))
+            }
+        }
+    }
+
+    // Returns true if the sender used the command less than `cooldown` ago, and otherwise
+    // records that they used it now.
+    fn cooling_down(&mut self, index: usize, sender: &str, cooldown: Duration) -> bool {
+        let key = (self.commands[index].name.to_ascii_lowercase(), sender.to_owned());
+        let now = Instant::now();
+
+        if let Some(&last_run) = self.last_run.get(&key) {
+            if now.duration_since(last_run) < cooldown {
+                return true;
+            }
+        }
+
+        // NOTE: Entries are kept for as long as the longest cooldown, so that none expires early.
+        let longest = self.commands
+            .iter()
+            .filter_map(|command| command.cooldown)
+            .fold(REFUSAL_INTERVAL, ::std::cmp::max);
+
+        self.last_run.retain(|_, &mut last_run| now.duration_since(last_run) < longest);
+        self.last_run.insert(key, now);
+
+        false
+    }
+}
+
+impl Handler for Bot {
+    fn handle(&mut self, event: &Event, context: &mut Context) -> Propagation {
+        let (nick, target, text) = match *event {
+            Event::Message { source: Some(nick), target, text } => (nick, target, text),
+            _ => return Propagation::Continue,
+        };
+
+        let state = context.state();
+        let casemapping = state.isupport().casemapping();
+
+        let line = match self.command_line(text, state.nick(), &casemapping) {
+            Some(line) => line,
+            None => return Propagation::Continue,
+        };
+
+        let mut args = parse_arguments(line);
+
+        if args.is_empty() {
+            return Propagation::Continue;
+        }
+
+        let name = args.remove(0);
+        let invocation = Invocation {
+            nick: nick,
+            target: target,
+            command: &name,
+            args: args,
+        };
+
+        let index = match self.commands.iter().position(|command| command.name.eq_ignore_ascii_case(&name)) {
+            Some(index) => index,
+            None if name.eq_ignore_ascii_case("help") => {
+                if let Err(error) = self.help(&invocation, context) {
+                    let _ = context.reply(&error.to_string());
+                }
+
+                return Propagation::Stop;
+            }
+            None => return Propagation::Continue,
+        };
+
+        let sender = sender(context.message(), &casemapping);
+
+        if !is_permitted(&self.commands[index].permission, context) {
+            let interval = self.commands[index].cooldown.unwrap_or(REFUSAL_INTERVAL);
+
+            if !self.cooling_down(index, &sender, interval) {
+                let _ = context.reply(&format!("{}: You are not allowed to use {}.", nick, self.commands[index].name));
+            }
+
+            return Propagation::Stop;
+        }
+
+        if let Some(cooldown) = self.commands[index].cooldown {
+            if self.cooling_down(index, &sender, cooldown) {
+                return Propagation::Stop;
+            }
+        }
+
+        if let Err(error) = (self.commands[index].handler)(&invocation, context) {
+            let _ = context.reply(&format!("{}: {}", nick, error));
+        }
+
+        Propagation::Stop
+    }
+}
+
+// Identifies the sender of a message by their host, or by their nickname when the host is unknown.
+fn sender(message: &Message, casemapping: &CaseMapping) -> String {
+    match message.prefix() {
+        Some((_, _, Some(host))) => host.to_ascii_lowercase(),
+        Some((nick, _, None)) => casemap::casefold(casemapping, nick),
+        None => String::new(),
+    }
+}
+
+// Returns true if the sender of the message being handled may run a command with the given permission.
+fn is_permitted(permission: &Permission, context: &Context) -> bool {
+    let message = context.message();
+    let casemapping = context.isupport().casemapping();
+
+    match *permission {
+        Permission::Anyone => true,
+        Permission::Hostmasks(ref masks) => {
+            let hostmask = match message.prefix() {
+                Some((nick, Some(user), Some(host))) => format!("{}!{}@{}", nick, user, host),
+                _ => return false,
+            };
+
+            let hostmask = casemap::casefold(&casemapping, &hostmask);
+
+            masks.iter().any(|mask| wildcard_match(&casemap::casefold(&casemapping, mask), &hostmask))
+        }
+        Permission::Accounts(ref accounts) => {
+            let account = account(message, context);
+
+            account
+                .map(|account| accounts.iter().any(|allowed| casemap::eq(&casemapping, allowed, &account)))
+                .unwrap_or(false)
+        }
+    }
+}
+
+// Get the account of the sender, from the `account` tag or from what the state tracker knows.
+fn account(message: &Message, context: &Context) -> Option<String> {
+    if let Some(Account(account)) = message.tag::<Account>() {
+        return Some(account.into_owned());
+    }
+
+    message
+        .prefix()
+        .and_then(|(nick, _, _)| context.state().user(nick))
+        .and_then(|user| user.account.clone())
+}
+
+// Matches `value` against a mask containing the `*` and `?` wildcards.
+fn wildcard_match(mask: &str, value: &str) -> bool {
+    let mask: Vec<char> = mask.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+
+    let (mut m, mut v) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        if m < mask.len() && (mask[m] == '?' || mask[m] == value[v]) {
+            m += 1;
+            v += 1;
+        } else if m < mask.len() && mask[m] == '*' {
+            backtrack = Some((m, v));
+            m += 1;
+        } else if let Some((star, matched)) = backtrack {
+            m = star + 1;
+            v = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    mask[m..].iter().all(|&c| c == '*')
+}
+
+/// Splits a command line into its arguments. Arguments are separated by whitespace, unless it
+/// is within double or single quotes, and a backslash includes the next character as it is.
+/// Quotes are only recognized at the start of an argument, so the apostrophe in `it's` is kept.
+/// An unterminated quote runs to the end of the line.
+pub fn parse_arguments(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_argument = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+                in_argument = true;
+            }
+            '"' | '\'' if quote == Some(c) => quote = None,
+            '"' | '\'' if quote.is_none() && !in_argument => {
+                quote = Some(c);
+                in_argument = true;
+            }
+            c if c.is_whitespace() && quote.is_none() => {
+                if in_argument {
+                    args.push(current.clone());
+                    current.clear();
+                    in_argument = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_argument = true;
+            }
+        }
+    }
+
+    if in_argument {
+        args.push(current);
+    }
+
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use irc::event::{Dispatcher, EventKind};
+    use irc::state::State;
+
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        parse_arguments(line)
+    }
+
+    #[test]
+    fn splits_on_whitespace() {
+        assert_eq!(args("roll  2d6\t4"), vec!["roll", "2d6", "4"]);
+        assert_eq!(args("   "), Vec::<String>::new());
+    }
+
+    #[test]
+    fn keeps_quoted_whitespace() {
+        assert_eq!(args("echo \"hello world\" 'a b'"), vec!["echo", "hello world", "a b"]);
+        assert_eq!(args("echo \"it's\""), vec!["echo", "it's"]);
+        assert_eq!(args("echo \"\""), vec!["echo", ""]);
+    }
+
+    #[test]
+    fn keeps_quotes_within_words() {
+        assert_eq!(args("echo it's fine"), vec!["echo", "it's", "fine"]);
+        assert_eq!(args("echo don't won't"), vec!["echo", "don't", "won't"]);
+        assert_eq!(args("echo say\"hi\""), vec!["echo", "say\"hi\""]);
+    }
+
+    #[test]
+    fn unterminated_quotes_run_to_the_end() {
+        assert_eq!(args("echo \"hello world"), vec!["echo", "hello world"]);
+        assert_eq!(args("echo 'a"), vec!["echo", "a"]);
+    }
+
+    #[test]
+    fn backslash_escapes() {
+        assert_eq!(args("echo a\\ b \\\"c"), vec!["echo", "a b", "\"c"]);
+        assert_eq!(args("echo \"a\\\"b\""), vec!["echo", "a\"b"]);
+        assert_eq!(args("echo a\\"), vec!["echo", "a"]);
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(wildcard_match("*!*@example.com", "nick!user@example.com"));
+        assert!(wildcard_match("ni?k!*", "nick!user@host"));
+        assert!(!wildcard_match("*!*@example.com", "nick!user@example.org"));
+        assert!(wildcard_match("*", ""));
+    }
+
+    fn run(dispatcher: &mut Dispatcher, state: &State, line: &str) -> Vec<String> {
+        let message = Message::try_from(line.to_owned()).unwrap();
+
+        dispatcher.dispatch(&message, state)
+            .iter()
+            .map(|message| message.raw_message().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn runs_commands() {
+        let mut state = State::new();
+        state.handle(&Message::try_from(":server 001 Bot :Welcome".to_owned()).unwrap());
+
+        let echo = BotCommand::new("echo", "Repeats its arguments.", |invocation, context| {
+            context.reply(&invocation.args.join("|"))
+        });
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.register(&[EventKind::Message], 0, Bot::new("!").command(echo));
+
+        assert_eq!(run(&mut dispatcher, &state, ":friend!f@host PRIVMSG #a :!echo it's fine"),
+                   vec!["PRIVMSG #a it's|fine"]);
+        assert_eq!(run(&mut dispatcher, &state, ":friend!f@host PRIVMSG Bot :bot: echo 'a b' c"),
+                   vec!["PRIVMSG friend :a b|c"]);
+        assert_eq!(run(&mut dispatcher, &state, ":friend!f@host PRIVMSG #a :!help echo"),
+                   vec!["PRIVMSG #a :!echo: Repeats its arguments."]);
+        assert!(run(&mut dispatcher, &state, ":friend!f@host PRIVMSG #a :echo hi").is_empty());
+    }
+
+    fn echo_bot(command: BotCommand) -> (Dispatcher, State) {
+        let mut state = State::new();
+        state.handle(&Message::try_from(":server 001 Bot :Welcome".to_owned()).unwrap());
+
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.register(&[EventKind::Message], 0, Bot::new("!").command(command));
+
+        (dispatcher, state)
+    }
+
+    fn echo() -> BotCommand {
+        BotCommand::new("echo", "Repeats its arguments.", |invocation, context| {
+            context.reply(&invocation.args.join(" "))
+        })
+    }
+
+    #[test]
+    fn cools_down_by_host() {
+        let (mut dispatcher, state) = echo_bot(echo().cooldown(Duration::from_secs(60)));
+
+        assert_eq!(run(&mut dispatcher, &state, ":friend!f@host PRIVMSG #a :!echo one"), vec!["PRIVMSG #a one"]);
+        assert!(run(&mut dispatcher, &state, ":friend!f@host PRIVMSG #a :!echo two").is_empty());
+        assert!(run(&mut dispatcher, &state, ":renamed!f@HOST PRIVMSG #a :!echo three").is_empty());
+        assert_eq!(run(&mut dispatcher, &state, ":other!o@elsewhere PRIVMSG #a :!echo four"), vec!["PRIVMSG #a four"]);
+    }
+
+    #[test]
+    fn keeps_cooldowns_longer_than_an_hour() {
+        let mut bot = Bot::new("!").command(echo().cooldown(Duration::from_secs(3 * 3600)));
+
+        // NOTE: On a system that booted less than two hours ago there is no such instant.
+        let earlier = match Instant::now().checked_sub(Duration::from_secs(2 * 3600)) {
+            Some(earlier) => earlier,
+            None => return,
+        };
+
+        bot.last_run.insert(("echo".to_owned(), "host".to_owned()), earlier);
+
+        assert!(!bot.cooling_down(0, "elsewhere", Duration::from_secs(3 * 3600)));
+        assert!(bot.cooling_down(0, "host", Duration::from_secs(3 * 3600)));
+    }
+
+    #[test]
+    fn limits_refusals() {
+        let command = echo().permission(Permission::Hostmasks(vec!["admin!*@*".to_owned()]));
+        let (mut dispatcher, state) = echo_bot(command);
+
+        assert_eq!(
+            run(&mut dispatcher, &state, ":friend!f@host PRIVMSG #a :!echo hi"),
+            vec!["PRIVMSG #a :friend: You are not allowed to use echo."]
+        );
+        assert!(run(&mut dispatcher, &state, ":friend!f@host PRIVMSG #a :!echo hi").is_empty());
+        assert_eq!(run(&mut dispatcher, &state, ":admin!a@admin.host PRIVMSG #a :!echo hi"), vec!["PRIVMSG #a hi"]);
+    }
+}
@@ -0,0 +1,240 @@
+//! The builder module contains the `MessageBuilder` type which composes
+//! messages from their parts while making sure the result is a valid line.
+
+use irc::error::{ErrorKind, Result};
+use irc::message::Message;
+use irc::tag;
+
+/// A builder for messages to be sent to the server.
+///
+/// Every part is checked when the message is built, so that values taken
+/// from user input can't corrupt the line or inject further commands:
+///
+/// * No part may contain a CR, LF or NUL character.
+/// * Parameters other than the last may not be empty, contain a space or
+///   start with `:`. The last parameter is sent in its trailing `:` form when
+///   it needs to be, unless it was added with `middle`.
+/// * Tag values are escaped.
+///
+/// ```ignore
+/// let message = MessageBuilder::new("PRIVMSG")
+///     .tag("+draft/reply", Some("abc123"))
+///     .param("#rust")
+///     .param("Hello, world!")
+///     .build()?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct MessageBuilder {
+    tags: Vec<(String, Option<String>)>,
+    source: Option<String>,
+    command: String,
+    params: Vec<(String, bool)>,
+}
+
+impl MessageBuilder {
+    /// Create a new `MessageBuilder` for the given command.
+    pub fn new(command: &str) -> MessageBuilder {
+        MessageBuilder {
+            tags: Vec::new(),
+            source: None,
+            command: command.to_owned(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Adds a tag with an optional value, which is escaped as required.
+    pub fn tag(mut self, key: &str, value: Option<&str>) -> MessageBuilder {
+        self.tags.push((key.to_owned(), value.map(|value| value.to_owned())));
+        self
+    }
+
+    /// Sets the source of the message, which is sent as its prefix.
+    pub fn source(mut self, source: &str) -> MessageBuilder {
+        self.source = Some(source.to_owned());
+        self
+    }
+
+    /// Adds a parameter after any previously added ones.
+    pub fn param(mut self, param: &str) -> MessageBuilder {
+        self.params.push((param.to_owned(), false));
+        self
+    }
+
+    /// Adds a parameter that may never be sent in the trailing form, such as
+    /// a nickname or a channel name.
+    pub fn middle(mut self, param: &str) -> MessageBuilder {
+        self.params.push((param.to_owned(), true));
+        self
+    }
+
+    /// Adds a parameter if `param` is `Some`.
+    pub fn param_opt(self, param: Option<&str>) -> MessageBuilder {
+        match param {
+            Some(param) => self.param(param),
+            None => self,
+        }
+    }
+
+    /// Validates the parts of the message and builds it.
+    pub fn build(self) -> Result<Message> {
+        let mut line = String::new();
+
+        if !self.tags.is_empty() {
+            line.push('@');
+
+            for (index, &(ref key, ref value)) in self.tags.iter().enumerate() {
+                if key.is_empty() || !key.chars().all(is_key_char) {
+                    return Err(invalid(format!("The tag key {:?} is not valid.", key)));
+                }
+
+                if index > 0 {
+                    line.push(';');
+                }
+
+                line.push_str(key);
+
+                if let Some(ref value) = *value {
+                    if value.contains('\0') {
+                        return Err(invalid(format!("The value of the tag {} contains a NUL character.", key)));
+                    }
+
+                    line.push('=');
+                    line.push_str(&tag::escape(value));
+                }
+            }
+
+            line.push(' ');
+        }
+
+        if let Some(ref source) = self.source {
+            if source.is_empty() || source.contains(|c| c == ' ' || is_forbidden(c)) {
+                return Err(invalid(format!("The source {:?} is not valid.", source)));
+            }
+
+            line.push(':');
+            line.push_str(source);
+            line.push(' ');
+        }
+
+        let numeric = self.command.len() == 3 && self.command.chars().all(|c| c.is_ascii_digit());
+
+        if !numeric && (self.command.is_empty() || !self.command.chars().all(|c| c.is_ascii_alphabetic())) {
+            return Err(invalid(format!("The command {:?} is not valid.", self.command)));
+        }
+
+        line.push_str(&self.command);
+
+        if let Some((last, middle)) = self.params.split_last() {
+            for &(ref param, _) in middle {
+                check_middle(param)?;
+
+                line.push(' ');
+                line.push_str(param);
+            }
+
+            let (ref last, only_middle) = *last;
+
+            if only_middle {
+                check_middle(last)?;
+            } else if last.contains(is_forbidden) {
+                return Err(invalid(format!("The parameter {:?} is not valid.", last)));
+            }
+
+            line.push(' ');
+
+            if last.is_empty() || last.starts_with(':') || last.contains(' ') {
+                line.push(':');
+            }
+
+            line.push_str(last);
+        }
+
+        Message::try_from(line)
+    }
+}
+
+// Checks a parameter that has to be sent in the middle form.
+fn check_middle(param: &str) -> Result<()> {
+    if param.is_empty() || param.starts_with(':') || param.contains(|c| c == ' ' || is_forbidden(c)) {
+        Err(invalid(format!("The parameter {:?} is not valid.", param)))
+    } else {
+        Ok(())
+    }
+}
+
+// Characters that would end the line or be rejected by the server.
+fn is_forbidden(c: char) -> bool {
+    c == '\r' || c == '\n' || c == '\0'
+}
+
+// Characters allowed in a tag key, including the client-only `+` prefix and a vendor prefix.
+fn is_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '/' || c == '.' || c == '+'
+}
+
+fn invalid(message: String) -> ::irc::error::Error {
+    ErrorKind::InvalidMessage(message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(builder: MessageBuilder) -> String {
+        builder.build().unwrap().raw_message().to_owned()
+    }
+
+    #[test]
+    fn builds_messages() {
+        assert_eq!(line(MessageBuilder::new("PRIVMSG").param("#rust").param("Hello")), "PRIVMSG #rust Hello");
+        assert_eq!(
+            line(MessageBuilder::new("PRIVMSG").param("#rust").param("Hello, world!")),
+            "PRIVMSG #rust :Hello, world!"
+        );
+        assert_eq!(line(MessageBuilder::new("QUIT").param("")), "QUIT :");
+        assert_eq!(line(MessageBuilder::new("TOPIC").param("#rust").param(":)")), "TOPIC #rust ::)");
+        assert_eq!(line(MessageBuilder::new("001").source("irc.test").middle("nick")), ":irc.test 001 nick");
+        assert_eq!(line(MessageBuilder::new("PART").middle("#rust").param_opt(None)), "PART #rust");
+    }
+
+    #[test]
+    fn escapes_tag_values() {
+        let message = MessageBuilder::new("TAGMSG")
+            .tag("+draft/reply", Some("a b;c\\d"))
+            .tag("+typing", None)
+            .middle("#rust")
+            .build()
+            .unwrap();
+
+        assert_eq!(message.raw_message(), "@+draft/reply=a\\sb\\:c\\\\d;+typing TAGMSG #rust");
+    }
+
+    #[test]
+    fn rejects_line_breaks() {
+        assert!(MessageBuilder::new("PRIVMSG").param("#rust").param("a\r\nQUIT").build().is_err());
+        assert!(MessageBuilder::new("PRIVMSG").param("#rust").param("a\0").build().is_err());
+        assert!(MessageBuilder::new("PRIVMSG").middle("#rust\n").param("a").build().is_err());
+        assert!(MessageBuilder::new("PRIVMSG").source("a\rb").param("a").build().is_err());
+        assert!(MessageBuilder::new("PRIVMSG").tag("a", Some("\0")).build().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_middle_parameters() {
+        assert!(MessageBuilder::new("JOIN").param("").param("key").build().is_err());
+        assert!(MessageBuilder::new("JOIN").param("#a b").param("key").build().is_err());
+        assert!(MessageBuilder::new("JOIN").param(":#a").param("key").build().is_err());
+        assert!(MessageBuilder::new("NICK").middle("a b").build().is_err());
+        assert!(MessageBuilder::new("NICK").middle("").build().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_commands_sources_and_tags() {
+        assert!(MessageBuilder::new("").build().is_err());
+        assert!(MessageBuilder::new("PRIV MSG").build().is_err());
+        assert!(MessageBuilder::new("12").build().is_err());
+        assert!(MessageBuilder::new("PING").source("").param("a").build().is_err());
+        assert!(MessageBuilder::new("PING").source("a b").param("a").build().is_err());
+        assert!(MessageBuilder::new("PING").tag("", None).param("a").build().is_err());
+        assert!(MessageBuilder::new("PING").tag("a=b", None).param("a").build().is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! The capabilities module contains the `Capabilities` type which keeps track
+//! of the IRCv3 capabilities advertised by the server and enabled for a
+//! connection.
+
+use std::collections::{HashMap, HashSet};
+
+use irc::command::Cap;
+use irc::error::Result;
+use irc::message::Message;
+
+// The longest list of capabilities sent in a single `CAP REQ` line. This leaves
+// plenty of room for the command itself within the 512 byte message limit.
+const MAX_REQUEST_LENGTH: usize = 400;
+
+/// Tracks the capabilities the client wants, the capabilities the server
+/// advertises and the capabilities that have been acknowledged by the server.
+///
+/// `IrcTransport` feeds every `CAP` message it receives into its
+/// `Capabilities`, which takes care of requesting wanted capabilities when
+/// they are listed or advertised with `CAP NEW`, and of removing capabilities
+/// the server withdraws with `CAP DEL`.
+#[derive(Clone, Debug, Default)]
+pub struct Capabilities {
+    wanted: Vec<String>,
+    available: HashMap<String, Option<String>>,
+    enabled: HashMap<String, Option<String>>,
+    requested: HashSet<String>,
+    listed: bool,
+}
+
+impl Capabilities {
+    /// Create a new `Capabilities` that will request each of the `wanted` capabilities.
+    pub fn new(wanted: &[String]) -> Capabilities {
+        Capabilities {
+            wanted: wanted.to_vec(),
+            ..Capabilities::default()
+        }
+    }
+
+    /// Returns true if the server has acknowledged the capability.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.enabled.contains_key(name)
+    }
+
+    /// Returns true if the server has advertised the capability.
+    pub fn is_available(&self, name: &str) -> bool {
+        self.available.contains_key(name)
+    }
+
+    /// Retrieves the value the server advertised for a capability, such as the
+    /// mechanism list of `sasl=PLAIN,EXTERNAL`.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.available
+            .get(name)
+            .and_then(|value| value.as_ref().map(|value| value.as_str()))
+    }
+
+    /// Get the names of the enabled capabilities.
+    pub fn enabled(&self) -> Vec<&str> {
+        self.enabled.keys().map(|name| name.as_str()).collect()
+    }
+
+    /// Returns true once the server has finished listing its capabilities and
+    /// has replied to every outstanding request.
+    pub fn is_settled(&self) -> bool {
+        self.listed && self.requested.is_empty()
+    }
+
+    /// Adds a capability to the wanted set. The capability is requested
+    /// immediately if the server has already advertised it.
+    pub fn want(&mut self, name: &str) -> Result<Vec<Message>> {
+        if !self.wanted.iter().any(|wanted| wanted == name) {
+            self.wanted.push(name.to_owned());
+        }
+
+        if self.listed {
+            self.request(&[name])
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    /// Updates the tracked capabilities with a `CAP` message received from the
+    /// server and returns any messages that should be sent in response.
+    pub fn handle(&mut self, cap: &Cap) -> Result<Vec<Message>> {
+        let &Cap(_, subcommand, more, capabilities) = cap;
+        let capabilities = capabilities.split_whitespace().map(split_capability);
+
+        match subcommand {
+            "LS" => {
+                self.available.extend(capabilities.map(|(name, value)| {
+                    (name.to_owned(), value.map(|value| value.to_owned()))
+                }));
+
+                if more {
+                    return Ok(Vec::new());
+                }
+
+                self.listed = true;
+
+                let wanted = self.wanted.clone();
+                let names: Vec<&str> = wanted.iter().map(|name| name.as_str()).collect();
+
+                self.request(&names)
+            }
+            "NEW" => {
+                let mut names = Vec::new();
+
+                for (name, value) in capabilities {
+                    self.available
+                        .insert(name.to_owned(), value.map(|value| value.to_owned()));
+                    names.push(name);
+                }
+
+                self.request(&names)
+            }
+            "DEL" => {
+                for (name, _) in capabilities {
+                    self.available.remove(name);
+                    self.enabled.remove(name);
+                }
+
+                Ok(Vec::new())
+            }
+            "ACK" => {
+                for (name, _) in capabilities {
+                    if let Some(name) = name.strip_prefix('-') {
+                        self.enabled.remove(name);
+                        self.requested.remove(name);
+                    } else {
+                        let value = self.available.get(name).cloned().unwrap_or(None);
+                        self.enabled.insert(name.to_owned(), value);
+                        self.requested.remove(name);
+                    }
+                }
+
+                Ok(Vec::new())
+            }
+            "NAK" => {
+                for (name, _) in capabilities {
+                    self.requested.remove(name.trim_start_matches('-'));
+                }
+
+                Ok(Vec::new())
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    // Builds `CAP REQ` messages for the given capabilities that are wanted,
+    // available and not already enabled or requested.
+    fn request(&mut self, names: &[&str]) -> Result<Vec<Message>> {
+        let mut messages = Vec::new();
+        let mut line = String::new();
+
+        for &name in names {
+            let wanted = self.wanted.iter().any(|wanted| wanted == name);
+
+            if !wanted || !self.available.contains_key(name) || self.enabled.contains_key(name)
+                || self.requested.contains(name)
+            {
+                continue;
+            }
+
+            if !line.is_empty() && line.len() + name.len() + 1 > MAX_REQUEST_LENGTH {
+                messages.push(Message::cap("REQ", Some(&line))?);
+                line.clear();
+            }
+
+            if !line.is_empty() {
+                line.push(' ');
+            }
+
+            line.push_str(name);
+            self.requested.insert(name.to_owned());
+        }
+
+        if !line.is_empty() {
+            messages.push(Message::cap("REQ", Some(&line))?);
+        }
+
+        Ok(messages)
+    }
+}
+
+// Splits a capability token such as `sasl=PLAIN,EXTERNAL` into its name and value.
+fn split_capability(token: &str) -> (&str, Option<&str>) {
+    match token.find('=') {
+        Some(index) => (&token[..index], Some(&token[index + 1..])),
+        None => (token, None),
+    }
+}
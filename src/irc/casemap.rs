@@ -0,0 +1,191 @@
+//! The casemap module contains the case folding rules used by IRC servers to compare
+//! nicknames and channel names, along with the `Nick` and `Channel` types which compare
+//! according to those rules and can be used as `HashMap` keys.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use irc::isupport::CaseMapping;
+
+/// Folds `value` to lower case according to `casemapping`, so that two names are equal
+/// when their folded forms are.
+///
+/// Case mappings this library doesn't know about are treated as `rfc1459`, which is the
+/// default when the server doesn't advertise one.
+///
+/// `rfc7613` is approximated by mapping fullwidth characters to their ASCII forms and
+/// lower casing every character. The PRECIS profiles it is based on aren't implemented,
+/// so names that the server considers the same may still compare as different, for
+/// example when they only differ in how accented characters are composed.
+pub fn casefold(casemapping: &CaseMapping, value: &str) -> String {
+    match *casemapping {
+        CaseMapping::Ascii => value.to_ascii_lowercase(),
+        CaseMapping::StrictRfc1459 => value.chars().map(fold_strict_rfc1459).collect(),
+        CaseMapping::Rfc1459 | CaseMapping::Other(_) => value.chars().map(fold_rfc1459).collect(),
+        CaseMapping::Rfc7613 => fold_rfc7613(value),
+    }
+}
+
+/// Returns true if `a` and `b` name the same nickname or channel according to `casemapping`.
+pub fn eq(casemapping: &CaseMapping, a: &str, b: &str) -> bool {
+    casefold(casemapping, a) == casefold(casemapping, b)
+}
+
+fn fold_strict_rfc1459(c: char) -> char {
+    match c {
+        '[' => '{',
+        ']' => '}',
+        '\\' => '|',
+        c => c.to_ascii_lowercase(),
+    }
+}
+
+fn fold_rfc1459(c: char) -> char {
+    match c {
+        '~' => '^',
+        c => fold_strict_rfc1459(c),
+    }
+}
+
+// Maps fullwidth characters to their ASCII forms and lower cases the result.
+fn fold_rfc7613(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            // Fullwidth forms of the ASCII characters are mapped to their ASCII equivalents.
+            '\u{FF01}'..='\u{FF5E}' => ::std::char::from_u32(c as u32 - 0xFF01 + 0x21).unwrap_or(c),
+            '\u{3000}' => ' ',
+            c => c,
+        })
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+macro_rules! casemapped {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Clone, Debug)]
+        pub struct $name {
+            name: String,
+            folded: String,
+        }
+
+        impl $name {
+            /// Create a new name which compares according to `casemapping`.
+            pub fn new(name: &str, casemapping: &CaseMapping) -> $name {
+                $name {
+                    name: name.to_owned(),
+                    folded: casefold(casemapping, name),
+                }
+            }
+
+            /// Get the name as it was given.
+            pub fn as_str(&self) -> &str {
+                &self.name
+            }
+
+            /// Get the case folded form of the name, which is used for comparisons.
+            pub fn folded(&self) -> &str {
+                &self.folded
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &$name) -> bool {
+                self.folded == other.folded
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &$name) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &$name) -> Ordering {
+                self.folded.cmp(&other.folded)
+            }
+        }
+
+        impl Hash for $name {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.folded.hash(state);
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(&self.name)
+            }
+        }
+    };
+}
+
+casemapped! {
+    /// A nickname which compares equal to any other nickname the server considers the same,
+    /// such as `Foo[]` and `foo{}` under `rfc1459`. The name is kept as it was given for display.
+    Nick
+}
+
+casemapped! {
+    /// A channel name which compares equal to any other channel name the server considers the
+    /// same, such as `#Rust` and `#rust`. The name is kept as it was given for display.
+    Channel
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn folds_ascii() {
+        assert_eq!(casefold(&CaseMapping::Ascii, "Nick[]\\~"), "nick[]\\~");
+        assert!(!eq(&CaseMapping::Ascii, "a[", "A{"));
+    }
+
+    #[test]
+    fn folds_rfc1459() {
+        assert_eq!(casefold(&CaseMapping::Rfc1459, "Nick[]\\~"), "nick{}|^");
+        assert_eq!(casefold(&CaseMapping::Rfc1459, "nick{}|^"), "nick{}|^");
+        assert!(eq(&CaseMapping::Rfc1459, "Foo[]", "foo{}"));
+        assert!(eq(&CaseMapping::Other("unknown".to_owned()), "a~", "A^"));
+    }
+
+    #[test]
+    fn folds_strict_rfc1459() {
+        assert_eq!(casefold(&CaseMapping::StrictRfc1459, "Nick[]\\~"), "nick{}|~");
+        assert!(!eq(&CaseMapping::StrictRfc1459, "a~", "a^"));
+    }
+
+    #[test]
+    fn folds_rfc7613() {
+        assert_eq!(casefold(&CaseMapping::Rfc7613, "ÉCOLE"), "école");
+        assert_eq!(casefold(&CaseMapping::Rfc7613, "\u{FF2E}\u{FF49}\u{FF43}\u{FF4B}"), "nick");
+        assert!(eq(&CaseMapping::Rfc7613, "ΣΑΣ", "σασ"));
+        assert!(!eq(&CaseMapping::Rfc7613, "a[", "a{"));
+    }
+
+    #[test]
+    fn compares_names_by_their_folded_form() {
+        let casemapping = CaseMapping::Rfc1459;
+        let nick = Nick::new("Foo[]", &casemapping);
+
+        assert_eq!(nick, Nick::new("foo{}", &casemapping));
+        assert_ne!(nick, Nick::new("foo", &casemapping));
+        assert_eq!(nick.as_str(), "Foo[]");
+        assert_eq!(nick.folded(), "foo{}");
+        assert_eq!(nick.to_string(), "Foo[]");
+
+        let channels: HashSet<Channel> = vec![Channel::new("#Rust", &casemapping), Channel::new("#rust", &casemapping)]
+            .into_iter()
+            .collect();
+
+        assert_eq!(channels.len(), 1);
+    }
+}
@@ -7,70 +7,176 @@ extern crate tokio_core;
 
 
 
-use futures::{Future, Async, Poll};
+use futures::{future, Future, Poll};
 
 use tokio_core::reactor::Handle;
-use tokio_core::net::{TcpStream, TcpStreamNew};
-use self::tokio_io::{AsyncRead};
+use tokio_core::net::TcpStream;
+use self::tokio_io::{AsyncRead, AsyncWrite};
 
-use std::net::SocketAddr;
-//use std::time;
 
 use irc::transport::{IrcTransport};
 use irc::codec;
+use irc::config::Config;
+use irc::encoding::Encodings;
 use irc::error::{Error};
+use irc::net;
+use irc::reconnect::Reconnecting;
+use irc::registration::Registration;
+use irc::tls::{self, TlsConfig, TlsStream};
 
 /// A light-weight client type for establishing connections to remote servers.
-/// This type consumes a given `SocketAddr` and provides several methods for
-/// establishing connections to a remote server.  
-/// Currently these methods allow for the connection to a server with unencrypted data.
+/// This type consumes a given host and a registration `Config` and
+/// provides several methods for establishing connections to a remote server.  
+/// These methods allow for the connection to a server with either unencrypted
+/// data or data encrypted with TLS.
 /// Each of the connection methods will return a future, that when successfully
 /// resolved, will provide a `Stream` that allows for communication with the
 /// remote server.
+#[derive(Clone)]
 pub struct Client {
-    host: SocketAddr,
+    host: String,
+    config: Config,
 }
 
 
 impl Client {
-    /// Create a new instance of `Client` that will connect to host
-    pub fn new<H: Into<SocketAddr>>(host: H) -> Client {
-        Client { host: host.into() }
+    /// Create a new instance of `Client` that will connect to host and
+    /// register using the given `config`.
+    ///
+    /// The host is either a `SocketAddr` or a `host:port` string such as
+    /// `"irc.libera.chat:6697"`. Host names are resolved when connecting and
+    /// every address is tried until one accepts the connection; when none do,
+    /// the `ConnectFailed` error lists each address and why it failed.
+    pub fn new<H: ToString>(host: H, config: Config) -> Client {
+        Client {
+            host: host.to_string(),
+            config: config,
+        }
     }
     
     /// Returns a future, that when resolved provides an unecrypted `Stream`
     /// that can be used to receive `Message` from the server and send `Message`
     /// to the server.
     ///
+    /// The future only resolves once the server has accepted the registration
+    /// by sending the WELCOME (001) numeric. It fails with `NicknameInUse` or
+    /// `ErroneousNickname` when every nickname has been rejected, and with
+    /// `PasswordMismatch` or `Banned` when the server refuses the connection.
+    ///
     /// The resulting `Stream` can be `split` into a separate `Stream` for
     /// receiving `Message` from the server and a `Sink` for sending `Message`
     /// to the server.
-    pub fn connect(&self, handle: &Handle) -> ClientConnectFuture {
-        let tcp_stream = TcpStream::connect(&self.host, handle);
+    pub fn connect(&self, handle: &Handle) -> ClientConnectFuture<TcpStream> {
+        let tcp_stream = net::connect(&self.host, handle, self.config.connect_timeout);
 
-        ClientConnectFuture { inner: tcp_stream }
+        ClientConnectFuture::new(tcp_stream, self.config.clone(), handle)
     }
-}
 
-/// Represents a future, that when resolved provides an unecrypted `Stream`
-/// that can be used to receive `Message` from the server and send `Message`
-/// to the server.
-pub struct ClientConnectFuture {
-    inner: TcpStreamNew,
-}
+    /// Returns a future, that when resolved provides a `Stream` encrypted with
+    /// TLS that can be used to receive `Message` from the server and send
+    /// `Message` to the server.
+    ///
+    /// The server certificate is verified as described by `tls`. Apart from the
+    /// TLS handshake, the future behaves just like the one returned by `connect`.
+    pub fn connect_tls(&self, handle: &Handle, tls: &TlsConfig) -> ClientConnectFuture<TlsStream<TcpStream>> {
+        let settings = tls.client_config()
+            .and_then(|client_config| Ok((client_config, tls.server_name(net::host_name(&self.host))?)));
+
+        let tls_stream: Box<dyn Future<Item = TlsStream<TcpStream>, Error = Error>> = match settings {
+            Ok((client_config, server_name)) => Box::new(
+                net::connect(&self.host, handle, self.config.connect_timeout)
+                    .and_then(move |tcp_stream| tls::connect(client_config, server_name, tcp_stream))
+                    .flatten(),
+            ),
+            Err(error) => Box::new(future::err(error)),
+        };
+
+        ClientConnectFuture::new(tls_stream, self.config.clone(), handle)
+    }
 
-impl Future for ClientConnectFuture {
-    type Item = IrcTransport<TcpStream>;
-    type Error = Error;
+    /// Returns an unencrypted `Stream` and `Sink` that connects to the server
+    /// and reconnects whenever the connection is lost, as described by
+    /// `Reconnecting`.
+    pub fn reconnecting(&self, handle: &Handle) -> Reconnecting<TcpStream> {
+        let client = self.clone();
+        let connect_handle = handle.clone();
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let framed = try_ready!(self.inner.poll()).framed(codec::IrcCodec);
-        let irc_transport = IrcTransport::new(framed);
+        Reconnecting::new(handle, self.config.reconnect_delay, self.config.max_reconnect_delay, move || {
+            client.connect(&connect_handle)
+        })
+    }
 
-        Ok(Async::Ready(irc_transport))
+    /// Returns a `Stream` and `Sink` encrypted with TLS that connects to the
+    /// server and reconnects whenever the connection is lost, as described by
+    /// `Reconnecting`.
+    pub fn reconnecting_tls(&self, handle: &Handle, tls: &TlsConfig) -> Reconnecting<TlsStream<TcpStream>> {
+        let client = self.clone();
+        let connect_handle = handle.clone();
+        let tls = tls.clone();
+
+        Reconnecting::new(handle, self.config.reconnect_delay, self.config.max_reconnect_delay, move || {
+            client.connect_tls(&connect_handle, &tls)
+        })
     }
 }
 
+enum ConnectState<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    Connecting(Box<dyn Future<Item = T, Error = Error>>),
+    Registering(Box<Registration<T>>),
+}
 
+/// Represents a future, that when resolved provides a `Stream` that can be
+/// used to receive `Message` from the server and send `Message` to the server.
+pub struct ClientConnectFuture<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    state: ConnectState<T>,
+    config: Config,
+    handle: Handle,
+}
 
+impl<T> ClientConnectFuture<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    fn new(stream: Box<dyn Future<Item = T, Error = Error>>, config: Config, handle: &Handle) -> ClientConnectFuture<T> {
+        ClientConnectFuture {
+            state: ConnectState::Connecting(stream),
+            config: config,
+            handle: handle.clone(),
+        }
+    }
+}
 
+impl<T> Future for ClientConnectFuture<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    type Item = IrcTransport<T>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let registration = match self.state {
+                ConnectState::Connecting(ref mut inner) => {
+                    let framed = try_ready!(inner.poll()).framed(codec::IrcCodec::new(
+                        self.config.max_line_length,
+                        Encodings::new(self.config.encoding, &self.config.target_encodings),
+                    ));
+                    let irc_transport = IrcTransport::new(framed, &self.handle, &self.config)?;
+
+                    Box::new(Registration::new(irc_transport, &self.config)?)
+                }
+                ConnectState::Registering(ref mut registration) => {
+                    return registration.poll();
+                }
+            };
+
+            self.state = ConnectState::Registering(registration);
+        }
+    }
+}
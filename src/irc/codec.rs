@@ -1,42 +1,210 @@
-extern crate bytes;
-extern crate tokio_io;
-extern crate tokio_core;
-
-use self::bytes::BytesMut;
-use self::tokio_io::codec::{Encoder, Decoder};
-
-use irc::message::{Message};
-
-use irc::error::{Error, Result};
-
-const DELIMETER_LENGTH: usize = 2;
-
-pub struct IrcCodec;
-
-impl Decoder for IrcCodec {
-    type Item = Message;
-    type Error = Error;
-
-    fn decode(&mut self, buffer: &mut BytesMut) -> Result<Option<Self::Item>> {
-        if let Some(index) = buffer.iter().position(|&b| b == b'\n') {
-            let command = buffer.split_to(index - 1);
-            buffer.split_to(DELIMETER_LENGTH);
-
-            Ok(Some(Message::try_from(String::from_utf8(command.to_vec())?)?))
-        } else {
-            Ok(None)
-        }
-    }
-}
-
-impl Encoder for IrcCodec {
-    type Item = Message;
-    type Error = Error;
-
-    fn encode(&mut self, message: Self::Item, buffer: &mut BytesMut) -> Result<()> {
-        buffer.extend(message.raw_message().as_bytes());
-        buffer.extend(b"\r\n");
-
-        Ok(())
-    }
-}
+extern crate bytes;
+extern crate tokio_io;
+extern crate tokio_core;
+
+use self::bytes::BytesMut;
+use self::tokio_io::codec::{Encoder, Decoder};
+
+use irc::encoding::Encodings;
+use irc::message::{Message};
+
+use irc::error::{Error, ErrorKind, Result};
+
+/// The default maximum line length, which allows for the largest tag section of
+/// 8191 bytes along with a 512 byte message.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 8191 + 512;
+
+/// Splits the incoming data into lines and parses each into a `Message`.
+///
+/// Lines may be terminated by CR-LF, a bare LF or a stray CR, and empty lines
+/// are skipped. A line longer than the maximum length is discarded and the
+/// decoder returns a `LineTooLong` error, after which it can be used again to
+/// decode the following lines.
+///
+/// Lines are converted to and from text using the given `Encodings`.
+pub struct IrcCodec {
+    max_line_length: usize,
+    encodings: Encodings,
+    // The number of bytes already searched for a line ending.
+    searched: usize,
+    // True while the remainder of an oversized line is being thrown away.
+    discarding: bool,
+}
+
+impl IrcCodec {
+    /// Create a new `IrcCodec` that rejects lines longer than `max_line_length` bytes.
+    pub fn new(max_line_length: usize, encodings: Encodings) -> IrcCodec {
+        IrcCodec {
+            max_line_length: max_line_length,
+            encodings: encodings,
+            searched: 0,
+            discarding: false,
+        }
+    }
+}
+
+impl Default for IrcCodec {
+    fn default() -> IrcCodec {
+        IrcCodec::new(DEFAULT_MAX_LINE_LENGTH, Encodings::default())
+    }
+}
+
+impl Decoder for IrcCodec {
+    type Item = Message;
+    type Error = Error;
+
+    fn decode(&mut self, buffer: &mut BytesMut) -> Result<Option<Self::Item>> {
+        loop {
+            let end = buffer[self.searched..]
+                .iter()
+                .position(|&b| b == b'\r' || b == b'\n')
+                .map(|index| index + self.searched);
+
+            let index = match end {
+                Some(index) => index,
+                None if buffer.len() > self.max_line_length => {
+                    buffer.clear();
+                    self.searched = 0;
+
+                    if self.discarding {
+                        return Ok(None);
+                    }
+
+                    self.discarding = true;
+                    return Err(ErrorKind::LineTooLong(self.max_line_length).into());
+                }
+                None => {
+                    self.searched = buffer.len();
+                    return Ok(None);
+                }
+            };
+
+            let line = buffer.split_to(index);
+            buffer.split_to(1);
+            self.searched = 0;
+
+            // NOTE: The CR of a CR-LF ending leaves an empty line behind, which is skipped here.
+            if self.discarding {
+                self.discarding = false;
+                continue;
+            }
+
+            if line.len() > self.max_line_length {
+                return Err(ErrorKind::LineTooLong(self.max_line_length).into());
+            }
+
+            if line.is_empty() {
+                continue;
+            }
+
+            // NOTE: The line has already been taken off the buffer, so a line that can't be
+            // parsed is reported as such, and decoding carries on with the next one.
+            return Message::from_received(self.encodings.decode(&line)?)
+                .map(Some)
+                .map_err(|error| ErrorKind::MalformedLine(error.to_string()).into());
+        }
+    }
+}
+
+impl Encoder for IrcCodec {
+    type Item = Message;
+    type Error = Error;
+
+    fn encode(&mut self, message: Self::Item, buffer: &mut BytesMut) -> Result<()> {
+        buffer.extend(self.encodings.encode(&message));
+        buffer.extend(b"\r\n");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(codec: &mut IrcCodec, buffer: &mut BytesMut) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        while let Some(message) = codec.decode(buffer).unwrap() {
+            lines.push(message.raw_message().to_owned());
+        }
+
+        lines
+    }
+
+    #[test]
+    fn splits_lines_on_any_ending() {
+        let mut codec = IrcCodec::default();
+        let mut buffer = BytesMut::from(&b"PING a\r\nPING b\nPING c\rPING d\r\n\r\n\nPING e"[..]);
+
+        assert_eq!(decode_all(&mut codec, &mut buffer), vec!["PING a", "PING b", "PING c", "PING d"]);
+
+        buffer.extend(b"\r\n");
+        assert_eq!(decode_all(&mut codec, &mut buffer), vec!["PING e"]);
+    }
+
+    #[test]
+    fn waits_for_partial_lines() {
+        let mut codec = IrcCodec::default();
+        let mut buffer = BytesMut::from(&b"PING "[..]);
+
+        assert!(decode_all(&mut codec, &mut buffer).is_empty());
+
+        buffer.extend(b"token\r");
+        assert_eq!(decode_all(&mut codec, &mut buffer), vec!["PING token"]);
+
+        buffer.extend(b"\nPING next\r\n");
+        assert_eq!(decode_all(&mut codec, &mut buffer), vec!["PING next"]);
+    }
+
+    #[test]
+    fn discards_oversized_lines() {
+        let mut codec = IrcCodec::new(16, Encodings::default());
+        let mut buffer = BytesMut::from(&b"PING 0123456789abcdef"[..]);
+
+        match codec.decode(&mut buffer) {
+            Err(ref error) if error.is_recoverable() => {}
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+
+        // The rest of the oversized line is thrown away without another error.
+        buffer.extend(b"0123456789abcdef");
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+
+        buffer.extend(b"tail\r\nPING ok\r\n");
+        assert_eq!(decode_all(&mut codec, &mut buffer), vec!["PING ok"]);
+    }
+
+    #[test]
+    fn rejects_oversized_complete_lines() {
+        let mut codec = IrcCodec::new(16, Encodings::default());
+        let mut buffer = BytesMut::from(&b"PING 0123456789abcdef\r\nPING ok\r\n"[..]);
+
+        assert!(codec.decode(&mut buffer).is_err());
+        assert_eq!(decode_all(&mut codec, &mut buffer), vec!["PING ok"]);
+    }
+
+    #[test]
+    fn skips_malformed_lines() {
+        let mut codec = IrcCodec::default();
+        let mut buffer = BytesMut::from(&b"@tags\r\n:server \r\nPING ok\r\n"[..]);
+
+        for _ in 0..2 {
+            match codec.decode(&mut buffer) {
+                Err(Error(ErrorKind::MalformedLine(_), _)) => {}
+                result => panic!("unexpected result: {:?}", result.map(|_| ())),
+            }
+        }
+
+        assert_eq!(decode_all(&mut codec, &mut buffer), vec!["PING ok"]);
+    }
+
+    #[test]
+    fn encodes_lines() {
+        let mut codec = IrcCodec::default();
+        let mut buffer = BytesMut::new();
+
+        codec.encode(Message::ping("token").unwrap(), &mut buffer).unwrap();
+        assert_eq!(&buffer[..], &b"PING token\r\n"[..]);
+    }
+}
@@ -5,6 +5,9 @@ use std::ops::Range;
 use std::slice::Iter;
 
 use irc::command;
+use irc::ctcp::Ctcp;
+use irc::isupport::ISupport;
+use irc::mode::{self, ModeChange};
 
 /// An implementation of Iterator that iterates over the arguments of a `Message`.
 #[derive(Clone)]
@@ -88,12 +91,37 @@ macro_rules! command_match {
     }};
 }
 
-/// A macro for creating implementations of basic commands with up to four
-/// &str arguments.
+/// A macro for creating implementations of basic commands with &str arguments.
 ///
-
+/// Arguments listed after a `;` are optional and are represented as `Option<&str>`,
+/// as in `("PART" => Part(channels; reason))`.
 #[macro_export]
 macro_rules! command {
+    ($(#[$meta:meta])* ($command:expr => $command_name:ident($($name:ident),*; $($optional:ident),+))) => {
+        $(#[$meta])*
+        pub struct $command_name<'a>($(pub expand_param!($name),)* $(pub expand_optional_param!($optional)),+);
+
+        impl<'a> command::Command<'a> for $command_name<'a> {
+            fn name() -> &'static str {
+                $command
+            }
+
+            fn parse(mut arguments: command::ArgumentIter<'a>) -> Option<$command_name> {
+                $(
+                    let $name = match arguments.next() {
+                        Some(value) => value,
+                        None => return None
+                    };
+                )*
+                $(
+                    let $optional = arguments.next();
+                )+
+
+                Some($command_name($($name,)* $($optional),+))
+            }
+        }
+    };
+
     ($(#[$meta:meta])* ($command:expr => $command_name:ident())) => {
         $(#[$meta])*
         pub struct $command_name;
@@ -139,6 +167,20 @@ macro_rules! expand_param {
     ($i:ident) =>  { &'a str };
 }
 
+#[doc(hidden)]
+#[macro_export]
+macro_rules! expand_optional_param {
+    ($i:ident) =>  { Option<&'a str> };
+}
+
+/// An iterator over the items of a comma separated list, such as the targets of a PRIVMSG.
+pub type List<'a> = ::std::str::Split<'a, char>;
+
+/// Splits a comma separated list into its items.
+pub fn list(value: &str) -> List {
+    value.split(',')
+}
+
 command! { 
     /// Represents a PING command.  The first element is the host.
     ("PING" => Ping(host)) 
@@ -150,17 +192,244 @@ command! {
 }
 
 command! {
-    /// Represents a PRIVMSG command.  The first element is the target of the message and
-    ("PRIVMSG" => PrivMsg(target, message))
+    /// Represents a PRIVMSG command. The first element is the comma separated list of targets
+    /// and the second element is the message.
+    ("PRIVMSG" => PrivMsg(targets, message))
+}
+
+impl<'a> PrivMsg<'a> {
+    /// Get the targets of the message.
+    pub fn targets(&self) -> List<'a> {
+        list(self.0)
+    }
+
+    /// Get the CTCP message contained in the text, if there is one.
+    pub fn ctcp(&self) -> Option<Ctcp<'a>> {
+        Ctcp::parse(self.1)
+    }
+}
+
+command! {
+    /// Represents a NOTICE command. The first element is the comma separated list of targets
+    /// and the second element is the message.
+    ("NOTICE" => Notice(targets, message))
+}
+
+impl<'a> Notice<'a> {
+    /// Get the targets of the message.
+    pub fn targets(&self) -> List<'a> {
+        list(self.0)
+    }
+
+    /// Get the CTCP message contained in the text, if there is one.
+    pub fn ctcp(&self) -> Option<Ctcp<'a>> {
+        Ctcp::parse(self.1)
+    }
+}
+
+command! {
+    /// Represents a JOIN command. The first element is the comma separated list of channels.
+    ///
+    /// When sent by the client, the second element is the comma separated list of keys. When
+    /// received with the `extended-join` capability enabled, the second element is the account
+    /// of the user, or `*` if they are not logged in, and the third element is their real name.
+    ("JOIN" => Join(channels; keys, realname))
+}
+
+impl<'a> Join<'a> {
+    /// Get the channels being joined.
+    pub fn channels(&self) -> List<'a> {
+        list(self.0)
+    }
+
+    /// Get the account of the user that joined, when sent with `extended-join`.
+    pub fn account(&self) -> Option<&'a str> {
+        match (self.1, self.2) {
+            (Some("*"), Some(_)) => None,
+            (Some(account), Some(_)) => Some(account),
+            _ => None,
+        }
+    }
+}
+
+command! {
+    /// Represents a PART command. The first element is the comma separated list of channels that were left
+    /// and the second element is the reason.
+    ("PART" => Part(channels; reason))
+}
+
+impl<'a> Part<'a> {
+    /// Get the channels being left.
+    pub fn channels(&self) -> List<'a> {
+        list(self.0)
+    }
+}
+
+command! {
+    /// Represents a QUIT command. The first element is the reason.
+    ("QUIT" => Quit(; reason))
+}
+
+command! {
+    /// Represents a NICK command. The first element is the new nickname.
+    ("NICK" => Nick(nick))
+}
+
+command! {
+    /// Represents a KICK command. The first element is the comma separated list of channels, the second
+    /// element is the comma separated list of nicknames of the users that were kicked and the third element
+    /// is the reason.
+    ("KICK" => Kick(channels, nicks; reason))
+}
+
+impl<'a> Kick<'a> {
+    /// Get the channel and nickname of every user being kicked. A single channel applies to every
+    /// nickname, otherwise each channel is paired with the nickname in the same position.
+    pub fn kicks(&self) -> Vec<(&'a str, &'a str)> {
+        let channels: Vec<&'a str> = list(self.0).collect();
+
+        list(self.1)
+            .enumerate()
+            .filter_map(|(index, nick)| match channels.len() {
+                1 => Some((channels[0], nick)),
+                _ => channels.get(index).map(|&channel| (channel, nick)),
+            })
+            .collect()
+    }
+}
+
+command! {
+    /// Represents an INVITE command. The first element is the nickname of the user being invited
+    /// and the second element is the channel.
+    ("INVITE" => Invite(nick, channel))
+}
+
+command! {
+    /// Represents a TOPIC command. The first element is the channel and the second element is the
+    /// new topic, which is empty when the topic was cleared. When sent by the client without a topic,
+    /// the current topic is requested.
+    ("TOPIC" => Topic(channel; topic))
+}
+
+/// Represents a MODE command. The first element is the target, the second element is the
+/// mode string, such as `+o-v`, and the third element iterates over the mode arguments.
+/// When sent by the client without a mode string, the current modes are requested.
+pub struct Mode<'a>(pub &'a str, pub Option<&'a str>, pub ArgumentIter<'a>);
+
+impl<'a> Command<'a> for Mode<'a> {
+    fn name() -> &'static str {
+        "MODE"
+    }
+
+    fn parse(mut arguments: ArgumentIter<'a>) -> Option<Mode<'a>> {
+        let target = match arguments.next() {
+            Some(target) => target,
+            None => return None,
+        };
+
+        let modes = arguments.next();
+
+        Some(Mode(target, modes, arguments))
+    }
+}
+
+impl<'a> Mode<'a> {
+    /// Get the mode changes, pairing each mode with its argument according to the server's
+    /// ISUPPORT tokens. See `mode::parse_modes` for details.
+    pub fn changes(&self, isupport: &ISupport) -> Vec<ModeChange> {
+        mode::parse_modes(isupport, self.0, self.1.unwrap_or(""), self.2.clone())
+    }
+}
+
+command! {
+    /// Represents a KILL command. The first element is the nickname of the user being disconnected
+    /// and the second element is the reason.
+    ("KILL" => Kill(nick, reason))
+}
+
+command! {
+    /// Represents an ERROR command. The first element is the message, usually the reason the
+    /// connection is being closed.
+    ("ERROR" => ErrorMessage(message))
+}
+
+command! {
+    /// Represents an AWAY command. The first element is the away message, or `None` when the
+    /// user is no longer away.
+    ("AWAY" => Away(; message))
+}
+
+command! {
+    /// Represents a WALLOPS command. The first element is the message.
+    ("WALLOPS" => Wallops(message))
+}
+
+command! {
+    /// Represents a CHGHOST command. The first element is the new username and the second
+    /// element is the new host.
+    ("CHGHOST" => ChgHost(user, host))
+}
+
+command! {
+    /// Represents an ACCOUNT command. The first element is the account the user logged in to,
+    /// or `*` when they logged out.
+    ("ACCOUNT" => AccountChange(account))
+}
+
+impl<'a> AccountChange<'a> {
+    /// Get the account the user logged in to, or `None` if they logged out.
+    pub fn account(&self) -> Option<&'a str> {
+        match self.0 {
+            "*" => None,
+            account => Some(account),
+        }
+    }
+}
+
+command! {
+    /// Represents a SETNAME command. The first element is the new real name.
+    ("SETNAME" => SetName(realname))
 }
 
-// command! {
-//     ("JOIN" => Join(channel))
-// }
+/// Represents a CAP command. The first element is the target, the second element is the
+/// subcommand (such as `LS`, `ACK` or `NEW`), the third element indicates whether more
+/// lines of the same reply follow and the fourth element is the space separated list of
+/// capabilities.
+pub struct Cap<'a>(pub &'a str, pub &'a str, pub bool, pub &'a str);
+
+impl<'a> Command<'a> for Cap<'a> {
+    fn name() -> &'static str {
+        "CAP"
+    }
+
+    fn parse(mut arguments: ArgumentIter<'a>) -> Option<Cap<'a>> {
+        let target = match arguments.next() {
+            Some(target) => target,
+            None => return None,
+        };
+
+        let subcommand = match arguments.next() {
+            Some(subcommand) => subcommand,
+            None => return None,
+        };
+
+        // NOTE: Multi-line replies mark every line but the last with a `*`
+        // before the capability list.
+        let (more, capabilities) = match (arguments.next(), arguments.next()) {
+            (Some("*"), Some(capabilities)) => (true, capabilities),
+            (Some(capabilities), _) => (false, capabilities),
+            (None, _) => (false, ""),
+        };
 
-// command! {
-//     ("PART" => Part(channel))
-// }
+        Some(Cap(target, subcommand, more, capabilities))
+    }
+}
+
+command! {
+    /// Represents an AUTHENTICATE command. The first element is a chunk of base64 encoded SASL data,
+    /// or `+` when there is no data.
+    ("AUTHENTICATE" => Authenticate(payload))
+}
 
 command! { 
     /// Represents a WELCOME numeric. The first element is the unsername and the second element is the welcome message.
@@ -182,6 +451,442 @@ command!{
     ("004" => ServerInfo(user, message))
 }
 
+command! {
+    /// Represents an ERR_ERRONEUSNICKNAME numeric. The first element is the username, the second element
+    /// is the rejected nickname and the third element is the error message.
+    ("432" => ErroneousNickname(user, nick, message))
+}
+
+command! {
+    /// Represents an ERR_NICKNAMEINUSE numeric. The first element is the username, the second element
+    /// is the rejected nickname and the third element is the error message.
+    ("433" => NicknameInUse(user, nick, message))
+}
+
+command! {
+    /// Represents an ERR_PASSWDMISMATCH numeric. The first element is the username and the second element
+    /// is the error message.
+    ("464" => PasswordMismatch(user, message))
+}
+
+command! {
+    /// Represents an ERR_YOUREBANNEDCREEP numeric. The first element is the username and the second element
+    /// is the reason for the ban.
+    ("465" => Banned(user, message))
+}
+
+command! {
+    /// Represents an RPL_LOGGEDIN numeric. The first element is the username, the second element is the
+    /// full mask of the client, the third element is the account name and the fourth element is the message.
+    ("900" => LoggedIn(user, mask, account, message))
+}
+
+command! {
+    /// Represents an RPL_LOGGEDOUT numeric. The first element is the username, the second element is the
+    /// full mask of the client and the third element is the message.
+    ("901" => LoggedOut(user, mask, message))
+}
+
+command! {
+    /// Represents an ERR_NICKLOCKED numeric. The first element is the username and the second element is
+    /// the error message.
+    ("902" => NickLocked(user, message))
+}
+
+command! {
+    /// Represents an RPL_SASLSUCCESS numeric. The first element is the username and the second element is
+    /// the message.
+    ("903" => SaslSuccess(user, message))
+}
+
+command! {
+    /// Represents an ERR_SASLFAIL numeric. The first element is the username and the second element is
+    /// the error message.
+    ("904" => SaslFail(user, message))
+}
+
+command! {
+    /// Represents an ERR_SASLTOOLONG numeric. The first element is the username and the second element is
+    /// the error message.
+    ("905" => SaslTooLong(user, message))
+}
+
+command! {
+    /// Represents an ERR_SASLABORTED numeric. The first element is the username and the second element is
+    /// the error message.
+    ("906" => SaslAborted(user, message))
+}
+
+command! {
+    /// Represents an ERR_SASLALREADY numeric. The first element is the username and the second element is
+    /// the error message.
+    ("907" => SaslAlready(user, message))
+}
+
+command! {
+    /// Represents an RPL_SASLMECHS numeric. The first element is the username, the second element is the
+    /// comma separated list of mechanisms supported by the server and the third element is the message.
+    ("908" => SaslMechanisms(user, mechanisms, message))
+}
+
+command! {
+    /// Represents an RPL_AWAY numeric. The first element is the username, the second element is the nickname of
+    /// the user who is away and the third element is their away message.
+    ("301" => AwayReply(user, nick, message))
+}
+
+command! {
+    /// Represents an RPL_UNAWAY numeric. The first element is the username and the second element is the message.
+    ("305" => UnAway(user, message))
+}
+
+command! {
+    /// Represents an RPL_NOWAWAY numeric. The first element is the username and the second element is the
+    /// message.
+    ("306" => NowAway(user, message))
+}
+
+command! {
+    /// Represents an RPL_WHOISUSER numeric. The first element is the username, the second element is the
+    /// nickname, the third element is their username, the fourth element is their host, the fifth element is
+    /// always `*` and the sixth element is their real name.
+    ("311" => WhoisUser(user, nick, username, host, star, realname))
+}
+
+command! {
+    /// Represents an RPL_WHOISSERVER numeric. The first element is the username, the second element is the
+    /// nickname, the third element is the server they are connected to and the fourth element is the description
+    /// of that server.
+    ("312" => WhoisServer(user, nick, server, info))
+}
+
+command! {
+    /// Represents an RPL_WHOISOPERATOR numeric. The first element is the username, the second element is the
+    /// nickname and the third element is the message.
+    ("313" => WhoisOperator(user, nick, message))
+}
+
+command! {
+    /// Represents an RPL_ENDOFWHO numeric. The first element is the username, the second element is the mask that
+    /// was queried and the third element is the message.
+    ("315" => EndOfWho(user, mask, message))
+}
+
+/// Represents an RPL_WHOISIDLE numeric. The first element is the username, the second element is the
+/// nickname, the third element is the number of seconds they have been idle, the fourth element is the time
+/// they connected, in seconds since the Unix epoch, and the fifth element is the message.
+pub struct WhoisIdle<'a>(pub &'a str, pub &'a str, pub &'a str, pub Option<&'a str>, pub Option<&'a str>);
+
+impl<'a> Command<'a> for WhoisIdle<'a> {
+    fn name() -> &'static str {
+        "317"
+    }
+
+    fn parse(mut arguments: ArgumentIter<'a>) -> Option<WhoisIdle<'a>> {
+        let user = match arguments.next() {
+            Some(user) => user,
+            None => return None,
+        };
+
+        let nick = match arguments.next() {
+            Some(nick) => nick,
+            None => return None,
+        };
+
+        let idle = match arguments.next() {
+            Some(idle) => idle,
+            None => return None,
+        };
+
+        // NOTE: Some servers leave out the signon time, so the arguments are taken from
+        // the end. The message is always last.
+        let message = arguments.next_back();
+        let signon = arguments.next_back();
+
+        Some(WhoisIdle(user, nick, idle, signon, message))
+    }
+}
+
+command! {
+    /// Represents an RPL_ENDOFWHOIS numeric. The first element is the username, the second element is the
+    /// nickname and the third element is the message.
+    ("318" => EndOfWhois(user, nick, message))
+}
+
+command! {
+    /// Represents an RPL_WHOISCHANNELS numeric. The first element is the username, the second element is the
+    /// nickname and the third element is the space separated list of channels they are in, each with any
+    /// membership prefix.
+    ("319" => WhoisChannels(user, nick, channels))
+}
+
+command! {
+    /// Represents an RPL_LISTSTART numeric. The first element is the username.
+    ("321" => ListStart(user))
+}
+
+command! {
+    /// Represents an RPL_LIST numeric. The first element is the username, the second element is the channel, the
+    /// third element is the number of visible users and the fourth element is the topic.
+    ("322" => ListReply(user, channel, visible, topic))
+}
+
+command! {
+    /// Represents an RPL_LISTEND numeric. The first element is the username and the second element is the
+    /// message.
+    ("323" => ListEnd(user, message))
+}
+
+command! {
+    /// Represents an RPL_CHANNELMODEIS numeric. The first element is the username, the second element is the
+    /// channel and the third element is the mode string. Any mode arguments follow as further arguments.
+    ("324" => ChannelModeIs(user, channel, modes))
+}
+
+command! {
+    /// Represents an RPL_CREATIONTIME numeric. The first element is the username, the second element is the
+    /// channel and the third element is the time it was created, in seconds since the Unix epoch.
+    ("329" => CreationTime(user, channel, time))
+}
+
+command! {
+    /// Represents an RPL_WHOISACCOUNT numeric. The first element is the username, the second element is the
+    /// nickname, the third element is the account they are logged in to and the fourth element is the message.
+    ("330" => WhoisAccount(user, nick, account, message))
+}
+
+command! {
+    /// Represents an RPL_NOTOPIC numeric. The first element is the username, the second element is the channel
+    /// and the third element is the message.
+    ("331" => NoTopic(user, channel, message))
+}
+
+command! {
+    /// Represents an RPL_TOPIC numeric. The first element is the username, the second element is the channel and
+    /// the third element is the topic.
+    ("332" => TopicReply(user, channel, topic))
+}
+
+command! {
+    /// Represents an RPL_TOPICWHOTIME numeric. The first element is the username, the second element is the
+    /// channel, the third element is the nickname or mask of whoever set the topic and the fourth element is the
+    /// time it was set, in seconds since the Unix epoch.
+    ("333" => TopicWhoTime(user, channel, setter, time))
+}
+
+command! {
+    /// Represents an RPL_INVITING numeric. The first element is the username, the second element is the nickname
+    /// of the invited user and the third element is the channel.
+    ("341" => Inviting(user, nick, channel))
+}
+
+command! {
+    /// Represents an RPL_WHOREPLY numeric. The first element is the username, the second element is a channel the
+    /// user is in or `*`, the third element is their username, the fourth element is their host, the fifth
+    /// element is their server, the sixth element is their nickname, the seventh element holds their away status
+    /// and privileges and the eighth element is the hop count followed by their real name.
+    ("352" => WhoReply(user, channel, username, host, server, nick, flags, realname))
+}
+
+command! {
+    /// Represents an RPL_BANLIST numeric. The first element is the username, the second element is the channel,
+    /// the third element is the banned mask, the fourth element is whoever set the ban and the fifth element is
+    /// the time it was set, in seconds since the Unix epoch.
+    ("367" => BanList(user, channel, mask; setter, time))
+}
+
+command! {
+    /// Represents an RPL_ENDOFBANLIST numeric. The first element is the username, the second element is the
+    /// channel and the third element is the message.
+    ("368" => EndOfBanList(user, channel, message))
+}
+
+command! {
+    /// Represents an RPL_MOTD numeric. The first element is the username and the second element is a line of the
+    /// message of the day.
+    ("372" => Motd(user, message))
+}
+
+command! {
+    /// Represents an RPL_MOTDSTART numeric. The first element is the username and the second element is the
+    /// message.
+    ("375" => MotdStart(user, message))
+}
+
+command! {
+    /// Represents an RPL_ENDOFMOTD numeric. The first element is the username and the second element is the
+    /// message.
+    ("376" => EndOfMotd(user, message))
+}
+
+command! {
+    /// Represents an RPL_YOUREOPER numeric. The first element is the username and the second element is the
+    /// message.
+    ("381" => YoureOper(user, message))
+}
+
+command! {
+    /// Represents an RPL_HOSTHIDDEN numeric. The first element is the username, the second element is the host
+    /// now shown to other users and the third element is the message.
+    ("396" => HostHidden(user, host, message))
+}
+
+command! {
+    /// Represents an ERR_NOSUCHNICK numeric. The first element is the username, the second element is the
+    /// nickname and the third element is the error message.
+    ("401" => NoSuchNick(user, nick, message))
+}
+
+command! {
+    /// Represents an ERR_NOSUCHSERVER numeric. The first element is the username, the second element is the
+    /// server name and the third element is the error message.
+    ("402" => NoSuchServer(user, server, message))
+}
+
+command! {
+    /// Represents an ERR_NOSUCHCHANNEL numeric. The first element is the username, the second element is the
+    /// channel and the third element is the error message.
+    ("403" => NoSuchChannel(user, channel, message))
+}
+
+command! {
+    /// Represents an ERR_CANNOTSENDTOCHAN numeric. The first element is the username, the second element is the
+    /// channel and the third element is the error message.
+    ("404" => CannotSendToChannel(user, channel, message))
+}
+
+command! {
+    /// Represents an ERR_TOOMANYCHANNELS numeric. The first element is the username, the second element is the
+    /// channel and the third element is the error message.
+    ("405" => TooManyChannels(user, channel, message))
+}
+
+command! {
+    /// Represents an ERR_NOTEXTTOSEND numeric. The first element is the username and the second element is the
+    /// error message.
+    ("412" => NoTextToSend(user, message))
+}
+
+command! {
+    /// Represents an ERR_UNKNOWNCOMMAND numeric. The first element is the username, the second element is the
+    /// command and the third element is the error message.
+    ("421" => UnknownCommand(user, command, message))
+}
+
+command! {
+    /// Represents an ERR_NOMOTD numeric. The first element is the username and the second element is the error
+    /// message.
+    ("422" => NoMotd(user, message))
+}
+
+command! {
+    /// Represents an ERR_NONICKNAMEGIVEN numeric. The first element is the username and the second element is the
+    /// error message.
+    ("431" => NoNicknameGiven(user, message))
+}
+
+command! {
+    /// Represents an ERR_NICKCOLLISION numeric. The first element is the username, the second element is the
+    /// nickname and the third element is the error message.
+    ("436" => NickCollision(user, nick, message))
+}
+
+command! {
+    /// Represents an ERR_USERNOTINCHANNEL numeric. The first element is the username, the second element is the
+    /// nickname, the third element is the channel and the fourth element is the error message.
+    ("441" => UserNotInChannel(user, nick, channel, message))
+}
+
+command! {
+    /// Represents an ERR_NOTONCHANNEL numeric. The first element is the username, the second element is the
+    /// channel and the third element is the error message.
+    ("442" => NotOnChannel(user, channel, message))
+}
+
+command! {
+    /// Represents an ERR_USERONCHANNEL numeric. The first element is the username, the second element is the
+    /// nickname, the third element is the channel and the fourth element is the error message.
+    ("443" => UserOnChannel(user, nick, channel, message))
+}
+
+command! {
+    /// Represents an ERR_NOTREGISTERED numeric. The first element is the username and the second element is the
+    /// error message.
+    ("451" => NotRegistered(user, message))
+}
+
+command! {
+    /// Represents an ERR_NEEDMOREPARAMS numeric. The first element is the username, the second element is the
+    /// command and the third element is the error message.
+    ("461" => NeedMoreParams(user, command, message))
+}
+
+command! {
+    /// Represents an ERR_ALREADYREGISTERED numeric. The first element is the username and the second element is
+    /// the error message.
+    ("462" => AlreadyRegistered(user, message))
+}
+
+command! {
+    /// Represents an ERR_CHANNELISFULL numeric. The first element is the username, the second element is the
+    /// channel and the third element is the error message.
+    ("471" => ChannelIsFull(user, channel, message))
+}
+
+command! {
+    /// Represents an ERR_UNKNOWNMODE numeric. The first element is the username, the second element is the mode
+    /// character and the third element is the error message.
+    ("472" => UnknownMode(user, mode, message))
+}
+
+command! {
+    /// Represents an ERR_INVITEONLYCHAN numeric. The first element is the username, the second element is the
+    /// channel and the third element is the error message.
+    ("473" => InviteOnlyChannel(user, channel, message))
+}
+
+command! {
+    /// Represents an ERR_BANNEDFROMCHAN numeric. The first element is the username, the second element is the
+    /// channel and the third element is the error message.
+    ("474" => BannedFromChannel(user, channel, message))
+}
+
+command! {
+    /// Represents an ERR_BADCHANNELKEY numeric. The first element is the username, the second element is the
+    /// channel and the third element is the error message.
+    ("475" => BadChannelKey(user, channel, message))
+}
+
+command! {
+    /// Represents an ERR_NOPRIVILEGES numeric. The first element is the username and the second element is the
+    /// error message.
+    ("481" => NoPrivileges(user, message))
+}
+
+command! {
+    /// Represents an ERR_CHANOPRIVSNEEDED numeric. The first element is the username, the second element is the
+    /// channel and the third element is the error message.
+    ("482" => ChanOpPrivsNeeded(user, channel, message))
+}
+
+command! {
+    /// Represents an ERR_UMODEUNKNOWNFLAG numeric. The first element is the username and the second element is
+    /// the error message.
+    ("501" => UModeUnknownFlag(user, message))
+}
+
+command! {
+    /// Represents an ERR_USERSDONTMATCH numeric. The first element is the username and the second element is the
+    /// error message.
+    ("502" => UsersDontMatch(user, message))
+}
+
+command! {
+    /// Represents an RPL_WHOISSECURE numeric. The first element is the username, the second element is the
+    /// nickname and the third element is the message.
+    ("671" => WhoisSecure(user, nick, message))
+}
+
 #[derive(PartialEq, Debug)]
 pub enum NamesReplyChannelType {
     Secret,
@@ -252,3 +957,23 @@ impl<'a> Command<'a> for EndNamesReply<'a> {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use irc::message::Message;
+    use super::*;
+
+    #[test]
+    fn whois_idle_takes_the_message_from_the_end() {
+        let line = ":irc.test 317 me nick 42 1700000000 :seconds idle, signon time";
+        let message = Message::try_from(line.to_owned()).unwrap();
+        let WhoisIdle(_, nick, idle, signon, text) = message.command::<WhoisIdle>().unwrap();
+
+        assert_eq!((nick, idle, signon, text), ("nick", "42", Some("1700000000"), Some("seconds idle, signon time")));
+
+        let message = Message::try_from(":irc.test 317 me nick 42 :seconds idle".to_owned()).unwrap();
+        let WhoisIdle(_, nick, idle, signon, text) = message.command::<WhoisIdle>().unwrap();
+
+        assert_eq!((nick, idle, signon, text), ("nick", "42", None, Some("seconds idle")));
+    }
+}
@@ -0,0 +1,88 @@
+//! The config module contains the `Config` struct which describes how the
+//! client should connect to and identify itself with a remote IRC host.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use irc::codec;
+use irc::encoding::Encoding;
+use irc::sasl::Sasl;
+
+/// The registration details sent to the server when a connection is made,
+/// along with the settings that control the connection itself.
+///
+/// The preferred nickname is tried first, followed by each of the alternate
+/// nicknames in order whenever the server reports that a nickname is already
+/// in use or is not valid.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The preferred nickname.
+    pub nick: String,
+    /// Nicknames to fall back on, in order, when the preferred one is rejected.
+    pub alt_nicks: Vec<String>,
+    /// The username sent with the USER command.
+    pub username: String,
+    /// The real name sent with the USER command.
+    pub realname: String,
+    /// The server password sent with the PASS command, if any.
+    pub password: Option<String>,
+    /// The IRCv3 capabilities to request from the server, such as `server-time`.
+    pub capabilities: Vec<String>,
+    /// The SASL mechanism and credentials used to authenticate before registration completes, if any.
+    pub sasl: Option<Sasl>,
+    /// How long to wait for each address of the host to accept the connection.
+    pub connect_timeout: Duration,
+    /// The longest line accepted from the server, including the tag section. Longer lines are discarded.
+    pub max_line_length: usize,
+    /// How the text of messages is decoded and encoded. Defaults to UTF-8, falling back to CP1252.
+    pub encoding: Encoding,
+    /// The encodings used instead for particular channels and users.
+    pub target_encodings: HashMap<String, Encoding>,
+    /// How long the connection may go without receiving anything before a PING is sent.
+    pub ping_interval: Duration,
+    /// How long to wait for a reply to a PING before the connection is considered dead.
+    pub ping_timeout: Duration,
+    /// The number of bytes that may be sent in a burst before outgoing messages are throttled.
+    pub send_burst: usize,
+    /// The number of bytes per second that may be sent once the burst is used up, or zero to
+    /// send messages without throttling.
+    pub send_rate: usize,
+    /// The delay before the first reconnection attempt, which doubles after each failed attempt.
+    pub reconnect_delay: Duration,
+    /// The longest delay between reconnection attempts.
+    pub max_reconnect_delay: Duration,
+    /// Whether CTCP VERSION, PING, TIME, SOURCE and CLIENTINFO requests are answered automatically when sent to us privately.
+    pub ctcp_replies: bool,
+    /// The reply to CTCP VERSION requests.
+    pub ctcp_version: String,
+    /// The reply to CTCP SOURCE requests, such as the address of the client's repository, if any.
+    pub ctcp_source: Option<String>,
+}
+
+impl Config {
+    /// Create a new `Config` that uses `nick` as the nickname, username and real name.
+    pub fn new(nick: &str) -> Config {
+        Config {
+            nick: nick.to_owned(),
+            alt_nicks: Vec::new(),
+            username: nick.to_owned(),
+            realname: nick.to_owned(),
+            password: None,
+            capabilities: Vec::new(),
+            sasl: None,
+            connect_timeout: Duration::from_secs(10),
+            max_line_length: codec::DEFAULT_MAX_LINE_LENGTH,
+            encoding: Encoding::default(),
+            target_encodings: HashMap::new(),
+            ping_interval: Duration::from_secs(60),
+            ping_timeout: Duration::from_secs(30),
+            send_burst: 1024,
+            send_rate: 128,
+            reconnect_delay: Duration::from_secs(1),
+            max_reconnect_delay: Duration::from_secs(5 * 60),
+            ctcp_replies: true,
+            ctcp_version: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            ctcp_source: None,
+        }
+    }
+}
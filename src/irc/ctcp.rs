@@ -0,0 +1,238 @@
+//! The ctcp module contains the `Ctcp` type which extracts Client-To-Client Protocol
+//! requests and replies, such as `\x01ACTION waves\x01`, from the text of PRIVMSG and
+//! NOTICE messages, and the `CtcpResponder` which answers common requests automatically.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant, SystemTime};
+
+use irc::command::PrivMsg;
+use irc::error::{ErrorKind, Result};
+use irc::isupport::ISupport;
+use irc::message::Message;
+use irc::tag::ServerTime;
+
+// The character that delimits CTCP messages.
+const DELIMITER: char = '\x01';
+
+// How long a sender has to wait between requests before we answer another one.
+const REPLY_INTERVAL: Duration = Duration::from_secs(3);
+
+// How many replies are sent at most within `REPLY_WINDOW`, whoever the senders are.
+const MAX_REPLIES: usize = 5;
+const REPLY_WINDOW: Duration = Duration::from_secs(10);
+
+// The requests answered by `CtcpResponder`, as listed in the reply to CLIENTINFO.
+const SUPPORTED: &str = "ACTION CLIENTINFO PING SOURCE TIME VERSION";
+
+/// A CTCP request sent within a PRIVMSG, or a reply sent within a NOTICE. The first element
+/// is the command, such as `ACTION` or `VERSION`, and the second element is everything after it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ctcp<'a>(pub &'a str, pub Option<&'a str>);
+
+impl<'a> Ctcp<'a> {
+    /// Extracts the CTCP message from the text of a PRIVMSG or NOTICE, if it contains one.
+    /// The closing delimiter is optional, as some clients leave it out.
+    pub fn parse(text: &'a str) -> Option<Ctcp<'a>> {
+        if !text.starts_with(DELIMITER) || text.len() < 2 {
+            return None;
+        }
+
+        let body = &text[1..];
+        let body = if body.ends_with(DELIMITER) { &body[..body.len() - 1] } else { body };
+
+        match body.find(' ') {
+            Some(index) => Some(Ctcp(&body[..index], Some(&body[index + 1..]))),
+            None => Some(Ctcp(body, None)),
+        }
+    }
+
+    /// Get the command of the message.
+    pub fn command(&self) -> &'a str {
+        self.0
+    }
+
+    /// Get everything after the command.
+    pub fn params(&self) -> Option<&'a str> {
+        self.1
+    }
+
+    /// Returns true if this is an ACTION, as sent with the `/me` command.
+    pub fn is_action(&self) -> bool {
+        self.0.eq_ignore_ascii_case("ACTION")
+    }
+}
+
+/// Formats a CTCP message, such as `\x01VERSION\x01`, to be sent as the text of a PRIVMSG or NOTICE.
+pub fn format(command: &str, params: Option<&str>) -> Result<String> {
+    if command.is_empty() || command.contains(' ') || command.contains(DELIMITER) {
+        return Err(ErrorKind::InvalidMessage(format!("The CTCP command {:?} is not valid.", command)).into());
+    }
+
+    if params.map(|params| params.contains(DELIMITER)).unwrap_or(false) {
+        return Err(ErrorKind::InvalidMessage("The CTCP parameters contain a \\x01 character.".to_owned()).into());
+    }
+
+    Ok(match params {
+        Some(params) => format!("{}{} {}{}", DELIMITER, command, params, DELIMITER),
+        None => format!("{}{}{}", DELIMITER, command, DELIMITER),
+    })
+}
+
+/// Answers the VERSION, PING, TIME, SOURCE and CLIENTINFO requests received from other users.
+///
+/// Each sender is answered at most once every few seconds, and no more than a few replies are
+/// sent every ten seconds overall, so that a flood of requests, even from many hosts at once,
+/// can't be used to make us flood the server and get disconnected. Requests that would go over
+/// either limit are ignored, as are requests sent to a channel rather than to us.
+#[derive(Clone, Debug)]
+pub struct CtcpResponder {
+    version: String,
+    source: Option<String>,
+    answered: HashMap<String, Instant>,
+    replies: VecDeque<Instant>,
+}
+
+impl CtcpResponder {
+    /// Create a new `CtcpResponder` that replies to VERSION requests with `version`, and to
+    /// SOURCE requests with `source` when it is given.
+    pub fn new(version: &str, source: Option<&str>) -> CtcpResponder {
+        CtcpResponder {
+            version: version.to_owned(),
+            source: source.map(|source| source.to_owned()),
+            answered: HashMap::new(),
+            replies: VecDeque::new(),
+        }
+    }
+
+    /// Returns the reply to `message` if it is a CTCP request that should be answered.
+    /// Requests whose reply can't be sent, such as a PING with a malformed token, are ignored.
+    /// `isupport` tells which targets are channels.
+    pub fn handle(&mut self, message: &Message, isupport: &ISupport) -> Option<Message> {
+        let ctcp = match message.command::<PrivMsg>() {
+            Some(PrivMsg(target, _)) if isupport.is_channel(target) => return None,
+            Some(PrivMsg(_, text)) => Ctcp::parse(text),
+            None => None,
+        };
+
+        let ctcp = match ctcp {
+            Some(ctcp) => ctcp,
+            None => return None,
+        };
+
+        let (nick, host) = match message.prefix() {
+            Some((nick, _, host)) => (nick, host.unwrap_or(nick)),
+            None => return None,
+        };
+
+        let reply = match ctcp.command().to_ascii_uppercase().as_str() {
+            "VERSION" => Some(self.version.clone()),
+            "PING" => ctcp.params().map(|params| params.to_owned()),
+            "TIME" => Some(ServerTime(SystemTime::now()).to_string()),
+            "SOURCE" => self.source.clone(),
+            "CLIENTINFO" => Some(SUPPORTED.to_owned()),
+            _ => None,
+        };
+
+        let reply = match reply {
+            Some(reply) => reply,
+            None => return None,
+        };
+
+        // NOTE: Senders are told apart by their host, so that changing nicknames doesn't get around the limit.
+        let now = Instant::now();
+
+        if let Some(&answered) = self.answered.get(host) {
+            if now.duration_since(answered) < REPLY_INTERVAL {
+                return None;
+            }
+        }
+
+        while self.replies.front().map(|&sent| now.duration_since(sent) >= REPLY_WINDOW).unwrap_or(false) {
+            self.replies.pop_front();
+        }
+
+        if self.replies.len() >= MAX_REPLIES {
+            return None;
+        }
+
+        self.answered.retain(|_, &mut answered| now.duration_since(answered) < REPLY_INTERVAL);
+        self.answered.insert(host.to_owned(), now);
+        self.replies.push_back(now);
+
+        Message::ctcp_reply(nick, ctcp.command(), Some(&reply)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(line: &str) -> Message {
+        Message::try_from(line.to_owned()).unwrap()
+    }
+
+    #[test]
+    fn parses_requests() {
+        assert_eq!(Ctcp::parse("\x01VERSION\x01"), Some(Ctcp("VERSION", None)));
+        assert_eq!(Ctcp::parse("\x01ACTION waves\x01"), Some(Ctcp("ACTION", Some("waves"))));
+        assert_eq!(Ctcp::parse("\x01ACTION waves"), Some(Ctcp("ACTION", Some("waves"))));
+        assert_eq!(Ctcp::parse("\x01"), None);
+        assert_eq!(Ctcp::parse("hello"), None);
+        assert!(Ctcp::parse("\x01action x\x01").unwrap().is_action());
+    }
+
+    #[test]
+    fn formats_requests() {
+        assert_eq!(format("PING", Some("123")).unwrap(), "\x01PING 123\x01");
+        assert_eq!(format("VERSION", None).unwrap(), "\x01VERSION\x01");
+        assert!(format("", None).is_err());
+        assert!(format("TWO WORDS", None).is_err());
+        assert!(format("PING", Some("a\x01b")).is_err());
+    }
+
+    #[test]
+    fn answers_private_requests() {
+        let mut responder = CtcpResponder::new("client 1.0", None);
+        let isupport = ISupport::new();
+
+        let reply = responder.handle(&request(":friend!f@host PRIVMSG me :\x01VERSION\x01"), &isupport).unwrap();
+        assert_eq!(reply.raw_message(), "NOTICE friend :\x01VERSION client 1.0\x01");
+
+        let mut responder = CtcpResponder::new("client 1.0", None);
+        assert!(responder.handle(&request(":friend!f@host PRIVMSG me :\x01SOURCE\x01"), &isupport).is_none());
+        assert!(responder.handle(&request(":friend!f@host PRIVMSG me :\x01FINGER\x01"), &isupport).is_none());
+        assert!(responder.handle(&request(":friend!f@host NOTICE me :\x01VERSION\x01"), &isupport).is_none());
+    }
+
+    #[test]
+    fn ignores_channel_requests() {
+        let mut responder = CtcpResponder::new("client 1.0", None);
+        let isupport = ISupport::new();
+
+        assert!(responder.handle(&request(":friend!f@host PRIVMSG #chan :\x01VERSION\x01"), &isupport).is_none());
+        assert!(responder.handle(&request(":friend!f@host PRIVMSG &chan :\x01VERSION\x01"), &isupport).is_none());
+    }
+
+    #[test]
+    fn limits_replies_per_host() {
+        let mut responder = CtcpResponder::new("client 1.0", None);
+        let isupport = ISupport::new();
+
+        assert!(responder.handle(&request(":a!f@host PRIVMSG me :\x01PING 1\x01"), &isupport).is_some());
+        assert!(responder.handle(&request(":b!f@host PRIVMSG me :\x01PING 2\x01"), &isupport).is_none());
+        assert!(responder.handle(&request(":c!f@other PRIVMSG me :\x01PING 3\x01"), &isupport).is_some());
+    }
+
+    #[test]
+    fn limits_replies_overall() {
+        let mut responder = CtcpResponder::new("client 1.0", None);
+        let isupport = ISupport::new();
+
+        for host in 0..MAX_REPLIES {
+            let line = format!(":n{0}!u@host{0} PRIVMSG me :\x01VERSION\x01", host);
+            assert!(responder.handle(&request(&line), &isupport).is_some());
+        }
+
+        assert!(responder.handle(&request(":late!u@elsewhere PRIVMSG me :\x01VERSION\x01"), &isupport).is_none());
+    }
+}
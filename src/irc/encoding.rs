@@ -0,0 +1,258 @@
+//! The encoding module contains the types that describe how the bytes sent
+//! and received over a connection are converted to and from text.
+//!
+//! IRC has no fixed character encoding. Most networks use UTF-8, but older
+//! clients and networks still send legacy encodings such as ISO-8859-1,
+//! CP1252 or KOI8-R.
+
+use std::collections::HashMap;
+
+use irc::casemap;
+use irc::error::Result;
+use irc::isupport::{CaseMapping, ISupport};
+use irc::message::Message;
+
+// The characters of CP1252 for the bytes 0x80 to 0x9F. The remaining bytes are the same as ISO-8859-1.
+// The five bytes CP1252 leaves undefined are mapped to the control characters of the same value.
+const CP1252: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+// The characters of KOI8-R for the bytes 0x80 to 0xFF.
+const KOI8_R: [char; 128] = [
+    '\u{2500}', '\u{2502}', '\u{250C}', '\u{2510}', '\u{2514}', '\u{2518}', '\u{251C}', '\u{2524}',
+    '\u{252C}', '\u{2534}', '\u{253C}', '\u{2580}', '\u{2584}', '\u{2588}', '\u{258C}', '\u{2590}',
+    '\u{2591}', '\u{2592}', '\u{2593}', '\u{2320}', '\u{25A0}', '\u{2219}', '\u{221A}', '\u{2248}',
+    '\u{2264}', '\u{2265}', '\u{00A0}', '\u{2321}', '\u{00B0}', '\u{00B2}', '\u{00B7}', '\u{00F7}',
+    '\u{2550}', '\u{2551}', '\u{2552}', '\u{0451}', '\u{2553}', '\u{2554}', '\u{2555}', '\u{2556}',
+    '\u{2557}', '\u{2558}', '\u{2559}', '\u{255A}', '\u{255B}', '\u{255C}', '\u{255D}', '\u{255E}',
+    '\u{255F}', '\u{2560}', '\u{2561}', '\u{0401}', '\u{2562}', '\u{2563}', '\u{2564}', '\u{2565}',
+    '\u{2566}', '\u{2567}', '\u{2568}', '\u{2569}', '\u{256A}', '\u{256B}', '\u{256C}', '\u{00A9}',
+    '\u{044E}', '\u{0430}', '\u{0431}', '\u{0446}', '\u{0434}', '\u{0435}', '\u{0444}', '\u{0433}',
+    '\u{0445}', '\u{0438}', '\u{0439}', '\u{043A}', '\u{043B}', '\u{043C}', '\u{043D}', '\u{043E}',
+    '\u{043F}', '\u{044F}', '\u{0440}', '\u{0441}', '\u{0442}', '\u{0443}', '\u{0436}', '\u{0432}',
+    '\u{044C}', '\u{044B}', '\u{0437}', '\u{0448}', '\u{044D}', '\u{0449}', '\u{0447}', '\u{044A}',
+    '\u{042E}', '\u{0410}', '\u{0411}', '\u{0426}', '\u{0414}', '\u{0415}', '\u{0424}', '\u{0413}',
+    '\u{0425}', '\u{0418}', '\u{0419}', '\u{041A}', '\u{041B}', '\u{041C}', '\u{041D}', '\u{041E}',
+    '\u{041F}', '\u{042F}', '\u{0420}', '\u{0421}', '\u{0422}', '\u{0423}', '\u{0416}', '\u{0412}',
+    '\u{042C}', '\u{042B}', '\u{0417}', '\u{0428}', '\u{042D}', '\u{0429}', '\u{0427}', '\u{042A}',
+];
+
+/// A legacy single byte character set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Charset {
+    /// ISO-8859-1, also known as Latin-1.
+    Latin1,
+    /// Windows-1252, the Western European code page used by Windows.
+    Cp1252,
+    /// KOI8-R, used for Russian text.
+    Koi8R,
+}
+
+impl Charset {
+    /// Converts bytes in this character set to text. Every byte maps to a character,
+    /// so decoding can't fail.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        bytes.iter().map(|&byte| self.decode_byte(byte)).collect()
+    }
+
+    /// Converts text to bytes in this character set. Characters that the character
+    /// set can't represent are replaced with `?`.
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        text.chars().map(|c| self.encode_char(c).unwrap_or(b'?')).collect()
+    }
+
+    fn decode_byte(&self, byte: u8) -> char {
+        if byte < 0x80 {
+            return byte as char;
+        }
+
+        match *self {
+            Charset::Latin1 => byte as char,
+            Charset::Cp1252 if byte < 0xA0 => CP1252[(byte - 0x80) as usize],
+            Charset::Cp1252 => byte as char,
+            Charset::Koi8R => KOI8_R[(byte - 0x80) as usize],
+        }
+    }
+
+    fn encode_char(&self, c: char) -> Option<u8> {
+        if (c as u32) < 0x80 {
+            return Some(c as u8);
+        }
+
+        let table: &[char] = match *self {
+            Charset::Latin1 => return if (c as u32) < 0x100 { Some(c as u32 as u8) } else { None },
+            Charset::Cp1252 if (c as u32) >= 0xA0 && (c as u32) < 0x100 => return Some(c as u32 as u8),
+            Charset::Cp1252 => &CP1252,
+            Charset::Koi8R => &KOI8_R,
+        };
+
+        table.iter().position(|&mapped| mapped == c).map(|index| 0x80 + index as u8)
+    }
+}
+
+/// Describes how the bytes of a line are converted to and from text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Lines must be valid UTF-8. Any other line is rejected with a `Utf8` error,
+    /// after which the following lines can still be received.
+    Strict,
+    /// Lines are decoded as UTF-8, replacing invalid sequences with U+FFFD.
+    Lossy,
+    /// Lines are decoded as UTF-8 when they are valid, and using the given
+    /// character set otherwise. Text is always sent as UTF-8.
+    Fallback(Charset),
+    /// Lines are decoded and sent using the given character set.
+    Legacy(Charset),
+}
+
+impl Encoding {
+    /// Converts the bytes of a line to text.
+    pub fn decode(&self, bytes: &[u8]) -> Result<String> {
+        match *self {
+            Encoding::Strict => Ok(String::from_utf8(bytes.to_vec())?),
+            Encoding::Lossy => Ok(String::from_utf8_lossy(bytes).into_owned()),
+            Encoding::Fallback(charset) => match String::from_utf8(bytes.to_vec()) {
+                Ok(text) => Ok(text),
+                Err(_) => Ok(charset.decode(bytes)),
+            },
+            Encoding::Legacy(charset) => Ok(charset.decode(bytes)),
+        }
+    }
+
+    /// Converts text to the bytes sent over the connection.
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        match *self {
+            Encoding::Legacy(charset) => charset.encode(text),
+            _ => text.as_bytes().to_vec(),
+        }
+    }
+}
+
+impl Default for Encoding {
+    fn default() -> Encoding {
+        Encoding::Fallback(Charset::Cp1252)
+    }
+}
+
+/// The encoding used for a connection, along with the encodings used for
+/// particular channels and users instead.
+///
+/// The encoding of a received message is chosen by its target, the first
+/// parameter, falling back to the nickname of the sender so that private
+/// messages from a user are decoded with that user's encoding. The encoding of
+/// a sent message is chosen by its target. Targets are compared using the case
+/// mapping the server advertises in `RPL_ISUPPORT`, which defaults to `rfc1459`.
+#[derive(Clone, Debug)]
+pub struct Encodings {
+    default: Encoding,
+    targets: Vec<(String, Encoding)>,
+    casemapping: CaseMapping,
+}
+
+impl Encodings {
+    /// Create a new `Encodings` that uses `default` unless one of `targets` applies.
+    pub fn new(default: Encoding, targets: &HashMap<String, Encoding>) -> Encodings {
+        Encodings {
+            default: default,
+            targets: targets.iter().map(|(target, &encoding)| (target.clone(), encoding)).collect(),
+            casemapping: CaseMapping::Rfc1459,
+        }
+    }
+
+    /// Get the encoding used for messages to or from `target`.
+    pub fn for_target(&self, target: &str) -> Encoding {
+        self.find(target).unwrap_or(self.default)
+    }
+
+    /// Converts the bytes of a received line to text.
+    pub fn decode(&mut self, bytes: &[u8]) -> Result<String> {
+        if self.targets.is_empty() {
+            return self.default.decode(bytes);
+        }
+
+        // NOTE: The structure of the line is ASCII, so a lossy decoding is enough to find its target.
+        let message = match Message::from_received(String::from_utf8_lossy(bytes).into_owned()) {
+            Ok(message) => message,
+            Err(_) => return self.default.decode(bytes),
+        };
+
+        // NOTE: The codec can't see the state of the connection, so the case mapping
+        // advertised by the server is picked up here.
+        let mut isupport = ISupport::new();
+
+        if isupport.handle(&message) && isupport.contains("CASEMAPPING") {
+            self.casemapping = isupport.casemapping();
+        }
+
+        let target = message.raw_args().next().and_then(|target| self.find(target));
+        let sender = message.prefix().and_then(|(nick, _, _)| self.find(nick));
+
+        target.or(sender).unwrap_or(self.default).decode(bytes)
+    }
+
+    fn find(&self, target: &str) -> Option<Encoding> {
+        self.targets
+            .iter()
+            .find(|&&(ref name, _)| casemap::eq(&self.casemapping, name, target))
+            .map(|&(_, encoding)| encoding)
+    }
+
+    /// Converts a message to the bytes sent over the connection.
+    pub fn encode(&self, message: &Message) -> Vec<u8> {
+        let encoding = match message.raw_args().next() {
+            Some(target) => self.for_target(target),
+            None => self.default,
+        };
+
+        encoding.encode(message.raw_message())
+    }
+}
+
+impl Default for Encodings {
+    fn default() -> Encodings {
+        Encodings::new(Encoding::default(), &HashMap::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encodings() -> Encodings {
+        let mut targets = HashMap::new();
+        targets.insert("#Rust[]".to_owned(), Encoding::Legacy(Charset::Koi8R));
+        targets.insert("Friend".to_owned(), Encoding::Legacy(Charset::Koi8R));
+
+        Encodings::new(Encoding::Lossy, &targets)
+    }
+
+    #[test]
+    fn converts_legacy_charsets() {
+        let text = "\u{20AC} caf\u{E9}";
+
+        assert_eq!(Charset::Cp1252.decode(&Charset::Cp1252.encode(text)), text);
+        assert_eq!(Charset::Latin1.encode(text), b"? caf\xE9");
+        assert_eq!(Charset::Koi8R.decode(b"\xF0\xD2\xC9\xD7\xC5\xD4"), "\u{41F}\u{440}\u{438}\u{432}\u{435}\u{442}");
+        assert_eq!(Encoding::default().decode(b"caf\xE9").unwrap(), "caf\u{E9}");
+        assert!(Encoding::Strict.decode(b"caf\xE9").is_err());
+    }
+
+    #[test]
+    fn compares_targets_with_the_server_casemapping() {
+        let mut encodings = encodings();
+
+        assert_eq!(encodings.for_target("#rust{}"), Encoding::Legacy(Charset::Koi8R));
+        assert_eq!(encodings.decode(b":nick PRIVMSG #RUST{} :\xF0").unwrap(), ":nick PRIVMSG #RUST{} :\u{41F}");
+        assert_eq!(encodings.decode(b":FRIEND PRIVMSG me :\xF0").unwrap(), ":FRIEND PRIVMSG me :\u{41F}");
+
+        encodings.decode(b":irc.test 005 me CASEMAPPING=ascii :are supported").unwrap();
+
+        assert_eq!(encodings.for_target("#rust[]"), Encoding::Legacy(Charset::Koi8R));
+        assert_eq!(encodings.for_target("#rust{}"), Encoding::Lossy);
+        assert_eq!(encodings.decode(b":nick PRIVMSG #RUST{} :\xF0").unwrap(), ":nick PRIVMSG #RUST{} :\u{FFFD}");
+    }
+}
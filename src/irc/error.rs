@@ -16,10 +16,102 @@ error_chain! {
             display("{}", message)
         }
         
+        LineTooLong(max: usize) {
+            description("A line received from the server was too long and has been discarded.")
+            display("A line longer than {} bytes was received from the server and has been discarded.", max)
+        }
+
+        MalformedLine(message: String) {
+            description("A line received from the server could not be parsed and has been discarded.")
+            display("A line received from the server could not be parsed and has been discarded: {}", message)
+        }
+
+        InvalidMessage(message: String) {
+            description("The message could not be built.")
+            display("The message could not be built: {}", message)
+        }
+
         ConnectionReset {
             description("The connection was reset by the remote host.")
             display("The connection was reset by the remote host.")
         }
+
+        PingTimeout(timeout: ::std::time::Duration) {
+            description("The server did not respond to a PING in time.")
+            display("The server did not respond to a PING within {} seconds.", timeout.as_secs())
+        }
+
+        ResolveFailed(host: String, message: String) {
+            description("The host name could not be resolved.")
+            display("The host name {} could not be resolved: {}", host, message)
+        }
+
+        ConnectFailed(failures: Vec<(::std::net::SocketAddr, String)>) {
+            description("A connection could not be made to any of the host's addresses.")
+            display("A connection could not be made to any of the host's addresses: {}",
+                failures.iter()
+                    .map(|&(ref address, ref failure)| format!("{} ({})", address, failure))
+                    .collect::<Vec<_>>()
+                    .join(", "))
+        }
+
+        Tls(message: String) {
+            description("The encrypted connection could not be established.")
+            display("The encrypted connection could not be established: {}", message)
+        }
+
+        NicknameInUse(nick: String) {
+            description("The nickname is already in use.")
+            display("The nickname {} is already in use.", nick)
+        }
+
+        ErroneousNickname(nick: String) {
+            description("The nickname was rejected by the server.")
+            display("The nickname {} was rejected by the server.", nick)
+        }
+
+        PasswordMismatch {
+            description("The server password was incorrect.")
+            display("The server password was incorrect.")
+        }
+
+        Banned(reason: String) {
+            description("The client is banned from the server.")
+            display("The client is banned from the server: {}", reason)
+        }
+
+        SaslUnavailable {
+            description("The server does not support SASL authentication.")
+            display("The server does not support SASL authentication.")
+        }
+
+        SaslMechanismUnsupported(mechanisms: String) {
+            description("The SASL mechanism is not supported by the server.")
+            display("The SASL mechanism is not supported by the server, which supports: {}", mechanisms)
+        }
+
+        SaslFailed(reason: String) {
+            description("SASL authentication failed.")
+            display("SASL authentication failed: {}", reason)
+        }
+
+        SaslAborted {
+            description("SASL authentication was aborted.")
+            display("SASL authentication was aborted.")
+        }
+
+        NickLocked {
+            description("SASL authentication failed because the nickname is locked.")
+            display("SASL authentication failed because the nickname is locked.")
+        }
     }
     
 }
+
+impl Error {
+    /// Returns true if the error only affected a single line received from the
+    /// server, so the stream that produced it can continue to be polled.
+    pub fn is_recoverable(&self) -> bool {
+        matches!(*self.kind(), ErrorKind::LineTooLong(_) | ErrorKind::MalformedLine(_) | ErrorKind::Utf8(_))
+    }
+}
@@ -0,0 +1,435 @@
+//! The event module contains the `Event` enum which describes what a message
+//! received from the server means, and the `Dispatcher` type which passes events
+//! on to the handlers registered for them.
+
+extern crate tokio_io;
+
+use std::collections::VecDeque;
+
+use futures::{Async, AsyncSink, Future, Poll, Sink, Stream};
+use self::tokio_io::{AsyncRead, AsyncWrite};
+
+use irc::command::{Invite, Join, Kick, Mode as ModeCommand, Nick, Notice, Part, PrivMsg, Quit,
+                   Topic as TopicCommand};
+use irc::ctcp::Ctcp;
+use irc::error::{Error, Result};
+use irc::isupport::ISupport;
+use irc::message::Message;
+use irc::state::State;
+use irc::transport::IrcTransport;
+
+/// The meaning of a message received from the server.
+///
+/// Every borrowed value points into the message the event was created from. The
+/// `source` of an event is the nickname, or server name, the message came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// A PRIVMSG sent to a channel or to us.
+    Message { source: Option<&'a str>, target: &'a str, text: &'a str },
+    /// A NOTICE sent to a channel or to us.
+    Notice { source: Option<&'a str>, target: &'a str, text: &'a str },
+    /// A CTCP request, sent within a PRIVMSG. `params` is everything after the CTCP command.
+    Ctcp { source: Option<&'a str>, target: &'a str, command: &'a str, params: Option<&'a str> },
+    /// A CTCP reply, sent within a NOTICE. `params` is everything after the CTCP command.
+    CtcpReply { source: Option<&'a str>, target: &'a str, command: &'a str, params: Option<&'a str> },
+    /// A user joined a channel. The server sends a separate JOIN for every channel.
+    Join { nick: &'a str, channel: &'a str },
+    /// A user left a channel.
+    Part { nick: &'a str, channel: &'a str, reason: Option<&'a str> },
+    /// Users were kicked from channels by `source`. Each pair is a channel and the nickname
+    /// kicked from it, as the KICK command accepts lists of both.
+    Kick { source: Option<&'a str>, kicks: Vec<(&'a str, &'a str)>, reason: Option<&'a str> },
+    /// A user changed their nickname from `old` to `new`.
+    NickChange { old: &'a str, new: &'a str },
+    /// A user disconnected from the server.
+    Quit { nick: &'a str, reason: Option<&'a str> },
+    /// The topic of a channel was changed. An empty topic means it was cleared.
+    Topic { source: Option<&'a str>, channel: &'a str, topic: &'a str },
+    /// The modes of a channel or a user were changed.
+    Mode { source: Option<&'a str>, target: &'a str, modes: &'a str, arguments: Vec<&'a str> },
+    /// We were invited to a channel.
+    Invite { source: Option<&'a str>, nick: &'a str, channel: &'a str },
+    /// A numeric reply, with the arguments following our own nickname.
+    Numeric { code: u16, arguments: Vec<&'a str> },
+    /// Any other message.
+    Raw(&'a Message),
+}
+
+/// The kinds of `Event`, used to register handlers for some events only.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Message,
+    Notice,
+    Ctcp,
+    CtcpReply,
+    Join,
+    Part,
+    Kick,
+    NickChange,
+    Quit,
+    Topic,
+    Mode,
+    Invite,
+    Numeric,
+    Raw,
+}
+
+impl<'a> Event<'a> {
+    /// Creates the event describing `message`.
+    pub fn new(message: &'a Message) -> Event<'a> {
+        let source = message.prefix().map(|(nick, _, _)| nick);
+
+        if let Some(PrivMsg(target, text)) = message.command::<PrivMsg>() {
+            return match Ctcp::parse(text) {
+                Some(Ctcp(command, params)) => Event::Ctcp { source: source, target: target, command: command, params: params },
+                None => Event::Message { source: source, target: target, text: text },
+            };
+        }
+
+        if let Some(Notice(target, text)) = message.command::<Notice>() {
+            return match Ctcp::parse(text) {
+                Some(Ctcp(command, params)) => Event::CtcpReply { source: source, target: target, command: command, params: params },
+                None => Event::Notice { source: source, target: target, text: text },
+            };
+        }
+
+        if let Some(code) = message.numeric_code() {
+            return Event::Numeric {
+                code: code,
+                arguments: message.raw_args().skip(1).collect(),
+            };
+        }
+
+        if let Some(ModeCommand(target, modes, arguments)) = message.command::<ModeCommand>() {
+            return Event::Mode {
+                source: source,
+                target: target,
+                modes: modes.unwrap_or(""),
+                arguments: arguments.collect(),
+            };
+        }
+
+        if let Some(kick) = message.command::<Kick>() {
+            return Event::Kick { source: source, kicks: kick.kicks(), reason: kick.2 };
+        }
+
+        if let Some(TopicCommand(channel, topic)) = message.command::<TopicCommand>() {
+            return Event::Topic { source: source, channel: channel, topic: topic.unwrap_or("") };
+        }
+
+        if let Some(Invite(nick, channel)) = message.command::<Invite>() {
+            return Event::Invite { source: source, nick: nick, channel: channel };
+        }
+
+        // The remaining events are meaningless without knowing who sent them.
+        let nick = match source {
+            Some(nick) => nick,
+            None => return Event::Raw(message),
+        };
+
+        if let Some(Join(channel, _, _)) = message.command::<Join>() {
+            Event::Join { nick: nick, channel: channel }
+        } else if let Some(Part(channel, reason)) = message.command::<Part>() {
+            Event::Part { nick: nick, channel: channel, reason: reason }
+        } else if let Some(Nick(new)) = message.command::<Nick>() {
+            Event::NickChange { old: nick, new: new }
+        } else if let Some(Quit(reason)) = message.command::<Quit>() {
+            Event::Quit { nick: nick, reason: reason }
+        } else {
+            Event::Raw(message)
+        }
+    }
+
+    /// Get the kind of this event.
+    pub fn kind(&self) -> EventKind {
+        match *self {
+            Event::Message { .. } => EventKind::Message,
+            Event::Notice { .. } => EventKind::Notice,
+            Event::Ctcp { .. } => EventKind::Ctcp,
+            Event::CtcpReply { .. } => EventKind::CtcpReply,
+            Event::Join { .. } => EventKind::Join,
+            Event::Part { .. } => EventKind::Part,
+            Event::Kick { .. } => EventKind::Kick,
+            Event::NickChange { .. } => EventKind::NickChange,
+            Event::Quit { .. } => EventKind::Quit,
+            Event::Topic { .. } => EventKind::Topic,
+            Event::Mode { .. } => EventKind::Mode,
+            Event::Invite { .. } => EventKind::Invite,
+            Event::Numeric { .. } => EventKind::Numeric,
+            Event::Raw(_) => EventKind::Raw,
+        }
+    }
+}
+
+/// Tells the dispatcher whether to pass an event on to the remaining handlers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Propagation {
+    /// Pass the event on to the next handler.
+    Continue,
+    /// Don't pass the event on to any further handlers.
+    Stop,
+}
+
+/// What a handler has access to while handling an event.
+pub struct Context<'a> {
+    message: &'a Message,
+    state: &'a State,
+    replies: &'a mut Vec<Message>,
+}
+
+impl<'a> Context<'a> {
+    /// Get the message the event was created from.
+    pub fn message(&self) -> &'a Message {
+        self.message
+    }
+
+    /// Get the channels, users and nickname tracked from the messages received so far.
+    pub fn state(&self) -> &'a State {
+        self.state
+    }
+
+    /// Get the features advertised by the server with `RPL_ISUPPORT`.
+    pub fn isupport(&self) -> &'a ISupport {
+        self.state.isupport()
+    }
+
+    /// Queues a message to be sent to the server once the event has been handled.
+    pub fn send(&mut self, message: Message) {
+        self.replies.push(message);
+    }
+
+    /// Get where a reply to the message should go: the channel it was sent to, or the
+    /// user who sent it when it was sent to us privately.
+    pub fn reply_target(&self) -> Option<&'a str> {
+        let target = self.message.raw_args().next();
+
+        match target {
+            Some(target) if self.isupport().is_channel(target) => Some(target),
+            _ => self.message.prefix().map(|(nick, _, _)| nick),
+        }
+    }
+
+    /// Queues a PRIVMSG replying to the message, as described by `reply_target`. The text
+    /// is split across several messages when it's too long to fit in one.
+    pub fn reply(&mut self, text: &str) -> Result<()> {
+        if let Some(target) = self.reply_target() {
+            self.replies.extend(Message::split_priv_msg(target, text, None)?);
+        }
+
+        Ok(())
+    }
+}
+
+/// The `Handler` trait is implemented by types that handle events passed on by a `Dispatcher`.
+///
+/// It is implemented for closures taking an event and a context, so handlers can be written as
+/// `|event: &Event, context: &mut Context| { ...; Propagation::Continue }`.
+pub trait Handler {
+    /// Handles an event, returning whether it should be passed on to the remaining handlers.
+    fn handle(&mut self, event: &Event, context: &mut Context) -> Propagation;
+}
+
+impl<F> Handler for F
+where
+    F: FnMut(&Event, &mut Context) -> Propagation,
+{
+    fn handle(&mut self, event: &Event, context: &mut Context) -> Propagation {
+        self(event, context)
+    }
+}
+
+struct Registration {
+    kinds: Vec<EventKind>,
+    priority: i32,
+    handler: Box<dyn Handler>,
+}
+
+/// Passes the events created from received messages on to the handlers registered for them.
+///
+/// Handlers can query the `State` tracked by the transport through their `Context`.
+/// Handlers run in order of priority, lowest first, and handlers with the same priority run in
+/// the order they were registered. Any handler can stop an event from reaching the handlers
+/// after it. Messages queued by the handlers are returned by `dispatch`, or sent by the `Dispatch`
+/// future returned from `run`.
+#[derive(Default)]
+pub struct Dispatcher {
+    handlers: Vec<Registration>,
+}
+
+impl Dispatcher {
+    /// Create a new `Dispatcher` without any handlers.
+    pub fn new() -> Dispatcher {
+        Dispatcher::default()
+    }
+
+    /// Registers a handler for the given kinds of events, or for every event when `kinds` is empty.
+    pub fn register<H>(&mut self, kinds: &[EventKind], priority: i32, handler: H)
+    where
+        H: Handler + 'static,
+    {
+        let index = self.handlers
+            .iter()
+            .position(|registration| registration.priority > priority)
+            .unwrap_or(self.handlers.len());
+
+        self.handlers.insert(index, Registration {
+            kinds: kinds.to_vec(),
+            priority: priority,
+            handler: Box::new(handler),
+        });
+    }
+
+    /// Passes the event created from `message` on to the handlers, returning the messages they queued.
+    /// `state` is what the transport tracked up to and including `message`.
+    pub fn dispatch(&mut self, message: &Message, state: &State) -> Vec<Message> {
+        let event = Event::new(message);
+        let kind = event.kind();
+        let mut replies = Vec::new();
+
+        for registration in &mut self.handlers {
+            if !registration.kinds.is_empty() && !registration.kinds.contains(&kind) {
+                continue;
+            }
+
+            let mut context = Context {
+                message: message,
+                state: state,
+                replies: &mut replies,
+            };
+
+            if registration.handler.handle(&event, &mut context) == Propagation::Stop {
+                break;
+            }
+        }
+
+        replies
+    }
+
+    /// Returns a future that dispatches every message received from `transport` and sends the
+    /// messages queued by the handlers back to it. The future resolves when the stream ends.
+    pub fn run<T>(self, transport: IrcTransport<T>) -> Dispatch<T>
+    where
+        T: AsyncRead + AsyncWrite,
+    {
+        Dispatch {
+            dispatcher: self,
+            transport: transport,
+            outgoing: VecDeque::new(),
+        }
+    }
+}
+
+/// The future returned by `Dispatcher::run`.
+pub struct Dispatch<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    dispatcher: Dispatcher,
+    transport: IrcTransport<T>,
+    outgoing: VecDeque<Message>,
+}
+
+impl<T> Dispatch<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    // Sends as many of the queued replies as the transport will accept.
+    fn flush(&mut self) -> Result<()> {
+        while let Some(message) = self.outgoing.pop_front() {
+            if let AsyncSink::NotReady(message) = self.transport.start_send(message)? {
+                self.outgoing.push_front(message);
+                break;
+            }
+        }
+
+        self.transport.poll_complete()?;
+
+        Ok(())
+    }
+}
+
+impl<T> Future for Dispatch<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<(), Error> {
+        loop {
+            self.flush()?;
+
+            match self.transport.poll() {
+                Ok(Async::Ready(Some(message))) => {
+                    let replies = self.dispatcher.dispatch(&message, self.transport.state());
+                    self.outgoing.extend(replies);
+                }
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(())),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(ref error) if error.is_recoverable() => continue,
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kick_pairs_channels_and_nicks() {
+        let message = Message::try_from(":op KICK #a,#b one,two :bye".to_owned()).unwrap();
+
+        assert_eq!(
+            Event::new(&message),
+            Event::Kick { source: Some("op"), kicks: vec![("#a", "one"), ("#b", "two")], reason: Some("bye") }
+        );
+
+        let message = Message::try_from(":op KICK #a one,two".to_owned()).unwrap();
+
+        assert_eq!(
+            Event::new(&message),
+            Event::Kick { source: Some("op"), kicks: vec![("#a", "one"), ("#a", "two")], reason: None }
+        );
+    }
+
+    #[test]
+    fn dispatches_in_priority_order_with_the_given_state() {
+        let mut state = State::new();
+        let welcome = Message::try_from(":server 001 me :Welcome".to_owned()).unwrap();
+        state.handle(&welcome);
+
+        let mut dispatcher = Dispatcher::new();
+
+        dispatcher.register(&[EventKind::Message], 1, |_: &Event, context: &mut Context| {
+            context.send(Message::try_from("PRIVMSG #a :second".to_owned()).unwrap());
+            Propagation::Stop
+        });
+        dispatcher.register(&[], 2, |_: &Event, context: &mut Context| {
+            context.send(Message::try_from("PRIVMSG #a :never".to_owned()).unwrap());
+            Propagation::Continue
+        });
+        dispatcher.register(&[EventKind::Message], 0, |_: &Event, context: &mut Context| {
+            let nick = context.state().nick().unwrap_or("").to_owned();
+            context.reply(&format!("first {}", nick)).unwrap();
+            Propagation::Continue
+        });
+
+        let message = Message::try_from(":friend!f@host PRIVMSG #a :hi".to_owned()).unwrap();
+        let replies: Vec<String> = dispatcher.dispatch(&message, &state)
+            .iter()
+            .map(|message| message.raw_message().to_owned())
+            .collect();
+
+        assert_eq!(replies, vec!["PRIVMSG #a :first me", "PRIVMSG #a :second"]);
+
+        let message = Message::try_from(":friend!f@host NOTICE #a :hi".to_owned()).unwrap();
+        let replies: Vec<String> = dispatcher.dispatch(&message, &state)
+            .iter()
+            .map(|message| message.raw_message().to_owned())
+            .collect();
+
+        assert_eq!(replies, vec!["PRIVMSG #a :never"]);
+    }
+}
@@ -0,0 +1,489 @@
+//! The formatting module contains the parser for the mIRC formatting codes used to
+//! style the text of messages, such as `\x02` for bold and `\x03` for colours, along
+//! with functions to strip them and to render the styled text for terminals and HTML.
+
+use std::fmt::Write;
+
+const BOLD: char = '\x02';
+const COLOR: char = '\x03';
+const HEX_COLOR: char = '\x04';
+const RESET: char = '\x0F';
+const MONOSPACE: char = '\x11';
+const REVERSE: char = '\x16';
+const ITALIC: char = '\x1D';
+const STRIKETHROUGH: char = '\x1E';
+const UNDERLINE: char = '\x1F';
+
+// The colours of the 99 colour codes, from the modern IRC client protocol documentation.
+const PALETTE: [u32; 99] = [
+    0xffffff, 0x000000, 0x00007f, 0x009300, 0xff0000, 0x7f0000, 0x9c009c, 0xfc7f00,
+    0xffff00, 0x00fc00, 0x009393, 0x00ffff, 0x0000fc, 0xff00ff, 0x7f7f7f, 0xd2d2d2,
+    0x470000, 0x472100, 0x474700, 0x324700, 0x004700, 0x00472c, 0x004747, 0x002747,
+    0x000047, 0x2e0047, 0x470047, 0x47002a, 0x740000, 0x743a00, 0x747400, 0x517400,
+    0x007400, 0x007449, 0x007474, 0x004074, 0x000074, 0x4b0074, 0x740074, 0x740045,
+    0xb50000, 0xb56300, 0xb5b500, 0x7db500, 0x00b500, 0x00b571, 0x00b5b5, 0x0063b5,
+    0x0000b5, 0x7500b5, 0xb500b5, 0xb5006b, 0xff0000, 0xff8c00, 0xffff00, 0xb2ff00,
+    0x00ff00, 0x00ffa0, 0x00ffff, 0x008cff, 0x0000ff, 0xa500ff, 0xff00ff, 0xff0098,
+    0xff5959, 0xffb459, 0xffff71, 0xcfff60, 0x6fff6f, 0x65ffc9, 0x6dffff, 0x59b4ff,
+    0x5959ff, 0xc459ff, 0xff66ff, 0xff59bc, 0xff9c9c, 0xffd39c, 0xffff9c, 0xe2ff9c,
+    0x9cff9c, 0x9cffdb, 0x9cffff, 0x9cd3ff, 0x9c9cff, 0xdc9cff, 0xff9cff, 0xff94d3,
+    0x000000, 0x131313, 0x282828, 0x363636, 0x4d4d4d, 0x656565, 0x818181, 0x9f9f9f,
+    0xbcbcbc, 0xe2e2e2, 0xffffff,
+];
+
+/// A text colour, given either as one of the numbered mIRC colours or as an RGB value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    /// One of the colours numbered from 0 to 98.
+    Code(u8),
+    /// A colour given by its red, green and blue components, as sent with `\x04`.
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Get the red, green and blue components of the colour.
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        match *self {
+            Color::Code(code) => {
+                let value = PALETTE[(code as usize).min(PALETTE.len() - 1)];
+                ((value >> 16) as u8, (value >> 8) as u8, value as u8)
+            }
+            Color::Rgb(red, green, blue) => (red, green, blue),
+        }
+    }
+}
+
+/// The style of a span of text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    /// Whether the text is bold, toggled with `\x02`.
+    pub bold: bool,
+    /// Whether the text is italic, toggled with `\x1D`.
+    pub italic: bool,
+    /// Whether the text is underlined, toggled with `\x1F`.
+    pub underline: bool,
+    /// Whether the text is struck through, toggled with `\x1E`.
+    pub strikethrough: bool,
+    /// Whether the text is shown in a monospace font, toggled with `\x11`.
+    pub monospace: bool,
+    /// Whether the foreground and background colours are swapped.
+    pub reverse: bool,
+    /// The text colour, or `None` for the default colour.
+    pub foreground: Option<Color>,
+    /// The background colour, or `None` for the default colour.
+    pub background: Option<Color>,
+}
+
+impl Style {
+    /// Returns true if the style doesn't change how the text looks.
+    pub fn is_plain(&self) -> bool {
+        *self == Style::default()
+    }
+}
+
+/// A piece of text sharing a single style.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span<'a> {
+    /// The text, without any formatting codes.
+    pub text: &'a str,
+    /// The style of the text.
+    pub style: Style,
+}
+
+impl<'a> Span<'a> {
+    /// Create a new span of text with the given style.
+    pub fn new(text: &'a str, style: Style) -> Span<'a> {
+        Span {
+            text: text,
+            style: style,
+        }
+    }
+}
+
+/// Parses text containing formatting codes, such as the text of a PRIVMSG, into styled spans.
+/// Spans without any text are left out.
+pub fn parse(text: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut style = Style::default();
+    let mut span_style = style;
+    let mut start = 0;
+    let mut index = 0;
+
+    while let Some(c) = text[index..].chars().next() {
+        let code_length = match c {
+            BOLD => toggle(&mut style.bold),
+            ITALIC => toggle(&mut style.italic),
+            UNDERLINE => toggle(&mut style.underline),
+            STRIKETHROUGH => toggle(&mut style.strikethrough),
+            MONOSPACE => toggle(&mut style.monospace),
+            REVERSE => toggle(&mut style.reverse),
+            RESET => {
+                style = Style::default();
+                1
+            }
+            COLOR => 1 + parse_color(&text[index + 1..], &mut style, 2, |digits| {
+                digits.parse().ok().filter(|&code| code < 99).map(Color::Code)
+            }),
+            HEX_COLOR => 1 + parse_color(&text[index + 1..], &mut style, 6, |digits| {
+                u32::from_str_radix(digits, 16)
+                    .ok()
+                    .map(|value| Color::Rgb((value >> 16) as u8, (value >> 8) as u8, value as u8))
+            }),
+            c => {
+                index += c.len_utf8();
+                continue;
+            }
+        };
+
+        if start < index {
+            spans.push(Span::new(&text[start..index], span_style));
+        }
+
+        index += code_length;
+        start = index;
+        span_style = style;
+    }
+
+    if start < text.len() {
+        spans.push(Span::new(&text[start..], span_style));
+    }
+
+    spans
+}
+
+fn toggle(flag: &mut bool) -> usize {
+    *flag = !*flag;
+    1
+}
+
+// Parses the `fg[,bg]` part of a colour code, where each colour has up to `max_digits` digits.
+// A colour code without a colour resets both colours. Returns the length of what was parsed.
+fn parse_color<F>(text: &str, style: &mut Style, max_digits: usize, parse: F) -> usize
+where
+    F: Fn(&str) -> Option<Color>,
+{
+    let is_digit = |c: char| if max_digits == 2 { c.is_ascii_digit() } else { c.is_ascii_hexdigit() };
+    let digits = |text: &str| {
+        let length = text.chars().take(max_digits).take_while(|&c| is_digit(c)).count();
+
+        // NOTE: Hex colours must always have all six digits.
+        if max_digits == 6 && length < 6 { 0 } else { length }
+    };
+
+    let foreground_length = digits(text);
+
+    if foreground_length == 0 {
+        style.foreground = None;
+        style.background = None;
+        return 0;
+    }
+
+    style.foreground = parse(&text[..foreground_length]);
+
+    let rest = &text[foreground_length..];
+
+    if rest.starts_with(',') {
+        let background_length = digits(&rest[1..]);
+
+        if background_length > 0 {
+            style.background = parse(&rest[1..1 + background_length]);
+            return foreground_length + 1 + background_length;
+        }
+    }
+
+    foreground_length
+}
+
+/// Removes every formatting code from the text.
+pub fn strip(text: &str) -> String {
+    parse(text).into_iter().map(|span| span.text).collect()
+}
+
+/// Renders styled spans as text for a terminal, using ANSI escape codes with 24-bit colours.
+pub fn to_ansi(spans: &[Span]) -> String {
+    let mut output = String::new();
+
+    for span in spans {
+        let style = &span.style;
+
+        if style.is_plain() {
+            output.push_str(span.text);
+            continue;
+        }
+
+        let mut codes = Vec::new();
+
+        if style.bold { codes.push("1".to_owned()); }
+        if style.italic { codes.push("3".to_owned()); }
+        if style.underline { codes.push("4".to_owned()); }
+        if style.reverse { codes.push("7".to_owned()); }
+        if style.strikethrough { codes.push("9".to_owned()); }
+
+        if let Some(color) = style.foreground {
+            let (red, green, blue) = color.rgb();
+            codes.push(format!("38;2;{};{};{}", red, green, blue));
+        }
+
+        if let Some(color) = style.background {
+            let (red, green, blue) = color.rgb();
+            codes.push(format!("48;2;{};{};{}", red, green, blue));
+        }
+
+        if codes.is_empty() {
+            output.push_str(span.text);
+        } else {
+            let _ = write!(output, "\x1b[{}m{}\x1b[0m", codes.join(";"), span.text);
+        }
+    }
+
+    output
+}
+
+/// Renders styled spans as HTML, escaping the text and wrapping styled spans in `<span>` elements.
+pub fn to_html(spans: &[Span]) -> String {
+    let mut output = String::new();
+
+    for span in spans {
+        let style = &span.style;
+        let mut css = String::new();
+
+        if style.bold { css.push_str("font-weight:bold;"); }
+        if style.italic { css.push_str("font-style:italic;"); }
+        if style.monospace { css.push_str("font-family:monospace;"); }
+
+        match (style.underline, style.strikethrough) {
+            (true, true) => css.push_str("text-decoration:underline line-through;"),
+            (true, false) => css.push_str("text-decoration:underline;"),
+            (false, true) => css.push_str("text-decoration:line-through;"),
+            (false, false) => {}
+        }
+
+        let (foreground, background) = if style.reverse {
+            (Some(style.background.unwrap_or(Color::Code(0))), Some(style.foreground.unwrap_or(Color::Code(1))))
+        } else {
+            (style.foreground, style.background)
+        };
+
+        if let Some(color) = foreground {
+            let (red, green, blue) = color.rgb();
+            let _ = write!(css, "color:#{:02x}{:02x}{:02x};", red, green, blue);
+        }
+
+        if let Some(color) = background {
+            let (red, green, blue) = color.rgb();
+            let _ = write!(css, "background-color:#{:02x}{:02x}{:02x};", red, green, blue);
+        }
+
+        if css.is_empty() {
+            escape_html(span.text, &mut output);
+        } else {
+            let _ = write!(output, "<span style=\"{}\">", css);
+            escape_html(span.text, &mut output);
+            output.push_str("</span>");
+        }
+    }
+
+    output
+}
+
+fn escape_html(text: &str, output: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            c => output.push(c),
+        }
+    }
+}
+
+/// Builds text containing the formatting codes that give each span its style, to be sent
+/// as the text of a PRIVMSG or NOTICE.
+pub fn to_irc(spans: &[Span]) -> String {
+    let mut output = String::new();
+    let mut current = Style::default();
+
+    for span in spans.iter().filter(|span| !span.text.is_empty()) {
+        let style = span.style;
+
+        if style != current {
+            if !current.is_plain() {
+                output.push(RESET);
+            }
+
+            if style.bold { output.push(BOLD); }
+            if style.italic { output.push(ITALIC); }
+            if style.underline { output.push(UNDERLINE); }
+            if style.strikethrough { output.push(STRIKETHROUGH); }
+            if style.monospace { output.push(MONOSPACE); }
+            if style.reverse { output.push(REVERSE); }
+
+            if style.foreground.is_some() || style.background.is_some() {
+                push_colors(&mut output, &style, span.text);
+            }
+
+            current = style;
+        }
+
+        output.push_str(span.text);
+    }
+
+    output
+}
+
+// Writes the colour code for a style. Numbered colours are always written with two digits,
+// so that text starting with a digit isn't read as part of the code, and a comma starting the
+// text is kept apart from the code by an empty pair of bold codes.
+fn push_colors(output: &mut String, style: &Style, text: &str) {
+    let hex = matches!((style.foreground, style.background), (Some(Color::Rgb(..)), _) | (_, Some(Color::Rgb(..))));
+
+    if hex {
+        // NOTE: Hex colour codes can't leave the foreground at its default, so black is used.
+        let (red, green, blue) = style.foreground.map(|color| color.rgb()).unwrap_or((0, 0, 0));
+        let _ = write!(output, "{}{:02X}{:02X}{:02X}", HEX_COLOR, red, green, blue);
+
+        if let Some(background) = style.background {
+            let (red, green, blue) = background.rgb();
+            let _ = write!(output, ",{:02X}{:02X}{:02X}", red, green, blue);
+        }
+    } else {
+        let code = |color: Option<Color>| match color {
+            Some(Color::Code(code)) => code,
+            _ => 99,
+        };
+
+        let _ = write!(output, "{}{:02}", COLOR, code(style.foreground));
+
+        if style.background.is_some() {
+            let _ = write!(output, ",{:02}", code(style.background));
+        }
+    }
+
+    if style.background.is_none() && text.starts_with(',') {
+        output.push(BOLD);
+        output.push(BOLD);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bold() -> Style {
+        Style { bold: true, ..Style::default() }
+    }
+
+    fn colored(foreground: Option<Color>, background: Option<Color>) -> Style {
+        Style { foreground: foreground, background: background, ..Style::default() }
+    }
+
+    #[test]
+    fn parses_toggles() {
+        assert_eq!(parse("a\x02b\x02c"), vec![
+            Span::new("a", Style::default()),
+            Span::new("b", bold()),
+            Span::new("c", Style::default()),
+        ]);
+
+        let all = Style {
+            bold: true,
+            italic: true,
+            underline: true,
+            strikethrough: true,
+            monospace: true,
+            reverse: true,
+            ..Style::default()
+        };
+
+        assert_eq!(parse("\x02\x1D\x1F\x1E\x11\x16x\x0Fy"), vec![
+            Span::new("x", all),
+            Span::new("y", Style::default()),
+        ]);
+    }
+
+    #[test]
+    fn leaves_out_empty_spans() {
+        assert_eq!(parse(""), vec![]);
+        assert_eq!(parse("\x02\x02"), vec![]);
+        assert_eq!(parse("\x02\x1Dé"), vec![Span::new("é", Style { italic: true, ..bold() })]);
+    }
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(parse("\x034red"), vec![Span::new("red", colored(Some(Color::Code(4)), None))]);
+        assert_eq!(parse("\x0304,12x"), vec![Span::new("x", colored(Some(Color::Code(4)), Some(Color::Code(12))))]);
+        assert_eq!(parse("\x03123"), vec![Span::new("3", colored(Some(Color::Code(12)), None))]);
+        assert_eq!(parse("\x034,x"), vec![Span::new(",x", colored(Some(Color::Code(4)), None))]);
+        assert_eq!(parse("\x0399x"), vec![Span::new("x", Style::default())]);
+        assert_eq!(parse("\x034,2a\x03b"), vec![
+            Span::new("a", colored(Some(Color::Code(4)), Some(Color::Code(2)))),
+            Span::new("b", Style::default()),
+        ]);
+    }
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(parse("\x04FF8000x"), vec![Span::new("x", colored(Some(Color::Rgb(255, 128, 0)), None))]);
+        assert_eq!(parse("\x04000000,ffffffx"), vec![
+            Span::new("x", colored(Some(Color::Rgb(0, 0, 0)), Some(Color::Rgb(255, 255, 255)))),
+        ]);
+        assert_eq!(parse("\x04FF80x"), vec![Span::new("FF80x", Style::default())]);
+    }
+
+    #[test]
+    fn strips_codes() {
+        assert_eq!(strip("\x02bold\x02 \x034,5red\x03 \x04FFFFFFwhite\x0F."), "bold red white.");
+    }
+
+    #[test]
+    fn looks_up_colors() {
+        assert_eq!(Color::Code(4).rgb(), (255, 0, 0));
+        assert_eq!(Color::Code(98).rgb(), (255, 255, 255));
+        assert_eq!(Color::Rgb(1, 2, 3).rgb(), (1, 2, 3));
+    }
+
+    #[test]
+    fn renders_ansi() {
+        assert_eq!(to_ansi(&parse("a\x02b\x02")), "a\x1b[1mb\x1b[0m");
+        assert_eq!(to_ansi(&parse("\x1D\x034,1c")), "\x1b[3;38;2;255;0;0;48;2;0;0;0mc\x1b[0m");
+        assert_eq!(to_ansi(&parse("\x11mono")), "mono");
+    }
+
+    #[test]
+    fn renders_html() {
+        assert_eq!(to_html(&parse("<a & 'b'>")), "&lt;a &amp; &#39;b&#39;&gt;");
+        assert_eq!(
+            to_html(&parse("\x02\x1F\x1Ex\x0F \x034y")),
+            "<span style=\"font-weight:bold;text-decoration:underline line-through;\">x</span> \
+             <span style=\"color:#ff0000;\">y</span>"
+        );
+        assert_eq!(
+            to_html(&parse("\x16z")),
+            "<span style=\"color:#ffffff;background-color:#000000;\">z</span>"
+        );
+    }
+
+    #[test]
+    fn renders_irc() {
+        assert_eq!(to_irc(&[Span::new("a", bold()), Span::new("b", Style::default())]), "\x02a\x0Fb");
+        assert_eq!(to_irc(&[Span::new("1", colored(Some(Color::Code(4)), None))]), "\x03041");
+        assert_eq!(to_irc(&[Span::new(",", colored(Some(Color::Code(4)), None))]), "\x0304\x02\x02,");
+        assert_eq!(to_irc(&[Span::new("x", colored(None, Some(Color::Code(2))))]), "\x0399,02x");
+        assert_eq!(to_irc(&[Span::new("x", colored(Some(Color::Rgb(1, 2, 3)), None))]), "\x04010203x");
+    }
+
+    #[test]
+    fn round_trips_through_irc() {
+        let spans = vec![
+            Span::new("plain ", Style::default()),
+            Span::new("12", colored(Some(Color::Code(4)), Some(Color::Code(12)))),
+            Span::new(",3", colored(Some(Color::Code(3)), None)),
+            Span::new("hex", colored(Some(Color::Rgb(16, 32, 48)), Some(Color::Rgb(1, 2, 3)))),
+            Span::new("styled", Style { italic: true, underline: true, reverse: true, ..bold() }),
+        ];
+
+        assert_eq!(parse(&to_irc(&spans)), spans);
+    }
+}
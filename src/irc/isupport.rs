@@ -0,0 +1,395 @@
+//! The isupport module contains the `ISupport` type which keeps track of the
+//! features a server advertises with the `RPL_ISUPPORT` (005) numeric.
+
+use std::collections::HashMap;
+
+use irc::message::Message;
+
+/// The rules a server uses to compare nicknames and channel names without
+/// regard to case, as advertised by the `CASEMAPPING` token.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CaseMapping {
+    /// Only the ASCII letters are case insensitive.
+    Ascii,
+    /// The ASCII letters along with `{}|^`, which are the lower case forms of `[]\~`.
+    Rfc1459,
+    /// The ASCII letters along with `{}|`, which are the lower case forms of `[]\`.
+    StrictRfc1459,
+    /// The Unicode case mapping of RFC 7613. This library only approximates it, see `casemap::casefold`.
+    Rfc7613,
+    /// A case mapping this library doesn't know about.
+    Other(String),
+}
+
+impl CaseMapping {
+    /// Get the case mapping with the given name.
+    pub fn from_name(name: &str) -> CaseMapping {
+        match name {
+            "ascii" => CaseMapping::Ascii,
+            "rfc1459" => CaseMapping::Rfc1459,
+            "strict-rfc1459" => CaseMapping::StrictRfc1459,
+            "rfc7613" => CaseMapping::Rfc7613,
+            name => CaseMapping::Other(name.to_owned()),
+        }
+    }
+}
+
+/// The channel modes a server supports, grouped by how they take arguments,
+/// as advertised by the `CHANMODES` token.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChanModes {
+    /// Modes that add or remove an address from a list, which always take an argument.
+    pub list: String,
+    /// Modes that change a setting and always take an argument.
+    pub always: String,
+    /// Modes that change a setting and only take an argument when being set.
+    pub when_set: String,
+    /// Modes that change a setting and never take an argument.
+    pub never: String,
+}
+
+/// The features supported by the server, accumulated from every `RPL_ISUPPORT`
+/// reply received on the connection.
+///
+/// Tokens that the server hasn't advertised fall back to the defaults given
+/// in the ISUPPORT specification, or to `None` where there is no default.
+#[derive(Clone, Debug, Default)]
+pub struct ISupport {
+    tokens: HashMap<String, Option<String>>,
+}
+
+impl ISupport {
+    /// Create a new `ISupport` without any advertised tokens.
+    pub fn new() -> ISupport {
+        ISupport::default()
+    }
+
+    /// Applies the tokens of an `RPL_ISUPPORT` reply. Returns false if the
+    /// message is any other message.
+    pub fn handle(&mut self, message: &Message) -> bool {
+        if message.raw_command() != "005" {
+            return false;
+        }
+
+        let arguments: Vec<&str> = message.raw_args().collect();
+
+        // NOTE: The first argument is our nickname and the last is a human readable message.
+        if arguments.len() > 2 {
+            for token in &arguments[1..arguments.len() - 1] {
+                self.apply(token);
+            }
+        }
+
+        true
+    }
+
+    /// Applies a single token, such as `NICKLEN=30` or `-EXCEPTS`.
+    pub fn apply(&mut self, token: &str) {
+        if let Some(name) = token.strip_prefix('-') {
+            self.tokens.remove(name);
+            return;
+        }
+
+        match token.find('=') {
+            Some(index) => {
+                let value = unescape(&token[index + 1..]);
+                let value = if value.is_empty() { None } else { Some(value) };
+
+                self.tokens.insert(token[..index].to_owned(), value);
+            }
+            None => {
+                self.tokens.insert(token.to_owned(), None);
+            }
+        }
+    }
+
+    /// Returns true if the server advertised the given token.
+    pub fn contains(&self, name: &str) -> bool {
+        self.tokens.contains_key(name)
+    }
+
+    /// Get the value of the given token, if it was advertised with one.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.tokens
+            .get(name)
+            .and_then(|value| value.as_ref())
+            .map(|value| value.as_str())
+    }
+
+    /// Get the channel membership modes and their prefixes in order of rank, such as
+    /// `('o', '@')` and `('v', '+')`, from the `PREFIX` token.
+    pub fn prefix(&self) -> Vec<(char, char)> {
+        if !self.contains("PREFIX") {
+            return vec![('o', '@'), ('v', '+')];
+        }
+
+        let value = self.get("PREFIX").unwrap_or("");
+
+        match (value.find('('), value.find(')')) {
+            (Some(0), Some(end)) => value[1..end].chars().zip(value[end + 1..].chars()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Get the channel modes grouped by how they take arguments, from the `CHANMODES` token.
+    pub fn chanmodes(&self) -> ChanModes {
+        let value = self.get("CHANMODES").unwrap_or("b,k,l,imnpst");
+        let mut groups = value.split(',').map(|group| group.to_owned());
+
+        ChanModes {
+            list: groups.next().unwrap_or_default(),
+            always: groups.next().unwrap_or_default(),
+            when_set: groups.next().unwrap_or_default(),
+            never: groups.next().unwrap_or_default(),
+        }
+    }
+
+    /// Get the characters that may start a channel name, from the `CHANTYPES` token.
+    pub fn chantypes(&self) -> &str {
+        if self.contains("CHANTYPES") {
+            self.get("CHANTYPES").unwrap_or("")
+        } else {
+            "#&"
+        }
+    }
+
+    /// Returns true if `target` is a channel name according to the `CHANTYPES` token.
+    pub fn is_channel(&self, target: &str) -> bool {
+        target
+            .chars()
+            .next()
+            .map(|c| self.chantypes().contains(c))
+            .unwrap_or(false)
+    }
+
+    /// Get the case mapping used by the server, from the `CASEMAPPING` token.
+    pub fn casemapping(&self) -> CaseMapping {
+        CaseMapping::from_name(self.get("CASEMAPPING").unwrap_or("rfc1459"))
+    }
+
+    /// Get the longest nickname allowed, from the `NICKLEN` token.
+    pub fn nicklen(&self) -> Option<usize> {
+        self.number("NICKLEN")
+    }
+
+    /// Get the longest channel name allowed, from the `CHANNELLEN` token.
+    pub fn channellen(&self) -> Option<usize> {
+        self.number("CHANNELLEN")
+    }
+
+    /// Get the longest topic allowed, from the `TOPICLEN` token.
+    pub fn topiclen(&self) -> Option<usize> {
+        self.number("TOPICLEN")
+    }
+
+    /// Get the number of targets allowed in a PRIVMSG or NOTICE, from the `MAXTARGETS` token.
+    pub fn maxtargets(&self) -> Option<usize> {
+        self.number("MAXTARGETS")
+    }
+
+    /// Get the commands that accept several targets along with their limits, from the
+    /// `TARGMAX` token. A limit of `None` means there is no limit.
+    pub fn targmax(&self) -> Vec<(&str, Option<usize>)> {
+        self.get("TARGMAX")
+            .map(|value| {
+                value
+                    .split(',')
+                    .filter_map(|entry| {
+                        let mut parts = entry.splitn(2, ':');
+
+                        match (parts.next(), parts.next()) {
+                            (Some(command), Some(limit)) => Some((command, limit.parse().ok())),
+                            _ => None,
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Get the number of targets allowed for `command`, from the `TARGMAX` token or
+    /// the `MAXTARGETS` token. Returns `None` when there is no limit or none is known.
+    pub fn max_targets(&self, command: &str) -> Option<usize> {
+        if let Some(&(_, limit)) = self.targmax().iter().find(|&&(name, _)| name.eq_ignore_ascii_case(command)) {
+            return limit;
+        }
+
+        match command {
+            "PRIVMSG" | "NOTICE" => self.maxtargets(),
+            _ => None,
+        }
+    }
+
+    /// Get the number of modes with an argument allowed in a single MODE command, from the
+    /// `MODES` token. Returns `None` when there is no limit.
+    pub fn modes(&self) -> Option<usize> {
+        if self.contains("MODES") {
+            self.number("MODES")
+        } else {
+            Some(3)
+        }
+    }
+
+    /// Returns true if the server supports the MONITOR command.
+    pub fn supports_monitor(&self) -> bool {
+        self.contains("MONITOR")
+    }
+
+    /// Get the number of nicknames that may be monitored, from the `MONITOR` token.
+    pub fn monitor_limit(&self) -> Option<usize> {
+        self.number("MONITOR")
+    }
+
+    /// Get the name of the network, from the `NETWORK` token.
+    pub fn network(&self) -> Option<&str> {
+        self.get("NETWORK")
+    }
+
+    /// Get the longest line the server accepts, including the CR-LF, from the `LINELEN` token.
+    pub fn linelen(&self) -> usize {
+        self.number("LINELEN").unwrap_or(512)
+    }
+
+    fn number(&self, name: &str) -> Option<usize> {
+        self.get(name).and_then(|value| value.parse().ok())
+    }
+}
+
+// Replaces the `\xHH` escapes allowed in token values with the bytes they represent.
+fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] == b'\\' && index + 3 < bytes.len() && bytes[index + 1] == b'x' {
+            let digits = &bytes[index + 2..index + 4];
+
+            // NOTE: `from_str_radix` accepts a sign, so the digits are checked first.
+            let escaped = if digits.iter().all(|b| b.is_ascii_hexdigit()) {
+                ::std::str::from_utf8(digits).ok().and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            } else {
+                None
+            };
+
+            if let Some(byte) = escaped {
+                unescaped.push(byte);
+                index += 4;
+                continue;
+            }
+        }
+
+        unescaped.push(bytes[index]);
+        index += 1;
+    }
+
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn isupport(lines: &[&str]) -> ISupport {
+        let mut isupport = ISupport::new();
+
+        for line in lines {
+            assert!(isupport.handle(&Message::try_from(line.to_string()).unwrap()));
+        }
+
+        isupport
+    }
+
+    #[test]
+    fn uses_defaults_without_tokens() {
+        let isupport = ISupport::new();
+
+        assert_eq!(isupport.prefix(), vec![('o', '@'), ('v', '+')]);
+        assert_eq!(isupport.chanmodes().list, "b");
+        assert_eq!(isupport.chanmodes().never, "imnpst");
+        assert_eq!(isupport.chantypes(), "#&");
+        assert_eq!(isupport.casemapping(), CaseMapping::Rfc1459);
+        assert_eq!(isupport.modes(), Some(3));
+        assert_eq!(isupport.linelen(), 512);
+        assert_eq!(isupport.nicklen(), None);
+        assert!(!isupport.supports_monitor());
+    }
+
+    #[test]
+    fn parses_tokens() {
+        let isupport = isupport(&[
+            ":irc.test 005 nick PREFIX=(qaohv)~&@%+ CHANMODES=beI,k,l,imnpst CHANTYPES=# :are supported",
+            ":irc.test 005 nick CASEMAPPING=ascii NICKLEN=30 MODES MONITOR=100 NETWORK=Test\\x20Net :are supported",
+        ]);
+
+        assert_eq!(isupport.prefix(), vec![('q', '~'), ('a', '&'), ('o', '@'), ('h', '%'), ('v', '+')]);
+        assert_eq!(
+            isupport.chanmodes(),
+            ChanModes {
+                list: "beI".to_owned(),
+                always: "k".to_owned(),
+                when_set: "l".to_owned(),
+                never: "imnpst".to_owned(),
+            }
+        );
+        assert!(isupport.is_channel("#rust"));
+        assert!(!isupport.is_channel("&rust"));
+        assert!(!isupport.is_channel(""));
+        assert_eq!(isupport.casemapping(), CaseMapping::Ascii);
+        assert_eq!(isupport.nicklen(), Some(30));
+        assert_eq!(isupport.modes(), None);
+        assert_eq!(isupport.monitor_limit(), Some(100));
+        assert_eq!(isupport.network(), Some("Test Net"));
+    }
+
+    #[test]
+    fn ignores_the_nickname_and_message() {
+        let mut isupport = isupport(&[":irc.test 005 NICKLEN=1 :MODES=1"]);
+
+        assert!(!isupport.contains("NICKLEN"));
+        assert!(!isupport.contains("MODES=1"));
+        assert!(!isupport.handle(&Message::try_from(":irc.test 001 nick :Welcome".to_owned()).unwrap()));
+    }
+
+    #[test]
+    fn removes_negated_tokens() {
+        let isupport = isupport(&[
+            ":irc.test 005 nick EXCEPTS NICKLEN=30 :are supported",
+            ":irc.test 005 nick -EXCEPTS NICKLEN= :are supported",
+        ]);
+
+        assert!(!isupport.contains("EXCEPTS"));
+        assert!(isupport.contains("NICKLEN"));
+        assert_eq!(isupport.get("NICKLEN"), None);
+    }
+
+    #[test]
+    fn parses_target_limits() {
+        let isupport = isupport(&[":irc.test 005 nick TARGMAX=PRIVMSG:4,JOIN:,KICK:1 MAXTARGETS=2 :are supported"]);
+
+        assert_eq!(isupport.targmax(), vec![("PRIVMSG", Some(4)), ("JOIN", None), ("KICK", Some(1))]);
+        assert_eq!(isupport.max_targets("privmsg"), Some(4));
+        assert_eq!(isupport.max_targets("JOIN"), None);
+        assert_eq!(isupport.max_targets("NOTICE"), Some(2));
+        assert_eq!(isupport.max_targets("WHOIS"), None);
+    }
+
+    #[test]
+    fn unescapes_values() {
+        assert_eq!(unescape("a\\x20b\\x3Dc"), "a b=c");
+        assert_eq!(unescape("\\x5Cx20"), "\\x20");
+        assert_eq!(unescape("\\x2"), "\\x2");
+        assert_eq!(unescape("\\xZZ"), "\\xZZ");
+        assert_eq!(unescape("\\x+F"), "\\x+F");
+        assert_eq!(unescape("\\x-1"), "\\x-1");
+    }
+
+    #[test]
+    fn names_case_mappings() {
+        assert_eq!(CaseMapping::from_name("ascii"), CaseMapping::Ascii);
+        assert_eq!(CaseMapping::from_name("rfc1459"), CaseMapping::Rfc1459);
+        assert_eq!(CaseMapping::from_name("strict-rfc1459"), CaseMapping::StrictRfc1459);
+        assert_eq!(CaseMapping::from_name("rfc7613"), CaseMapping::Rfc7613);
+        assert_eq!(CaseMapping::from_name("other"), CaseMapping::Other("other".to_owned()));
+    }
+}
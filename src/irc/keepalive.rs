@@ -0,0 +1,202 @@
+//! The keepalive module contains the `Keepalive` type which detects dead
+//! connections by pinging the server when the connection goes quiet.
+
+extern crate tokio_core;
+
+use std::time::{Duration, Instant};
+
+use futures::{Async, Future};
+use tokio_core::reactor::{Handle, Timeout};
+
+use irc::error::{ErrorKind, Result};
+use irc::message::Message;
+
+/// Keeps track of the traffic received from the server and decides when to
+/// send our own PING.
+///
+/// Once no message has been received for `interval`, a PING carrying a unique
+/// token is sent. The connection is considered dead when the matching PONG,
+/// or any other message, hasn't arrived within `timeout`. The round-trip time
+/// of the most recent PING is available as the lag of the connection.
+pub struct Keepalive {
+    timer: Timeout,
+    interval: Duration,
+    timeout: Duration,
+    outstanding: Option<(String, Instant)>,
+    waiting: bool,
+    lag: Option<Duration>,
+    sent: u64,
+}
+
+impl Keepalive {
+    /// Create a new `Keepalive` that pings the server after `interval` of
+    /// silence and gives up after a further `timeout`.
+    pub fn new(handle: &Handle, interval: Duration, timeout: Duration) -> Result<Keepalive> {
+        Ok(Keepalive {
+            timer: Timeout::new(interval, handle)?,
+            interval: interval,
+            timeout: timeout,
+            outstanding: None,
+            waiting: false,
+            lag: None,
+            sent: 0,
+        })
+    }
+
+    /// Get the round-trip time of the most recently answered PING.
+    pub fn lag(&self) -> Option<Duration> {
+        self.lag
+    }
+
+    /// Checks the timer, returning a PING message to send when the connection
+    /// has been idle, or an error when the server failed to respond in time.
+    pub fn poll(&mut self) -> Result<Option<Message>> {
+        if let Async::NotReady = self.timer.poll()? {
+            return Ok(None);
+        }
+
+        if self.waiting {
+            return Err(ErrorKind::PingTimeout(self.timeout).into());
+        }
+
+        self.sent += 1;
+
+        let token = format!("keepalive-{}", self.sent);
+        let now = Instant::now();
+        let ping = Message::ping(&token)?;
+
+        self.outstanding = Some((token, now));
+        self.waiting = true;
+        self.timer.reset(now + self.timeout);
+
+        // NOTE: The timer has to be polled again for the task to be woken when it fires.
+        self.timer.poll()?;
+
+        Ok(Some(ping))
+    }
+
+    /// Records a message received from the server. Returns true if the message
+    /// is the PONG answering our own PING, which shouldn't be passed on.
+    pub fn received(&mut self, message: &Message) -> bool {
+        let answered = match self.outstanding {
+            Some((ref token, sent)) if message.raw_command() == "PONG" => {
+                if message.raw_args().next_back() == Some(token.as_str()) {
+                    Some(sent.elapsed())
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        if let Some(lag) = answered {
+            self.lag = Some(lag);
+            self.outstanding = None;
+        }
+
+        // Any traffic shows the connection is alive, so the outstanding PING no longer
+        // has to be answered in time. Its PONG is still used to measure the lag.
+        self.waiting = false;
+        self.timer.reset(Instant::now() + self.interval);
+
+        answered.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+    use tokio_core::reactor::Core;
+
+    use irc::error::Error;
+
+    use super::*;
+
+    // Polls the keepalive from within a task, as the timer requires.
+    fn poll(core: &mut Core, keepalive: &mut Keepalive) -> Result<Option<Message>> {
+        core.run(future::lazy(|| Ok::<_, ()>(keepalive.poll()))).unwrap()
+    }
+
+    fn sleep(core: &mut Core, milliseconds: u64) {
+        let timeout = Timeout::new(Duration::from_millis(milliseconds), &core.handle()).unwrap();
+        core.run(timeout).unwrap();
+    }
+
+    fn message(line: &str) -> Message {
+        Message::try_from(line.to_owned()).unwrap()
+    }
+
+    #[test]
+    fn pings_when_idle() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let mut keepalive = Keepalive::new(&handle, Duration::from_millis(20), Duration::from_secs(10)).unwrap();
+
+        assert!(poll(&mut core, &mut keepalive).unwrap().is_none());
+
+        sleep(&mut core, 30);
+        let ping = poll(&mut core, &mut keepalive).unwrap().unwrap();
+        assert_eq!(ping.raw_message(), "PING keepalive-1");
+
+        assert!(keepalive.received(&message(":server PONG server keepalive-1")));
+
+        sleep(&mut core, 30);
+        let ping = poll(&mut core, &mut keepalive).unwrap().unwrap();
+        assert_eq!(ping.raw_message(), "PING keepalive-2");
+    }
+
+    #[test]
+    fn measures_lag_from_the_matching_pong() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let mut keepalive = Keepalive::new(&handle, Duration::from_millis(10), Duration::from_secs(10)).unwrap();
+
+        sleep(&mut core, 20);
+        assert!(poll(&mut core, &mut keepalive).unwrap().is_some());
+        sleep(&mut core, 20);
+
+        assert!(!keepalive.received(&message(":server PONG server :keepalive-7")));
+        assert!(!keepalive.received(&message(":server PRIVMSG me :keepalive-1")));
+        assert_eq!(keepalive.lag(), None);
+
+        assert!(keepalive.received(&message(":server PONG server :keepalive-1")));
+        assert!(keepalive.lag().unwrap() >= Duration::from_millis(20));
+
+        // The PING has been answered, so a repeated PONG is passed on.
+        assert!(!keepalive.received(&message(":server PONG server :keepalive-1")));
+    }
+
+    #[test]
+    fn times_out_without_a_reply() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let mut keepalive = Keepalive::new(&handle, Duration::from_millis(10), Duration::from_millis(20)).unwrap();
+
+        sleep(&mut core, 15);
+        assert!(poll(&mut core, &mut keepalive).unwrap().is_some());
+        assert!(poll(&mut core, &mut keepalive).unwrap().is_none());
+
+        sleep(&mut core, 30);
+
+        match poll(&mut core, &mut keepalive) {
+            Err(Error(ErrorKind::PingTimeout(timeout), _)) => assert_eq!(timeout, Duration::from_millis(20)),
+            result => panic!("expected a ping timeout, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn any_traffic_postpones_the_timeout() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let mut keepalive = Keepalive::new(&handle, Duration::from_millis(10), Duration::from_millis(20)).unwrap();
+
+        sleep(&mut core, 15);
+        assert!(poll(&mut core, &mut keepalive).unwrap().is_some());
+
+        keepalive.received(&message(":friend!f@host PRIVMSG me :hello"));
+        sleep(&mut core, 15);
+
+        let ping = poll(&mut core, &mut keepalive).unwrap().unwrap();
+        assert_eq!(ping.raw_message(), "PING keepalive-2");
+    }
+}
@@ -5,11 +5,22 @@
 //! messages to be sent to the server.
 
 
+use irc::builder::MessageBuilder;
 use irc::command::{Command, ArgumentIter};
-use irc::error::{Result, Error};
+use irc::ctcp;
+use irc::error::{Result, Error, ErrorKind};
 use std::ops::Range;
 
+use irc::numeric::{self, Numeric};
 use irc::parser;
+use irc::tag::{Tag, TagIter};
+
+// The longest message allowed, excluding tags and the trailing CR-LF.
+const MAX_MESSAGE_LENGTH: usize = 510;
+
+// The prefix length assumed when our own prefix isn't known, which allows for a
+// long nickname, username and host name.
+const DEFAULT_PREFIX_LENGTH: usize = 100;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct PrefixRange {
@@ -40,6 +51,13 @@ impl Message {
         Ok(result)
     }
 
+    // This is intended for internal usage and thus hidden.
+    // Lines received from the server are not limited to 512 bytes once decoded.
+    #[doc(hidden)]
+    pub fn from_received(value: String) -> Result<Message> {
+        parser::parse_received(value)
+    }
+
     /// A strongly typed interface for determining the type of the command
     /// and retrieving the values of the command.
     pub fn command<'a, T>(&'a self) -> Option<T>
@@ -49,6 +67,32 @@ impl Message {
         <T as Command>::try_match(self.raw_command(), self.raw_args())
     }
 
+    /// Get the code of this message if its command is a numeric reply.
+    pub fn numeric_code(&self) -> Option<u16> {
+        numeric::parse_code(self.raw_command())
+    }
+
+    /// Get the numeric reply this message contains, if its command is a known numeric.
+    pub fn numeric(&self) -> Option<Numeric> {
+        self.numeric_code().and_then(Numeric::from_code)
+    }
+
+    /// Get an iterator over the tags of this message, with their values unescaped.
+    pub fn tags(&self) -> TagIter {
+        match self.tags {
+            Some(ref tags) => TagIter::new(&self.message, tags),
+            None => TagIter::new(&self.message, &[]),
+        }
+    }
+
+    /// A strongly typed interface for retrieving the value of a tag.
+    pub fn tag<'a, T>(&'a self) -> Option<T>
+    where
+        T: Tag<'a>,
+    {
+        <T as Tag>::try_match(self.tags())
+    }
+
     /// Retrieves the prefix for this message, if there is one.  If there is either
     /// a user or host associated with the prefix, it will also return those.
     pub fn prefix(&self) -> Option<(&str, Option<&str>, Option<&str>)> {
@@ -81,39 +125,167 @@ impl Message {
         &self.message
     }
     
+    /// Constructs a message containing a PING command carrying the specified token.
+    pub fn ping(token: &str) -> Result<Message> {
+        MessageBuilder::new("PING").param(token).build()
+    }
+
     /// Constructs a message containing a PONG command targeting the specified host.
     pub fn pong(host: &str) -> Result<Message> {
-        Message::try_from(format!("PONG {}", host))
+        MessageBuilder::new("PONG").param(host).build()
     }
     
+    /// Constructs a message containing a CAP command with the given subcommand.
+    /// The `capabilities` parameter is an optional space separated list of capabilities.
+    pub fn cap(subcommand: &str, capabilities: Option<&str>) -> Result<Message> {
+        MessageBuilder::new("CAP")
+            .param(subcommand)
+            .param_opt(capabilities)
+            .build()
+    }
+
+    /// Constructs a message containing an AUTHENTICATE command with the given mechanism or data chunk.
+    pub fn authenticate(payload: &str) -> Result<Message> {
+        MessageBuilder::new("AUTHENTICATE").param(payload).build()
+    }
+
+    /// Constructs a message containing a PASS command with the specified server password.
+    pub fn pass(password: &str) -> Result<Message> {
+        MessageBuilder::new("PASS").param(password).build()
+    }
+
     /// Constructs a message containing a NICK command with the specified nickname.
     pub fn nick(nick: &str) -> Result<Message> {
-        Message::try_from(format!("NICK {}", nick))
+        MessageBuilder::new("NICK").middle(nick).build()
     }
     
     /// Constructs a message containing a USER command with the specified username and real name.
     pub fn user(username: &str, real_name: &str) -> Result<Message> {
-        Message::try_from(format!("USER {} 0 * :{}", username, real_name))
+        MessageBuilder::new("USER")
+            .middle(username)
+            .param("0")
+            .param("*")
+            .param(real_name)
+            .build()
     }
     
     /// Constructs a message containing a JOIN command for the specified channel.
     /// The `channels` parameter is a comma separated list of channels to join.
     /// The `keys` parameter is an optional comma separated list of passwords for the channels being joined.
     pub fn join(channels: &str, keys: Option<&str>) -> Result<Message> {
-        let command = if let Some(keys) = keys {
-            format!("JOIN {} {}", channels, keys)
-        } else {
-            format!("JOIN {}", channels)
-        };
-    
-        Message::try_from(command)
+        let builder = MessageBuilder::new("JOIN").middle(channels);
+
+        match keys {
+            Some(keys) => builder.middle(keys).build(),
+            None => builder.build(),
+        }
     }
     
+    /// Constructs a message containing a PART command for the specified channels.
+    /// The `channels` parameter is a comma separated list of channels to leave.
+    pub fn part(channels: &str, reason: Option<&str>) -> Result<Message> {
+        MessageBuilder::new("PART").middle(channels).param_opt(reason).build()
+    }
+
+    /// Constructs a message containing a QUIT command with an optional reason.
+    pub fn quit(reason: Option<&str>) -> Result<Message> {
+        MessageBuilder::new("QUIT").param_opt(reason).build()
+    }
+
     /// Constructs a message containing a PRIVMSG command sent to the specified targets with the given message.
     pub fn priv_msg(targets: &str, message: &str) -> Result<Message> {
-        Message::try_from(format!("PRIVMSG {} :{}", targets, message))
+        MessageBuilder::new("PRIVMSG").middle(targets).param(message).build()
+    }
+
+    /// Constructs a message containing a NOTICE command sent to the specified targets with the given message.
+    pub fn notice(targets: &str, message: &str) -> Result<Message> {
+        MessageBuilder::new("NOTICE").middle(targets).param(message).build()
+    }
+
+    /// Constructs a message containing a CTCP request, sent to the specified target within a PRIVMSG.
+    pub fn ctcp_request(target: &str, command: &str, params: Option<&str>) -> Result<Message> {
+        Message::priv_msg(target, &ctcp::format(command, params)?)
+    }
+
+    /// Constructs a message containing a CTCP reply, sent to the specified target within a NOTICE.
+    pub fn ctcp_reply(target: &str, command: &str, params: Option<&str>) -> Result<Message> {
+        Message::notice(target, &ctcp::format(command, params)?)
+    }
+
+    /// Constructs a message containing a CTCP ACTION, as sent with the `/me` command.
+    pub fn action(target: &str, text: &str) -> Result<Message> {
+        Message::ctcp_request(target, "ACTION", Some(text))
+    }
+
+    /// Constructs as many PRIVMSG messages as needed to send `text` to the specified targets.
+    /// See `split` for how the text is divided.
+    pub fn split_priv_msg(targets: &str, text: &str, prefix: Option<&str>) -> Result<Vec<Message>> {
+        Message::split("PRIVMSG", targets, text, prefix)
+    }
+
+    /// Constructs as many NOTICE messages as needed to send `text` to the specified targets.
+    /// See `split` for how the text is divided.
+    pub fn split_notice(targets: &str, text: &str, prefix: Option<&str>) -> Result<Vec<Message>> {
+        Message::split("NOTICE", targets, text, prefix)
     }
 
+    /// Constructs as many messages containing `command` as needed to send `text` to the specified targets.
+    ///
+    /// The text is sent one line at a time, and lines that would make the message exceed the 512 byte
+    /// limit when relayed by the server are broken between words where possible, but always between
+    /// UTF-8 characters. Empty lines are skipped.
+    ///
+    /// The `prefix` parameter is our own `nick!user@host` prefix, which the server adds to the message
+    /// when relaying it to the targets. When it is `None`, room is left for a long prefix.
+    pub fn split(command: &str, targets: &str, text: &str, prefix: Option<&str>) -> Result<Vec<Message>> {
+        let prefix_length = prefix.map(|prefix| prefix.len()).unwrap_or(DEFAULT_PREFIX_LENGTH);
+
+        // The message is relayed as `:prefix COMMAND targets :text`.
+        let overhead = prefix_length + command.len() + targets.len() + 5;
+
+        if overhead + 4 > MAX_MESSAGE_LENGTH {
+            return Err(ErrorKind::InputTooLong(format!("There is no room for text in a {} message to {}.", command, targets)).into());
+        }
+
+        split_text(text, MAX_MESSAGE_LENGTH - overhead)
+            .into_iter()
+            .map(|chunk| MessageBuilder::new(command).middle(targets).param(chunk).build())
+            .collect()
+    }
+}
+
+// Breaks text into lines and then into chunks of at most `max_length` bytes,
+// preferring to break at spaces.
+fn split_text(text: &str, max_length: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+
+    for line in text.split(|c| c == '\r' || c == '\n') {
+        let mut rest = line;
+
+        while rest.len() > max_length {
+            let mut end = max_length;
+
+            while !rest.is_char_boundary(end) {
+                end -= 1;
+            }
+
+            match rest[..end].rfind(' ') {
+                Some(space) if space > 0 => {
+                    chunks.push(&rest[..space]);
+                    rest = &rest[space + 1..];
+                }
+                _ => {
+                    chunks.push(&rest[..end]);
+                    rest = &rest[end..];
+                }
+            }
+        }
+
+        chunks.push(rest);
+    }
+
+    chunks.retain(|chunk| !chunk.is_empty());
+    chunks
 }
 
 impl ::std::str::FromStr for Message {
@@ -123,3 +295,72 @@ impl ::std::str::FromStr for Message {
         Message::try_from(input.to_owned())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(messages: &[Message]) -> Vec<&str> {
+        messages.iter().map(|message| message.raw_args().last().unwrap()).collect()
+    }
+
+    #[test]
+    fn split_keeps_short_text_whole() {
+        let messages = Message::split_priv_msg("#rust", "Hello, world!", None).unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].raw_message(), "PRIVMSG #rust :Hello, world!");
+    }
+
+    #[test]
+    fn split_sends_each_line_and_skips_empty_ones() {
+        let messages = Message::split_notice("nick", "one\r\ntwo\n\nthree\r", None).unwrap();
+
+        assert!(messages.iter().all(|message| message.raw_command() == "NOTICE"));
+        assert_eq!(texts(&messages), vec!["one", "two", "three"]);
+    }
+
+    #[test]
+    fn split_breaks_long_lines_between_words() {
+        let prefix = "nick!user@host";
+        let text = vec!["word"; 200].join(" ");
+        let messages = Message::split_priv_msg("#rust", &text, Some(prefix)).unwrap();
+
+        assert!(messages.len() > 1);
+
+        for message in &messages {
+            // The server relays the message as `:prefix PRIVMSG #rust :text`.
+            assert!(prefix.len() + 2 + message.raw_message().len() <= MAX_MESSAGE_LENGTH);
+        }
+
+        let texts = texts(&messages);
+        assert!(texts.iter().all(|text| !text.starts_with(' ') && !text.ends_with(' ')));
+        assert_eq!(texts.join(" "), text);
+    }
+
+    #[test]
+    fn split_breaks_words_between_characters() {
+        let text = "é".repeat(600);
+        let messages = Message::split_priv_msg("#rust", &text, None).unwrap();
+
+        assert!(messages.len() > 1);
+        assert_eq!(texts(&messages).concat(), text);
+    }
+
+    #[test]
+    fn split_leaves_room_for_the_default_prefix() {
+        let text = "a".repeat(1000);
+        let messages = Message::split_priv_msg("#rust", &text, None).unwrap();
+
+        for message in &messages {
+            assert!(DEFAULT_PREFIX_LENGTH + 2 + message.raw_message().len() <= MAX_MESSAGE_LENGTH);
+        }
+    }
+
+    #[test]
+    fn split_fails_without_room_for_text() {
+        let targets = "a".repeat(500);
+
+        assert!(Message::split_priv_msg(&targets, "text", None).is_err());
+    }
+}
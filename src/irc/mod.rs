@@ -1,8 +1,28 @@
+pub mod bot;
+pub mod builder;
+pub mod capabilities;
+pub mod casemap;
 pub mod client;
+pub mod config;
+pub mod ctcp;
+pub mod encoding;
+pub mod event;
+pub mod formatting;
+pub mod isupport;
+pub mod keepalive;
+pub mod reconnect;
+pub mod registration;
+pub mod sasl;
+pub mod state;
+pub mod throttle;
+pub mod tls;
 pub mod transport;
 pub mod codec;
 pub mod message;
+pub mod mode;
+pub mod net;
+pub mod numeric;
 pub mod command;
 pub mod error;
+pub mod tag;
 mod parser;
-mod tag;
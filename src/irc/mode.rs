@@ -0,0 +1,453 @@
+//! The mode module contains the parser for MODE strings, such as `+ov-b nick1 nick2 mask`,
+//! the `ChannelModes` type that keeps track of the modes set on a channel, and helpers for
+//! sending mode changes in as few MODE messages as the server allows.
+
+use std::collections::BTreeMap;
+
+use irc::builder::MessageBuilder;
+use irc::error::Result;
+use irc::isupport::ISupport;
+use irc::message::Message;
+
+// The longest MODE message sent by `mode_messages`, leaving room for the CR-LF.
+const MAX_MODE_MESSAGE_LENGTH: usize = 510;
+
+/// How a channel mode takes its argument, as described by the `CHANMODES` and
+/// `PREFIX` ISUPPORT tokens.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModeKind {
+    /// A mode that adds or removes an address from a list, such as a ban (type A).
+    /// The argument is missing when the list itself is being requested.
+    List,
+    /// A mode that changes a setting and always takes an argument, such as a key (type B).
+    Always,
+    /// A mode that changes a setting and only takes an argument when set, such as a limit (type C).
+    WhenSet,
+    /// A mode that changes a setting and never takes an argument (type D).
+    Never,
+    /// A mode that gives or takes a membership status from a user, such as operator.
+    Prefix,
+}
+
+impl ModeKind {
+    /// Get the kind of the channel mode `mode` according to the server's ISUPPORT tokens.
+    /// Modes the server didn't advertise are assumed to take no argument.
+    pub fn of(isupport: &ISupport, mode: char) -> ModeKind {
+        let chanmodes = isupport.chanmodes();
+
+        if isupport.prefix().iter().any(|&(prefix_mode, _)| prefix_mode == mode) {
+            ModeKind::Prefix
+        } else if chanmodes.list.contains(mode) {
+            ModeKind::List
+        } else if chanmodes.always.contains(mode) {
+            ModeKind::Always
+        } else if chanmodes.when_set.contains(mode) {
+            ModeKind::WhenSet
+        } else {
+            ModeKind::Never
+        }
+    }
+
+    /// Returns true if a mode of this kind takes an argument when being set or unset.
+    pub fn takes_argument(self, adding: bool) -> bool {
+        match self {
+            ModeKind::List | ModeKind::Always | ModeKind::Prefix => true,
+            ModeKind::WhenSet => adding,
+            ModeKind::Never => false,
+        }
+    }
+}
+
+/// A single mode being set or unset, along with its argument.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModeChange {
+    /// True if the mode is being set, false if it is being unset.
+    pub adding: bool,
+    /// The mode character, such as `o`.
+    pub mode: char,
+    /// The argument of the mode, such as a nickname or a ban mask.
+    pub argument: Option<String>,
+}
+
+impl ModeChange {
+    /// Create a change that sets `mode`.
+    pub fn add(mode: char, argument: Option<&str>) -> ModeChange {
+        ModeChange {
+            adding: true,
+            mode: mode,
+            argument: argument.map(|argument| argument.to_owned()),
+        }
+    }
+
+    /// Create a change that unsets `mode`.
+    pub fn remove(mode: char, argument: Option<&str>) -> ModeChange {
+        ModeChange {
+            adding: false,
+            mode: mode,
+            argument: argument.map(|argument| argument.to_owned()),
+        }
+    }
+}
+
+/// Parses the mode string and arguments of a MODE message targeting a channel, pairing each
+/// change with its argument according to the server's `CHANMODES` and `PREFIX` tokens.
+///
+/// A change that needs an argument but has none left is kept with an argument of `None`,
+/// which is how a client asks for the contents of a list mode, as in `MODE #chan +b`.
+pub fn parse_channel_modes<'a, I>(isupport: &ISupport, modes: &str, arguments: I) -> Vec<ModeChange>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    parse(modes, arguments, |mode, adding| ModeKind::of(isupport, mode).takes_argument(adding))
+}
+
+/// Parses the mode string of a MODE message targeting a user. User modes don't take arguments,
+/// with the exception of the server notice mask of `+s`, which is paired when present.
+pub fn parse_user_modes<'a, I>(modes: &str, arguments: I) -> Vec<ModeChange>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    parse(modes, arguments, |mode, adding| mode == 's' && adding)
+}
+
+/// Parses the mode string and arguments of a MODE message, treating them as channel modes when
+/// `target` is a channel according to the server's `CHANTYPES` token and as user modes otherwise.
+pub fn parse_modes<'a, I>(isupport: &ISupport, target: &str, modes: &str, arguments: I) -> Vec<ModeChange>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    if isupport.is_channel(target) {
+        parse_channel_modes(isupport, modes, arguments)
+    } else {
+        parse_user_modes(modes, arguments)
+    }
+}
+
+fn parse<'a, I, F>(modes: &str, arguments: I, takes_argument: F) -> Vec<ModeChange>
+where
+    I: IntoIterator<Item = &'a str>,
+    F: Fn(char, bool) -> bool,
+{
+    let mut arguments = arguments.into_iter();
+    let mut adding = true;
+    let mut changes = Vec::new();
+
+    for mode in modes.chars() {
+        match mode {
+            '+' => adding = true,
+            '-' => adding = false,
+            mode => {
+                let argument = if takes_argument(mode, adding) {
+                    arguments.next().map(|argument| argument.to_owned())
+                } else {
+                    None
+                };
+
+                changes.push(ModeChange {
+                    adding: adding,
+                    mode: mode,
+                    argument: argument,
+                });
+            }
+        }
+    }
+
+    changes
+}
+
+/// Constructs as few MODE messages as possible that apply `changes` to `target`.
+///
+/// Each message carries at most as many changes with an argument as the server's `MODES`
+/// token allows, and is kept within the 512 byte line limit. Consecutive changes in the
+/// same direction share their `+` or `-` sign, as in `MODE #chan +ov-b nick1 nick2 mask`.
+pub fn mode_messages(isupport: &ISupport, target: &str, changes: &[ModeChange]) -> Result<Vec<Message>> {
+    let limit = isupport.modes();
+    let mut messages = Vec::new();
+    let mut batch: Vec<&ModeChange> = Vec::new();
+
+    for change in changes {
+        batch.push(change);
+
+        let with_arguments = batch.iter().filter(|change| change.argument.is_some()).count();
+        let too_many = limit.map(|limit| with_arguments > limit).unwrap_or(false);
+
+        if batch.len() > 1 && (too_many || mode_line_length(target, &batch) > MAX_MODE_MESSAGE_LENGTH) {
+            batch.pop();
+            messages.push(mode_message(target, &batch)?);
+            batch.clear();
+            batch.push(change);
+        }
+    }
+
+    if !batch.is_empty() {
+        messages.push(mode_message(target, &batch)?);
+    }
+
+    Ok(messages)
+}
+
+// Builds the mode string, such as `+ov-b`, for a batch of changes.
+fn mode_string(changes: &[&ModeChange]) -> String {
+    let mut modes = String::new();
+    let mut adding = None;
+
+    for change in changes {
+        if adding != Some(change.adding) {
+            modes.push(if change.adding { '+' } else { '-' });
+            adding = Some(change.adding);
+        }
+
+        modes.push(change.mode);
+    }
+
+    modes
+}
+
+// The length of the line `MODE target modes arguments...` for a batch of changes.
+fn mode_line_length(target: &str, changes: &[&ModeChange]) -> usize {
+    let arguments: usize = changes
+        .iter()
+        .filter_map(|change| change.argument.as_ref())
+        .map(|argument| argument.len() + 1)
+        .sum();
+
+    "MODE ".len() + target.len() + 1 + mode_string(changes).len() + arguments
+}
+
+fn mode_message(target: &str, changes: &[&ModeChange]) -> Result<Message> {
+    let mut builder = MessageBuilder::new("MODE").middle(target).middle(&mode_string(changes));
+
+    for argument in changes.iter().filter_map(|change| change.argument.as_ref()) {
+        builder = builder.middle(argument);
+    }
+
+    builder.build()
+}
+
+/// The modes set on a channel, excluding the membership modes of its users.
+///
+/// Settings, such as `+n` or `+k key`, are kept along with their argument, and
+/// list modes, such as bans, keep every entry that was added.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelModes {
+    settings: BTreeMap<char, Option<String>>,
+    lists: BTreeMap<char, Vec<String>>,
+}
+
+impl ChannelModes {
+    /// Create a new `ChannelModes` without any modes set.
+    pub fn new() -> ChannelModes {
+        ChannelModes::default()
+    }
+
+    /// Applies a change to the channel. Returns false if the change was ignored because it
+    /// changes the membership of a user, or lacks the argument needed to update a list.
+    pub fn apply(&mut self, isupport: &ISupport, change: &ModeChange) -> bool {
+        match ModeKind::of(isupport, change.mode) {
+            ModeKind::Prefix => false,
+            ModeKind::List => match change.argument {
+                Some(ref argument) => {
+                    let list = self.lists.entry(change.mode).or_default();
+                    list.retain(|entry| entry != argument);
+
+                    if change.adding {
+                        list.push(argument.clone());
+                    }
+
+                    true
+                }
+                None => false,
+            },
+            _ => {
+                if change.adding {
+                    self.settings.insert(change.mode, change.argument.clone());
+                } else {
+                    self.settings.remove(&change.mode);
+                }
+
+                true
+            }
+        }
+    }
+
+    /// Applies every change in `changes`, as parsed by `parse_channel_modes`.
+    pub fn apply_all(&mut self, isupport: &ISupport, changes: &[ModeChange]) {
+        for change in changes {
+            self.apply(isupport, change);
+        }
+    }
+
+    /// Unsets every setting, keeping the entries of the list modes.
+    pub fn clear_settings(&mut self) {
+        self.settings.clear();
+    }
+
+    /// Returns true if the setting `mode` is set on the channel.
+    pub fn is_set(&self, mode: char) -> bool {
+        self.settings.contains_key(&mode)
+    }
+
+    /// Get the argument of the setting `mode`, such as the key of `+k`.
+    pub fn argument(&self, mode: char) -> Option<&str> {
+        self.settings
+            .get(&mode)
+            .and_then(|argument| argument.as_ref())
+            .map(|argument| argument.as_str())
+    }
+
+    /// Get the entries of the list mode `mode`, such as the ban masks of `+b`.
+    pub fn list(&self, mode: char) -> &[String] {
+        self.lists.get(&mode).map(|list| list.as_slice()).unwrap_or(&[])
+    }
+
+    /// Get the settings in the form of a mode string and its arguments, such as `+kl` with
+    /// `["key", "20"]`, as returned by the server in `RPL_CHANNELMODEIS`.
+    pub fn to_mode_string(&self) -> (String, Vec<&str>) {
+        let mut modes = String::from("+");
+        let mut arguments = Vec::new();
+
+        for (&mode, argument) in &self.settings {
+            modes.push(mode);
+
+            if let Some(ref argument) = *argument {
+                arguments.push(argument.as_str());
+            }
+        }
+
+        (modes, arguments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn isupport(tokens: &[&str]) -> ISupport {
+        let mut isupport = ISupport::new();
+
+        for token in tokens {
+            isupport.apply(token);
+        }
+
+        isupport
+    }
+
+    fn lines(messages: &[Message]) -> Vec<&str> {
+        messages.iter().map(|message| message.raw_message()).collect()
+    }
+
+    #[test]
+    fn parses_channel_modes() {
+        let isupport = isupport(&["PREFIX=(ohv)@%+", "CHANMODES=beI,k,l,imnpst"]);
+        let changes = parse_channel_modes(&isupport, "+ov-b+kl-l+m", vec!["alice", "bob", "*!*@host", "key", "10"]);
+
+        assert_eq!(
+            changes,
+            vec![
+                ModeChange::add('o', Some("alice")),
+                ModeChange::add('v', Some("bob")),
+                ModeChange::remove('b', Some("*!*@host")),
+                ModeChange::add('k', Some("key")),
+                ModeChange::add('l', Some("10")),
+                ModeChange::remove('l', None),
+                ModeChange::add('m', None),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_list_requests_without_arguments() {
+        let changes = parse_channel_modes(&ISupport::new(), "+b", vec![]);
+
+        assert_eq!(changes, vec![ModeChange::add('b', None)]);
+    }
+
+    #[test]
+    fn parses_user_modes() {
+        assert_eq!(
+            parse_user_modes("+iw-x+s", vec!["+cC"]),
+            vec![
+                ModeChange::add('i', None),
+                ModeChange::add('w', None),
+                ModeChange::remove('x', None),
+                ModeChange::add('s', Some("+cC")),
+            ]
+        );
+
+        let isupport = ISupport::new();
+
+        assert_eq!(parse_modes(&isupport, "nick", "+o", vec!["arg"]), vec![ModeChange::add('o', None)]);
+        assert_eq!(parse_modes(&isupport, "#chan", "+o", vec!["arg"]), vec![ModeChange::add('o', Some("arg"))]);
+    }
+
+    #[test]
+    fn groups_changes_by_sign() {
+        let changes = vec![
+            ModeChange::add('o', Some("alice")),
+            ModeChange::add('v', Some("bob")),
+            ModeChange::remove('b', Some("*!*@host")),
+            ModeChange::add('m', None),
+        ];
+
+        assert_eq!(
+            lines(&mode_messages(&ISupport::new(), "#chan", &changes).unwrap()),
+            vec!["MODE #chan +ov-b+m alice bob *!*@host"]
+        );
+    }
+
+    #[test]
+    fn respects_the_modes_limit() {
+        let changes: Vec<ModeChange> = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|nick| ModeChange::add('o', Some(nick)))
+            .chain(Some(ModeChange::add('m', None)))
+            .collect();
+
+        assert_eq!(
+            lines(&mode_messages(&isupport(&["MODES=2"]), "#chan", &changes).unwrap()),
+            vec!["MODE #chan +oo a b", "MODE #chan +oo c d", "MODE #chan +om e"]
+        );
+
+        assert_eq!(
+            lines(&mode_messages(&isupport(&["MODES"]), "#chan", &changes).unwrap()),
+            vec!["MODE #chan +ooooom a b c d e"]
+        );
+    }
+
+    #[test]
+    fn respects_the_line_limit() {
+        let masks: Vec<String> = (0..10).map(|index| format!("{}!*@{}", index, "h".repeat(60))).collect();
+        let changes: Vec<ModeChange> = masks.iter().map(|mask| ModeChange::add('b', Some(mask))).collect();
+        let messages = mode_messages(&isupport(&["MODES"]), "#chan", &changes).unwrap();
+
+        assert!(messages.len() > 1);
+        assert!(messages.iter().all(|message| message.raw_message().len() <= MAX_MODE_MESSAGE_LENGTH));
+        assert_eq!(messages.iter().map(|message| message.raw_args().count() - 2).sum::<usize>(), 10);
+    }
+
+    #[test]
+    fn tracks_channel_modes() {
+        let isupport = ISupport::new();
+        let mut modes = ChannelModes::new();
+        let changes = parse_channel_modes(&isupport, "+ntkl+bbo", vec!["key", "10", "a!*@*", "b!*@*", "nick"]);
+
+        modes.apply_all(&isupport, &changes);
+
+        assert!(modes.is_set('n'));
+        assert!(!modes.is_set('o'));
+        assert_eq!(modes.argument('k'), Some("key"));
+        assert_eq!(modes.list('b'), &["a!*@*".to_owned(), "b!*@*".to_owned()][..]);
+        assert_eq!(modes.to_mode_string(), ("+klnt".to_owned(), vec!["key", "10"]));
+
+        modes.apply_all(&isupport, &parse_channel_modes(&isupport, "-kl-b", vec!["key", "a!*@*"]));
+
+        assert!(!modes.is_set('k'));
+        assert!(!modes.is_set('l'));
+        assert_eq!(modes.list('b'), &["b!*@*".to_owned()][..]);
+
+        modes.clear_settings();
+
+        assert_eq!(modes.to_mode_string(), ("+".to_owned(), vec![]));
+        assert_eq!(modes.list('b').len(), 1);
+    }
+}
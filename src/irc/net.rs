@@ -0,0 +1,277 @@
+//! The net module contains the types needed to resolve a host name and
+//! establish a TCP connection to one of its addresses.
+
+extern crate futures;
+extern crate tokio_core;
+
+use std::collections::VecDeque;
+use std::io;
+use std::mem;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
+
+use futures::{future, Async, Future, Poll};
+use futures::sync::oneshot;
+use tokio_core::net::{TcpStream, TcpStreamNew};
+use tokio_core::reactor::{Handle, Timeout};
+
+use irc::error::{Error, ErrorKind};
+
+// The delay before the next address is tried while earlier attempts are still pending,
+// as recommended by RFC 8305.
+const ATTEMPT_DELAY_IN_MILLISECONDS: u64 = 250;
+
+/// Returns a future, that when resolved provides a `TcpStream` connected to
+/// `host`, which is either a socket address or a `host:port` string.
+///
+/// Host names are resolved on a background thread, so the event loop isn't
+/// blocked while waiting for DNS.
+pub fn connect(host: &str, handle: &Handle, timeout: Duration) -> Box<dyn Future<Item = TcpStream, Error = Error>> {
+    let handle = handle.clone();
+
+    Box::new(resolve(host).and_then(move |addresses| ConnectAddrs::new(addresses, &handle, timeout)))
+}
+
+/// Returns a future, that when resolved provides every socket address `host` resolves to.
+pub fn resolve(host: &str) -> Box<dyn Future<Item = Vec<SocketAddr>, Error = Error>> {
+    if let Ok(address) = host.parse::<SocketAddr>() {
+        return Box::new(future::ok(vec![address]));
+    }
+
+    let (sender, receiver) = oneshot::channel();
+    let name = host.to_owned();
+
+    thread::spawn(move || {
+        let addresses = name.to_socket_addrs().map(|addresses| addresses.collect());
+        let _ = sender.send(addresses);
+    });
+
+    let host = host.to_owned();
+
+    Box::new(receiver.then(move |result| match result {
+        Ok(Ok(addresses)) => {
+            let addresses: Vec<SocketAddr> = addresses;
+
+            if addresses.is_empty() {
+                Err(ErrorKind::ResolveFailed(host, "No addresses were found.".to_owned()).into())
+            } else {
+                Ok(addresses)
+            }
+        }
+        Ok(Err(error)) => Err(ErrorKind::ResolveFailed(host, error.to_string()).into()),
+        Err(_) => Err(ErrorKind::ResolveFailed(host, "The resolver thread exited.".to_owned()).into()),
+    }))
+}
+
+/// Strips the port and any IPv6 brackets from a `host:port` string, leaving
+/// the name used for TLS server name indication.
+pub fn host_name(host: &str) -> &str {
+    let name = match host.rfind(':') {
+        Some(index) if host[index + 1..].parse::<u16>().is_ok() => &host[..index],
+        _ => host,
+    };
+
+    name.trim_start_matches('[').trim_end_matches(']')
+}
+
+struct Attempt {
+    address: SocketAddr,
+    stream: TcpStreamNew,
+    timeout: Timeout,
+}
+
+/// Represents a future, that when resolved provides a `TcpStream` connected
+/// to the first of several addresses that accepts the connection.
+///
+/// The addresses are tried in turn, alternating between IPv6 and IPv4.
+/// When an attempt hasn't succeeded or failed within a short delay, the next
+/// address is tried alongside it, in the style of the Happy Eyeballs
+/// algorithm. Each attempt is abandoned once `timeout` has passed.
+pub struct ConnectAddrs {
+    handle: Handle,
+    timeout: Duration,
+    pending: VecDeque<SocketAddr>,
+    attempts: Vec<Attempt>,
+    delay: Option<Timeout>,
+    failures: Vec<(SocketAddr, String)>,
+}
+
+impl ConnectAddrs {
+    /// Create a new `ConnectAddrs` that will try each of `addresses`.
+    pub fn new(addresses: Vec<SocketAddr>, handle: &Handle, timeout: Duration) -> ConnectAddrs {
+        ConnectAddrs {
+            handle: handle.clone(),
+            timeout: timeout,
+            pending: interleave(addresses),
+            attempts: Vec::new(),
+            delay: None,
+            failures: Vec::new(),
+        }
+    }
+
+    fn start_attempt(&mut self) -> io::Result<()> {
+        if let Some(address) = self.pending.pop_front() {
+            self.attempts.push(Attempt {
+                address: address,
+                stream: TcpStream::connect(&address, &self.handle),
+                timeout: Timeout::new(self.timeout, &self.handle)?,
+            });
+
+            let delay = Duration::from_millis(ATTEMPT_DELAY_IN_MILLISECONDS);
+            self.delay = Some(Timeout::new(delay, &self.handle)?);
+        }
+
+        Ok(())
+    }
+}
+
+impl Future for ConnectAddrs {
+    type Item = TcpStream;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let delay_elapsed = match self.delay {
+                Some(ref mut delay) => delay.poll()?.is_ready(),
+                None => true,
+            };
+
+            if self.attempts.is_empty() || delay_elapsed {
+                self.start_attempt()?;
+            }
+
+            let mut index = 0;
+
+            while index < self.attempts.len() {
+                let failure = {
+                    let attempt = &mut self.attempts[index];
+
+                    match attempt.stream.poll() {
+                        Ok(Async::Ready(stream)) => return Ok(Async::Ready(stream)),
+                        Ok(Async::NotReady) => match attempt.timeout.poll()? {
+                            Async::Ready(()) => Some("The connection attempt timed out.".to_owned()),
+                            Async::NotReady => None,
+                        },
+                        Err(error) => Some(error.to_string()),
+                    }
+                };
+
+                match failure {
+                    Some(failure) => {
+                        let attempt = self.attempts.remove(index);
+                        self.failures.push((attempt.address, failure));
+                    }
+                    None => index += 1,
+                }
+            }
+
+            if self.attempts.is_empty() {
+                if self.pending.is_empty() {
+                    let failures = mem::take(&mut self.failures);
+                    return Err(ErrorKind::ConnectFailed(failures).into());
+                }
+
+                // Every running attempt failed, so the next address is tried immediately.
+                continue;
+            }
+
+            // NOTE: When the delay elapsed, the loop runs again so that the new delay is polled.
+            if self.pending.is_empty() || !delay_elapsed {
+                return Ok(Async::NotReady);
+            }
+        }
+    }
+}
+
+// Orders the addresses so that IPv6 and IPv4 addresses alternate, starting
+// with the family of the first address.
+fn interleave(addresses: Vec<SocketAddr>) -> VecDeque<SocketAddr> {
+    let prefer_v6 = addresses.first().map(|address| address.is_ipv6()).unwrap_or(true);
+    let (mut first, mut second): (VecDeque<_>, VecDeque<_>) = addresses
+        .into_iter()
+        .partition(|address| address.is_ipv6() == prefer_v6);
+
+    let mut ordered = VecDeque::new();
+
+    loop {
+        match (first.pop_front(), second.pop_front()) {
+            (None, None) => return ordered,
+            (a, b) => {
+                ordered.extend(a);
+                ordered.extend(b);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    fn addresses(addresses: &[&str]) -> Vec<SocketAddr> {
+        addresses.iter().map(|address| address.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn interleaves_address_families() {
+        let ordered = interleave(addresses(&["[::1]:1", "[::2]:1", "[::3]:1", "127.0.0.1:1"]));
+        assert_eq!(Vec::from(ordered), addresses(&["[::1]:1", "127.0.0.1:1", "[::2]:1", "[::3]:1"]));
+
+        let ordered = interleave(addresses(&["127.0.0.1:1", "127.0.0.2:1", "[::1]:1", "127.0.0.3:1"]));
+        assert_eq!(Vec::from(ordered), addresses(&["127.0.0.1:1", "[::1]:1", "127.0.0.2:1", "127.0.0.3:1"]));
+
+        assert!(interleave(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn strips_ports_and_brackets() {
+        assert_eq!(host_name("irc.example.com:6697"), "irc.example.com");
+        assert_eq!(host_name("irc.example.com"), "irc.example.com");
+        assert_eq!(host_name("[::1]:6667"), "::1");
+        assert_eq!(host_name("[::1]"), "::1");
+        assert_eq!(host_name("127.0.0.1:6667"), "127.0.0.1");
+    }
+
+    // Returns an address on which nothing is listening.
+    fn closed_address() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    #[test]
+    fn collects_every_failure() {
+        let mut core = Core::new().unwrap();
+        let closed = vec![closed_address(), closed_address()];
+        let connect = ConnectAddrs::new(closed.clone(), &core.handle(), Duration::from_secs(5));
+
+        match core.run(connect) {
+            Err(Error(ErrorKind::ConnectFailed(failures), _)) => {
+                let mut failed: Vec<SocketAddr> = failures.iter().map(|&(address, _)| address).collect();
+                failed.sort();
+
+                let mut expected = closed;
+                expected.sort();
+
+                assert_eq!(failed, expected);
+            }
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("connected to a closed port"),
+        }
+    }
+
+    #[test]
+    fn connects_to_the_address_that_accepts() {
+        let mut core = Core::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap();
+        let connect = ConnectAddrs::new(vec![closed_address(), open], &core.handle(), Duration::from_secs(5));
+
+        let stream = core.run(connect).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), open);
+    }
+}
@@ -0,0 +1,332 @@
+//! The numeric module contains the `Numeric` enum which names the numeric
+//! replies a server sends, as listed in RFC 1459, RFC 2812 and the modern
+//! IRC client protocol documentation.
+//!
+//! Typed access to the arguments of the most common numerics is provided by
+//! the `command` module.
+
+/// A macro for creating the `Numeric` enum from a list of codes and names.
+macro_rules! numerics {
+    ($($code:expr => $name:ident,)+) => {
+        /// A known numeric reply, named as in the specifications.
+        #[allow(non_camel_case_types)]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum Numeric {
+            $($name,)+
+        }
+
+        impl Numeric {
+            /// Get the numeric with the given code, if it is known.
+            pub fn from_code(code: u16) -> Option<Numeric> {
+                match code {
+                    $($code => Some(Numeric::$name),)+
+                    _ => None,
+                }
+            }
+
+            /// Get the code of the numeric.
+            pub fn code(&self) -> u16 {
+                match *self {
+                    $(Numeric::$name => $code,)+
+                }
+            }
+
+            /// Get the name of the numeric, such as `RPL_WELCOME`.
+            pub fn name(&self) -> &'static str {
+                match *self {
+                    $(Numeric::$name => stringify!($name),)+
+                }
+            }
+        }
+    };
+}
+
+numerics! {
+    1 => RPL_WELCOME,
+    2 => RPL_YOURHOST,
+    3 => RPL_CREATED,
+    4 => RPL_MYINFO,
+    5 => RPL_ISUPPORT,
+    10 => RPL_BOUNCE,
+    200 => RPL_TRACELINK,
+    201 => RPL_TRACECONNECTING,
+    202 => RPL_TRACEHANDSHAKE,
+    203 => RPL_TRACEUNKNOWN,
+    204 => RPL_TRACEOPERATOR,
+    205 => RPL_TRACEUSER,
+    206 => RPL_TRACESERVER,
+    207 => RPL_TRACESERVICE,
+    208 => RPL_TRACENEWTYPE,
+    209 => RPL_TRACECLASS,
+    211 => RPL_STATSLINKINFO,
+    212 => RPL_STATSCOMMANDS,
+    213 => RPL_STATSCLINE,
+    215 => RPL_STATSILINE,
+    216 => RPL_STATSKLINE,
+    218 => RPL_STATSYLINE,
+    219 => RPL_ENDOFSTATS,
+    221 => RPL_UMODEIS,
+    234 => RPL_SERVLIST,
+    235 => RPL_SERVLISTEND,
+    241 => RPL_STATSLLINE,
+    242 => RPL_STATSUPTIME,
+    243 => RPL_STATSOLINE,
+    244 => RPL_STATSHLINE,
+    251 => RPL_LUSERCLIENT,
+    252 => RPL_LUSEROP,
+    253 => RPL_LUSERUNKNOWN,
+    254 => RPL_LUSERCHANNELS,
+    255 => RPL_LUSERME,
+    256 => RPL_ADMINME,
+    257 => RPL_ADMINLOC1,
+    258 => RPL_ADMINLOC2,
+    259 => RPL_ADMINEMAIL,
+    261 => RPL_TRACELOG,
+    262 => RPL_TRACEEND,
+    263 => RPL_TRYAGAIN,
+    265 => RPL_LOCALUSERS,
+    266 => RPL_GLOBALUSERS,
+    276 => RPL_WHOISCERTFP,
+    300 => RPL_NONE,
+    301 => RPL_AWAY,
+    302 => RPL_USERHOST,
+    303 => RPL_ISON,
+    305 => RPL_UNAWAY,
+    306 => RPL_NOWAWAY,
+    307 => RPL_WHOISREGNICK,
+    311 => RPL_WHOISUSER,
+    312 => RPL_WHOISSERVER,
+    313 => RPL_WHOISOPERATOR,
+    314 => RPL_WHOWASUSER,
+    315 => RPL_ENDOFWHO,
+    317 => RPL_WHOISIDLE,
+    318 => RPL_ENDOFWHOIS,
+    319 => RPL_WHOISCHANNELS,
+    320 => RPL_WHOISSPECIAL,
+    321 => RPL_LISTSTART,
+    322 => RPL_LIST,
+    323 => RPL_LISTEND,
+    324 => RPL_CHANNELMODEIS,
+    325 => RPL_UNIQOPIS,
+    329 => RPL_CREATIONTIME,
+    330 => RPL_WHOISACCOUNT,
+    331 => RPL_NOTOPIC,
+    332 => RPL_TOPIC,
+    333 => RPL_TOPICWHOTIME,
+    336 => RPL_INVITELIST,
+    337 => RPL_ENDOFINVITELIST,
+    338 => RPL_WHOISACTUALLY,
+    341 => RPL_INVITING,
+    342 => RPL_SUMMONING,
+    346 => RPL_INVEXLIST,
+    347 => RPL_ENDOFINVEXLIST,
+    348 => RPL_EXCEPTLIST,
+    349 => RPL_ENDOFEXCEPTLIST,
+    351 => RPL_VERSION,
+    352 => RPL_WHOREPLY,
+    353 => RPL_NAMREPLY,
+    354 => RPL_WHOSPCRPL,
+    364 => RPL_LINKS,
+    365 => RPL_ENDOFLINKS,
+    366 => RPL_ENDOFNAMES,
+    367 => RPL_BANLIST,
+    368 => RPL_ENDOFBANLIST,
+    369 => RPL_ENDOFWHOWAS,
+    371 => RPL_INFO,
+    372 => RPL_MOTD,
+    374 => RPL_ENDOFINFO,
+    375 => RPL_MOTDSTART,
+    376 => RPL_ENDOFMOTD,
+    378 => RPL_WHOISHOST,
+    379 => RPL_WHOISMODES,
+    381 => RPL_YOUREOPER,
+    382 => RPL_REHASHING,
+    383 => RPL_YOURESERVICE,
+    391 => RPL_TIME,
+    392 => RPL_USERSSTART,
+    393 => RPL_USERS,
+    394 => RPL_ENDOFUSERS,
+    395 => RPL_NOUSERS,
+    396 => RPL_HOSTHIDDEN,
+    400 => ERR_UNKNOWNERROR,
+    401 => ERR_NOSUCHNICK,
+    402 => ERR_NOSUCHSERVER,
+    403 => ERR_NOSUCHCHANNEL,
+    404 => ERR_CANNOTSENDTOCHAN,
+    405 => ERR_TOOMANYCHANNELS,
+    406 => ERR_WASNOSUCHNICK,
+    407 => ERR_TOOMANYTARGETS,
+    408 => ERR_NOSUCHSERVICE,
+    409 => ERR_NOORIGIN,
+    411 => ERR_NORECIPIENT,
+    412 => ERR_NOTEXTTOSEND,
+    413 => ERR_NOTOPLEVEL,
+    414 => ERR_WILDTOPLEVEL,
+    415 => ERR_BADMASK,
+    417 => ERR_INPUTTOOLONG,
+    421 => ERR_UNKNOWNCOMMAND,
+    422 => ERR_NOMOTD,
+    423 => ERR_NOADMININFO,
+    424 => ERR_FILEERROR,
+    431 => ERR_NONICKNAMEGIVEN,
+    432 => ERR_ERRONEUSNICKNAME,
+    433 => ERR_NICKNAMEINUSE,
+    436 => ERR_NICKCOLLISION,
+    437 => ERR_UNAVAILRESOURCE,
+    441 => ERR_USERNOTINCHANNEL,
+    442 => ERR_NOTONCHANNEL,
+    443 => ERR_USERONCHANNEL,
+    444 => ERR_NOLOGIN,
+    445 => ERR_SUMMONDISABLED,
+    446 => ERR_USERSDISABLED,
+    451 => ERR_NOTREGISTERED,
+    461 => ERR_NEEDMOREPARAMS,
+    462 => ERR_ALREADYREGISTERED,
+    463 => ERR_NOPERMFORHOST,
+    464 => ERR_PASSWDMISMATCH,
+    465 => ERR_YOUREBANNEDCREEP,
+    466 => ERR_YOUWILLBEBANNED,
+    467 => ERR_KEYSET,
+    471 => ERR_CHANNELISFULL,
+    472 => ERR_UNKNOWNMODE,
+    473 => ERR_INVITEONLYCHAN,
+    474 => ERR_BANNEDFROMCHAN,
+    475 => ERR_BADCHANNELKEY,
+    476 => ERR_BADCHANMASK,
+    477 => ERR_NOCHANMODES,
+    478 => ERR_BANLISTFULL,
+    481 => ERR_NOPRIVILEGES,
+    482 => ERR_CHANOPRIVSNEEDED,
+    483 => ERR_CANTKILLSERVER,
+    484 => ERR_RESTRICTED,
+    485 => ERR_UNIQOPPRIVSNEEDED,
+    491 => ERR_NOOPERHOST,
+    501 => ERR_UMODEUNKNOWNFLAG,
+    502 => ERR_USERSDONTMATCH,
+    524 => ERR_HELPNOTFOUND,
+    525 => ERR_INVALIDKEY,
+    670 => RPL_STARTTLS,
+    671 => RPL_WHOISSECURE,
+    691 => ERR_STARTTLS,
+    696 => ERR_INVALIDMODEPARAM,
+    704 => RPL_HELPSTART,
+    705 => RPL_HELPTXT,
+    706 => RPL_ENDOFHELP,
+    723 => ERR_NOPRIVS,
+    730 => RPL_MONONLINE,
+    731 => RPL_MONOFFLINE,
+    732 => RPL_MONLIST,
+    733 => RPL_ENDOFMONLIST,
+    734 => ERR_MONLISTFULL,
+    900 => RPL_LOGGEDIN,
+    901 => RPL_LOGGEDOUT,
+    902 => ERR_NICKLOCKED,
+    903 => RPL_SASLSUCCESS,
+    904 => ERR_SASLFAIL,
+    905 => ERR_SASLTOOLONG,
+    906 => ERR_SASLABORTED,
+    907 => ERR_SASLALREADY,
+    908 => RPL_SASLMECHS,
+}
+
+impl Numeric {
+    /// Get the numeric with the given three digit code, such as `"001"`, if it is known.
+    pub fn from_command(command: &str) -> Option<Numeric> {
+        parse_code(command).and_then(Numeric::from_code)
+    }
+
+    /// Returns true if the numeric reports an error.
+    pub fn is_error(&self) -> bool {
+        self.name().starts_with("ERR_")
+    }
+
+    /// Get the three digit form of the code used in messages, such as `"001"`.
+    pub fn command(&self) -> String {
+        format!("{:03}", self.code())
+    }
+}
+
+impl ::std::fmt::Display for Numeric {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Returns true if the numeric with the given code reports an error. Codes that
+/// aren't known are treated as errors when they are in the 400 to 599 range.
+pub fn is_error(code: u16) -> bool {
+    match Numeric::from_code(code) {
+        Some(numeric) => numeric.is_error(),
+        None => (400..600).contains(&code),
+    }
+}
+
+/// Parses the code of a numeric command, which consists of exactly three digits.
+pub fn parse_code(command: &str) -> Option<u16> {
+    if command.len() == 3 && command.bytes().all(|b| b.is_ascii_digit()) {
+        command.parse().ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_codes() {
+        let mut known = 0;
+
+        for code in 0..1000 {
+            if let Some(numeric) = Numeric::from_code(code) {
+                known += 1;
+
+                assert_eq!(numeric.code(), code);
+                assert_eq!(numeric.command().len(), 3);
+                assert_eq!(Numeric::from_command(&numeric.command()), Some(numeric));
+                assert_eq!(parse_code(&numeric.command()), Some(code));
+            }
+        }
+
+        assert!(known > 100);
+        assert_eq!(Numeric::RPL_WELCOME.command(), "001");
+        assert_eq!(Numeric::ERR_NICKNAMEINUSE.command(), "433");
+        assert_eq!(Numeric::from_command("001"), Some(Numeric::RPL_WELCOME));
+        assert_eq!(Numeric::from_command("000"), None);
+        assert_eq!(Numeric::RPL_WELCOME.to_string(), "RPL_WELCOME");
+    }
+
+    #[test]
+    fn classifies_errors() {
+        assert!(Numeric::ERR_NICKNAMEINUSE.is_error());
+        assert!(!Numeric::RPL_WELCOME.is_error());
+
+        assert!(is_error(433));
+        assert!(is_error(904));
+        assert!(!is_error(1));
+        assert!(!is_error(903));
+
+        for &code in &[499, 599] {
+            assert_eq!(Numeric::from_code(code), None);
+            assert!(is_error(code));
+        }
+
+        for &code in &[399, 600, 999] {
+            assert_eq!(Numeric::from_code(code), None);
+            assert!(!is_error(code));
+        }
+    }
+
+    #[test]
+    fn parses_three_digit_codes_only() {
+        assert_eq!(parse_code("001"), Some(1));
+        assert_eq!(parse_code("433"), Some(433));
+        assert_eq!(parse_code("01"), None);
+        assert_eq!(parse_code("0001"), None);
+        assert_eq!(parse_code("+01"), None);
+        assert_eq!(parse_code("-01"), None);
+        assert_eq!(parse_code("PRIVMSG"), None);
+        assert_eq!(parse_code(""), None);
+    }
+}
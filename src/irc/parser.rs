@@ -8,7 +8,17 @@ use std::ops::Range;
 type ParseResult<'input, T> = Result<(T, usize)>;
 
 pub fn parse_message<M: Into<String>>(message: M) -> Result<Message> {
-    let message = message.into();
+    parse(message.into(), true)
+}
+
+// Parses a line received from the server, whose length has already been limited
+// by the codec before it was decoded. Decoding a legacy encoding can make the
+// text longer than the line was.
+pub fn parse_received(message: String) -> Result<Message> {
+    parse(message, false)
+}
+
+fn parse(message: String, check_length: bool) -> Result<Message> {
 
     let (tags, prefix, command, args) = {
         let input = message.as_bytes();
@@ -16,7 +26,7 @@ pub fn parse_message<M: Into<String>>(message: M) -> Result<Message> {
 
         let tags_end = position;
 
-        if tags_end > 512 {
+        if check_length && tags_end > 512 {
             return Err(
                 ErrorKind::InputTooLong("The tags length exceeded 512 bytes.".to_owned())
                     .into(),
@@ -27,7 +37,7 @@ pub fn parse_message<M: Into<String>>(message: M) -> Result<Message> {
         let (command, position) = parse_command(input, position)?;
         let (args, position) = parse_args(input, position)?;
 
-        if (position - tags_end) > 510 {
+        if check_length && (position - tags_end) > 510 {
             return Err(
                 ErrorKind::InputTooLong("The message length exceeded 512 bytes.".to_owned())
                     .into(),
@@ -68,11 +78,7 @@ fn parse_tags(input: &[u8]) -> ParseResult<Option<Vec<TagRange>>> {
 
         loop {
             let key_start = position;
-            while input[position] != b'=' && input[position] != b';' {
-                if input[position] == b' ' {
-                    return Err(ErrorKind::UnexpectedEndOfInput.into());
-                }
-
+            while input[position] != b'=' && input[position] != b';' && input[position] != b' ' {
                 position = move_next(position, len)?;
             }
 
@@ -200,7 +206,9 @@ fn parse_args(input: &[u8], mut position: usize) -> ParseResult<Option<Vec<Range
     let mut arg_start = position;
 
     loop {
-        if input[position] == b':' {
+        // NOTE: Only a `:` at the start of a parameter begins the trailing parameter,
+        // as in `TARGMAX=PRIVMSG:4` it is part of the value.
+        if position == arg_start && input[position] == b':' {
             position += 1;
             args.push(position..len);
             break;
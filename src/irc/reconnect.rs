@@ -0,0 +1,567 @@
+//! The reconnect module contains the `Reconnecting` type which keeps a
+//! connection to a remote IRC host alive by reconnecting whenever it's lost.
+
+extern crate futures;
+extern crate rand;
+extern crate tokio_core;
+extern crate tokio_io;
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use futures::{Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+use tokio_core::reactor::{Handle, Timeout};
+use self::tokio_io::{AsyncRead, AsyncWrite};
+
+use irc::capabilities::Capabilities;
+use irc::casemap;
+use irc::client::ClientConnectFuture;
+use irc::command::{BadChannelKey, BannedFromChannel, ChannelIsFull, InviteOnlyChannel, Kick, Part};
+use irc::error::{Error, ErrorKind};
+use irc::isupport::CaseMapping;
+use irc::message::Message;
+use irc::state::State as ConnectionState;
+use irc::transport::IrcTransport;
+
+/// The items produced by a `Reconnecting` stream.
+#[derive(Debug)]
+pub enum ReconnectEvent {
+    /// A message received from the server.
+    Message(Message),
+    /// A connection has been established and registered with the server.
+    Connected,
+    /// The established connection was lost.
+    Disconnected(Error),
+    /// An attempt to establish a connection failed.
+    ConnectFailed(Error),
+    /// Another connection attempt will be made after the given delay.
+    Reconnecting { attempt: u32, delay: Duration },
+}
+
+enum State<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    Connecting(Box<ClientConnectFuture<T>>),
+    Connected(Box<IrcTransport<T>>),
+    Waiting(Timeout),
+}
+
+/// A `Stream` and `Sink` that re-establishes its connection to the server
+/// whenever it's lost, instead of ending the stream.
+///
+/// Reconnection attempts are delayed using exponential backoff with jitter.
+/// Every attempt performs the full registration, after which the channels
+/// that were joined when the connection was lost, or were still being joined
+/// again after an earlier reconnect, are joined again along with their keys.
+/// Connection changes are reported as `ReconnectEvent` items alongside the
+/// received messages.
+///
+/// Messages sent while disconnected are kept and sent once the connection has
+/// been re-established. Sending a QUIT message ends the stream once the
+/// server closes the connection, rather than reconnecting.
+///
+/// The stream only fails when the server refuses the registration in a way
+/// that reconnecting cannot fix, such as with a wrong password or a ban.
+pub struct Reconnecting<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    connect: Box<dyn Fn() -> ClientConnectFuture<T>>,
+    handle: Handle,
+    state: State<T>,
+    backoff: Backoff,
+    attempt: u32,
+    events: VecDeque<ReconnectEvent>,
+    outgoing: VecDeque<Message>,
+    rejoins: Rejoins,
+    quitting: bool,
+}
+
+impl<T> Reconnecting<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    /// Create a new `Reconnecting` that uses `connect` to establish each connection.
+    ///
+    /// The first attempt is made immediately. Reconnection attempts are delayed by
+    /// `delay`, which doubles after each failed attempt up to `max_delay`.
+    pub fn new<F>(handle: &Handle, delay: Duration, max_delay: Duration, connect: F) -> Reconnecting<T>
+    where
+        F: Fn() -> ClientConnectFuture<T> + 'static,
+    {
+        let state = State::Connecting(Box::new(connect()));
+
+        Reconnecting {
+            connect: Box::new(connect),
+            handle: handle.clone(),
+            state: state,
+            backoff: Backoff::new(delay, max_delay),
+            attempt: 0,
+            events: VecDeque::new(),
+            outgoing: VecDeque::new(),
+            rejoins: Rejoins::new(),
+            quitting: false,
+        }
+    }
+
+    /// Returns true while a registered connection to the server is established.
+    pub fn is_connected(&self) -> bool {
+        matches!(self.state, State::Connected(_))
+    }
+
+    /// Retrieves the channels, users and nickname tracked by the current connection,
+    /// or `None` while disconnected.
+    pub fn state(&self) -> Option<&ConnectionState> {
+        match self.state {
+            State::Connected(ref transport) => Some(transport.state()),
+            _ => None,
+        }
+    }
+
+    /// Get the lag of the current connection, or `None` while disconnected or
+    /// before a PING has been answered.
+    pub fn lag(&self) -> Option<Duration> {
+        match self.state {
+            State::Connected(ref transport) => transport.lag(),
+            _ => None,
+        }
+    }
+
+    /// Retrieves the capabilities enabled for the current connection, or `None` while disconnected.
+    pub fn capabilities(&self) -> Option<&Capabilities> {
+        match self.state {
+            State::Connected(ref transport) => Some(transport.capabilities()),
+            _ => None,
+        }
+    }
+
+    /// Get the number of messages waiting to be sent, including those held
+    /// while disconnected and those in the connection's send queue.
+    pub fn queued(&self) -> usize {
+        match self.state {
+            State::Connected(ref transport) => self.outgoing.len() + transport.queued(),
+            _ => self.outgoing.len(),
+        }
+    }
+
+    /// Get the channels that will be joined again after reconnecting, along with their keys.
+    pub fn channels(&self) -> Vec<(String, Option<String>)> {
+        match self.state {
+            State::Connected(ref transport) => self.rejoins.list(transport.state()),
+            _ => self.rejoins.channels.clone(),
+        }
+    }
+
+    fn schedule(&mut self) -> Result<(), Error> {
+        self.attempt += 1;
+
+        let delay = self.backoff.next();
+        self.state = State::Waiting(Timeout::new(delay, &self.handle)?);
+        self.events.push_back(ReconnectEvent::Reconnecting {
+            attempt: self.attempt,
+            delay: delay,
+        });
+
+        Ok(())
+    }
+
+    fn connected(&mut self, transport: IrcTransport<T>) -> Result<(), Error> {
+        self.state = State::Connected(Box::new(transport));
+        self.attempt = 0;
+        self.backoff.reset();
+        self.events.push_back(ReconnectEvent::Connected);
+
+        for &(ref channel, ref key) in self.rejoins.channels.iter().rev() {
+            self.outgoing
+                .push_front(Message::join(channel, key.as_ref().map(|key| key.as_str()))?);
+        }
+
+        Ok(())
+    }
+
+    fn disconnected(&mut self, error: Error) -> Result<(), Error> {
+        if let State::Connected(ref transport) = self.state {
+            self.rejoins.disconnected(transport.state());
+        }
+
+        self.events.push_back(ReconnectEvent::Disconnected(error));
+
+        self.schedule()
+    }
+
+    // Sends as many of the outgoing messages as the transport will accept.
+    fn flush(&mut self) -> Result<(), Error> {
+        if let State::Connected(ref mut transport) = self.state {
+            while let Some(message) = self.outgoing.pop_front() {
+                if let AsyncSink::NotReady(message) = transport.start_send(message)? {
+                    self.outgoing.push_front(message);
+                    break;
+                }
+            }
+
+            transport.poll_complete()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Stream for Reconnecting<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    type Item = ReconnectEvent;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Async::Ready(Some(event)));
+            }
+
+            let result = match self.state {
+                State::Connecting(ref mut connect) => match connect.poll() {
+                    Ok(Async::Ready(transport)) => Ok(Some(transport)),
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(error) => Err(error),
+                },
+                State::Waiting(ref mut timeout) => {
+                    try_ready!(timeout.poll());
+                    Ok(None)
+                }
+                State::Connected(_) => {
+                    if let Err(error) = self.flush() {
+                        if self.quitting {
+                            return Ok(Async::Ready(None));
+                        }
+
+                        self.disconnected(error)?;
+                        continue;
+                    }
+
+                    let message = match self.state {
+                        State::Connected(ref mut transport) => transport.poll(),
+                        _ => unreachable!(),
+                    };
+
+                    match message {
+                        Ok(Async::Ready(Some(message))) => {
+                            if let State::Connected(ref transport) = self.state {
+                                self.rejoins.incoming(&message, transport.state());
+                            }
+
+                            return Ok(Async::Ready(Some(ReconnectEvent::Message(message))));
+                        }
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(ref error) if error.is_recoverable() => {}
+                        Ok(Async::Ready(None)) | Err(_) if self.quitting => return Ok(Async::Ready(None)),
+                        Ok(Async::Ready(None)) => self.disconnected(ErrorKind::ConnectionReset.into())?,
+                        Err(error) => self.disconnected(error)?,
+                    }
+
+                    continue;
+                }
+            };
+
+            match result {
+                Ok(Some(transport)) => self.connected(transport)?,
+                Ok(None) => self.state = State::Connecting(Box::new((self.connect)())),
+                Err(error) => {
+                    if is_fatal(&error) {
+                        return Err(error);
+                    }
+
+                    self.events.push_back(ReconnectEvent::ConnectFailed(error));
+                    self.schedule()?;
+                }
+            }
+        }
+    }
+}
+
+impl<T> Sink for Reconnecting<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    type SinkItem = Message;
+    type SinkError = Error;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        if item.raw_command() == "QUIT" {
+            self.quitting = true;
+        }
+
+        match self.state {
+            State::Connected(ref transport) => self.rejoins.outgoing(&item, &transport.isupport().casemapping()),
+            _ => {
+                let casemapping = self.rejoins.casemapping.clone();
+                self.rejoins.outgoing(&item, &casemapping);
+            }
+        }
+
+        self.outgoing.push_back(item);
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        // NOTE: Errors are left for the stream to discover, which takes care of reconnecting.
+        // Until then any unsent messages stay queued.
+        let _ = self.flush();
+
+        Ok(Async::Ready(()))
+    }
+}
+
+// Keeps track of the channels to join again after reconnecting, along with the keys used to join them.
+#[derive(Clone, Debug)]
+struct Rejoins {
+    // The channels to join again, as of the last time the connection was lost. They stay
+    // listed until they are left, even when the server hasn't confirmed joining them again yet.
+    channels: Vec<(String, Option<String>)>,
+    keys: Vec<(String, String)>,
+    // The case mapping of the last connection, used to compare channel names while disconnected.
+    casemapping: CaseMapping,
+}
+
+impl Rejoins {
+    fn new() -> Rejoins {
+        Rejoins {
+            channels: Vec::new(),
+            keys: Vec::new(),
+            casemapping: CaseMapping::Rfc1459,
+        }
+    }
+
+    // Lists the channels from before the last reconnect, followed by the other channels the
+    // connection is in, along with the keys used to join them.
+    fn list(&self, state: &ConnectionState) -> Vec<(String, Option<String>)> {
+        let casemapping = state.isupport().casemapping();
+        let mut channels = self.channels.clone();
+
+        for channel in state.channels() {
+            let key = self.keys
+                .iter()
+                .find(|&&(ref keyed, _)| casemap::eq(&casemapping, keyed, channel.name()))
+                .map(|&(_, ref key)| key.as_str())
+                .or_else(|| channel.modes().argument('k'))
+                .map(|key| key.to_owned());
+
+            match channels.iter_mut().find(|&&mut (ref name, _)| casemap::eq(&casemapping, name, channel.name())) {
+                Some(&mut (_, ref mut known)) => *known = key.or_else(|| known.take()),
+                None => channels.push((channel.name().to_owned(), key)),
+            }
+        }
+
+        channels
+    }
+
+    fn disconnected(&mut self, state: &ConnectionState) {
+        self.channels = self.list(state);
+        self.casemapping = state.isupport().casemapping();
+    }
+
+    // Forgets the channels we failed to join or have left, along with their keys.
+    fn incoming(&mut self, message: &Message, state: &ConnectionState) {
+        let casemapping = state.isupport().casemapping();
+        let mut forget = Vec::new();
+
+        if let Some(ChannelIsFull(_, channel, _)) = message.command::<ChannelIsFull>() {
+            forget.push(channel);
+        } else if let Some(InviteOnlyChannel(_, channel, _)) = message.command::<InviteOnlyChannel>() {
+            forget.push(channel);
+        } else if let Some(BannedFromChannel(_, channel, _)) = message.command::<BannedFromChannel>() {
+            forget.push(channel);
+        } else if let Some(BadChannelKey(_, channel, _)) = message.command::<BadChannelKey>() {
+            forget.push(channel);
+        } else if let Some(kick) = message.command::<Kick>() {
+            forget.extend(kick.kicks().into_iter().filter(|&(_, nick)| state.is_me(nick)).map(|(channel, _)| channel));
+        } else if let Some(part) = message.command::<Part>() {
+            if message.prefix().map(|(nick, _, _)| state.is_me(nick)).unwrap_or(false) {
+                forget.extend(part.channels());
+            }
+        }
+
+        for channel in forget {
+            self.channels.retain(|&(ref name, _)| !casemap::eq(&casemapping, name, channel));
+            self.keys.retain(|&(ref keyed, _)| !casemap::eq(&casemapping, keyed, channel));
+        }
+    }
+
+    // Remembers the keys of channels we're joining.
+    fn outgoing(&mut self, message: &Message, casemapping: &CaseMapping) {
+        if message.raw_command() != "JOIN" {
+            return;
+        }
+
+        let mut args = message.raw_args();
+
+        if let (Some(channels), Some(keys)) = (args.next(), args.next()) {
+            for (channel, key) in channels.split(',').zip(keys.split(',')) {
+                self.keys.retain(|&(ref keyed, _)| !casemap::eq(casemapping, keyed, channel));
+                self.keys.push((channel.to_owned(), key.to_owned()));
+            }
+        }
+    }
+}
+
+// Errors caused by the server refusing the registration, which reconnecting won't fix.
+fn is_fatal(error: &Error) -> bool {
+    matches!(
+        *error.kind(),
+        ErrorKind::PasswordMismatch
+            | ErrorKind::Banned(_)
+            | ErrorKind::ErroneousNickname(_)
+            | ErrorKind::SaslUnavailable
+            | ErrorKind::SaslMechanismUnsupported(_)
+            | ErrorKind::SaslFailed(_)
+            | ErrorKind::NickLocked
+            | ErrorKind::Tls(_)
+    )
+}
+
+/// Calculates the delay before each reconnection attempt using exponential
+/// backoff with jitter.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    /// Create a new `Backoff` that starts at `initial` and never exceeds `max`.
+    pub fn new(initial: Duration, max: Duration) -> Backoff {
+        Backoff {
+            initial: initial,
+            max: max,
+            current: initial,
+        }
+    }
+
+    /// Returns the next delay, chosen at random between half and all of the
+    /// current delay, and doubles the current delay.
+    pub fn next(&mut self) -> Duration {
+        let millis = duration_millis(self.current);
+        let delay = Duration::from_millis(rand::random_range(millis / 2..millis + 1));
+
+        self.current = ::std::cmp::min(self.current * 2, self.max);
+
+        delay
+    }
+
+    /// Resets the delay after a successful connection.
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+}
+
+fn duration_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(line: &str) -> Message {
+        Message::try_from(line.to_owned()).unwrap()
+    }
+
+    fn connection(lines: &[&str]) -> ConnectionState {
+        let mut state = ConnectionState::new();
+        state.handle(&message(":server 001 me :Welcome"));
+
+        for line in lines {
+            state.handle(&message(line));
+        }
+
+        state
+    }
+
+    fn channels(list: &[(&str, Option<&str>)]) -> Vec<(String, Option<String>)> {
+        list.iter().map(|&(channel, key)| (channel.to_owned(), key.map(|key| key.to_owned()))).collect()
+    }
+
+    // The channels of a connection are listed in no particular order.
+    fn sorted(mut list: Vec<(String, Option<String>)>) -> Vec<(String, Option<String>)> {
+        list.sort();
+        list
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(1000));
+
+        for &max in &[100, 200, 400, 800, 1000, 1000] {
+            let delay = backoff.next();
+            assert!(delay >= Duration::from_millis(max / 2) && delay <= Duration::from_millis(max), "{:?}", delay);
+        }
+
+        backoff.reset();
+        assert!(backoff.next() <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn classifies_fatal_errors() {
+        assert!(is_fatal(&ErrorKind::PasswordMismatch.into()));
+        assert!(is_fatal(&ErrorKind::Banned("You are banned".to_owned()).into()));
+        assert!(is_fatal(&ErrorKind::SaslUnavailable.into()));
+        assert!(!is_fatal(&ErrorKind::ConnectionReset.into()));
+        assert!(!is_fatal(&ErrorKind::PingTimeout(Duration::from_secs(60)).into()));
+    }
+
+    #[test]
+    fn lists_joined_channels_with_their_keys() {
+        let mut rejoins = Rejoins::new();
+        rejoins.outgoing(&message("JOIN #Foo,#bar secret"), &CaseMapping::Rfc1459);
+
+        let state = connection(&[":me!u@h JOIN #foo", ":me!u@h JOIN #bar", ":other!u@h JOIN #baz"]);
+        assert_eq!(sorted(rejoins.list(&state)), channels(&[("#bar", None), ("#foo", Some("secret"))]));
+
+        let state = connection(&[":me!u@h JOIN #keyed", ":server 324 me #keyed +k hunter2"]);
+        assert_eq!(rejoins.list(&state), channels(&[("#keyed", Some("hunter2"))]));
+    }
+
+    #[test]
+    fn replaces_keys_regardless_of_case() {
+        let mut rejoins = Rejoins::new();
+        rejoins.outgoing(&message("JOIN #Foo old"), &CaseMapping::Rfc1459);
+        rejoins.outgoing(&message("JOIN #FOO new"), &CaseMapping::Rfc1459);
+
+        assert_eq!(rejoins.keys, vec![("#FOO".to_owned(), "new".to_owned())]);
+    }
+
+    #[test]
+    fn keeps_unconfirmed_channels_across_reconnects() {
+        let mut rejoins = Rejoins::new();
+        rejoins.outgoing(&message("JOIN #a key"), &CaseMapping::Rfc1459);
+        rejoins.disconnected(&connection(&[":me!u@h JOIN #a", ":me!u@h JOIN #b"]));
+
+        // The connection was lost again before the server confirmed the joins.
+        rejoins.disconnected(&connection(&[]));
+        assert_eq!(sorted(rejoins.channels.clone()), channels(&[("#a", Some("key")), ("#b", None)]));
+
+        let state = connection(&[":me!u@h JOIN #B", ":me!u@h JOIN #c"]);
+        assert_eq!(sorted(rejoins.list(&state)), channels(&[("#a", Some("key")), ("#b", None), ("#c", None)]));
+    }
+
+    #[test]
+    fn forgets_channels_that_were_left_or_refused() {
+        let mut rejoins = Rejoins::new();
+        rejoins.outgoing(&message("JOIN #a,#b,#c,#d one,two,three,four"), &CaseMapping::Rfc1459);
+
+        let state = connection(&[":me!u@h JOIN #a", ":me!u@h JOIN #b", ":me!u@h JOIN #c", ":me!u@h JOIN #d"]);
+        rejoins.disconnected(&state);
+
+        let state = connection(&[]);
+        rejoins.incoming(&message(":me!u@h PART #A"), &state);
+        rejoins.incoming(&message(":op!u@h KICK #b me :bye"), &state);
+        rejoins.incoming(&message(":op!u@h KICK #c other :bye"), &state);
+        rejoins.incoming(&message(":server 475 me #d :Cannot join channel (+k)"), &state);
+
+        assert_eq!(rejoins.list(&state), channels(&[("#c", Some("three"))]));
+        assert_eq!(rejoins.keys, vec![("#c".to_owned(), "three".to_owned())]);
+    }
+}
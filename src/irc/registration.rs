@@ -0,0 +1,638 @@
+//! The registration module contains the future that performs the connection
+//! registration handshake (CAP, PASS, NICK and USER) with a remote IRC host.
+
+extern crate futures;
+extern crate tokio_io;
+
+use std::collections::VecDeque;
+
+use futures::{Async, AsyncSink, Future, Poll, Sink, Stream};
+use self::tokio_io::{AsyncRead, AsyncWrite};
+
+use irc::capabilities::Capabilities;
+use irc::command::{Banned, ErroneousNickname, NicknameInUse, PasswordMismatch, Welcome};
+use irc::config::Config;
+use irc::error::{Error, ErrorKind};
+use irc::message::Message;
+use irc::sasl::{Authenticator, Step};
+use irc::transport::IrcTransport;
+
+/// Represents a future, that when resolved provides an `IrcTransport` that has
+/// been accepted by the server.
+///
+/// The future sends the registration commands described by a `Config` and
+/// waits for the WELCOME (001) numeric. When the `Config` lists capabilities,
+/// capability negotiation is started with `CAP LS 302` and ended with
+/// `CAP END` once the server has replied to every request. When the `Config`
+/// contains SASL credentials, the `sasl` capability is requested and the
+/// SASL exchange is completed before `CAP END` is sent; the future fails with
+/// `SaslUnavailable` if the server completes the registration without it. Whenever the server rejects the
+/// current nickname, the next alternate nickname is tried. Any message
+/// received before the registration completes, including the WELCOME
+/// numeric itself, is handed back to the transport so that it's still
+/// available to the consumer of the stream.
+pub struct Registration<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    transport: Option<IrcTransport<T>>,
+    alt_nicks: VecDeque<String>,
+    nick: String,
+    outgoing: VecDeque<Message>,
+    received: Vec<Message>,
+    negotiating: bool,
+    authenticator: Option<Authenticator>,
+    authenticating: bool,
+}
+
+impl<T> Registration<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    /// Create a new `Registration` that will register `transport` using the details in `config`.
+    pub fn new(mut transport: IrcTransport<T>, config: &Config) -> Result<Registration<T>, Error> {
+        let mut outgoing = VecDeque::new();
+        let negotiating = !config.capabilities.is_empty() || config.sasl.is_some();
+
+        if negotiating {
+            let mut capabilities = config.capabilities.clone();
+
+            if config.sasl.is_some() && !capabilities.iter().any(|name| name == "sasl") {
+                capabilities.push("sasl".to_owned());
+            }
+
+            transport.set_capabilities(Capabilities::new(&capabilities));
+            outgoing.push_back(Message::cap("LS", Some("302"))?);
+        }
+
+        if let Some(ref password) = config.password {
+            outgoing.push_back(Message::pass(password)?);
+        }
+
+        outgoing.push_back(Message::nick(&config.nick)?);
+        outgoing.push_back(Message::user(&config.username, &config.realname)?);
+
+        Ok(Registration {
+            transport: Some(transport),
+            alt_nicks: config.alt_nicks.iter().cloned().collect(),
+            nick: config.nick.clone(),
+            outgoing: outgoing,
+            received: Vec::new(),
+            negotiating: negotiating,
+            authenticator: config.sasl.clone().map(Authenticator::new),
+            authenticating: false,
+        })
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        let transport = self.transport.as_mut().expect("polled Registration after completion");
+
+        while let Some(message) = self.outgoing.pop_front() {
+            if let AsyncSink::NotReady(message) = transport.start_send(message)? {
+                self.outgoing.push_front(message);
+                break;
+            }
+        }
+
+        transport.poll_complete()?;
+
+        Ok(())
+    }
+
+    fn next_nick(&mut self, rejected: Error) -> Result<(), Error> {
+        match self.alt_nicks.pop_front() {
+            Some(nick) => {
+                self.outgoing.push_back(Message::nick(&nick)?);
+                self.nick = nick;
+
+                Ok(())
+            }
+            None => Err(rejected),
+        }
+    }
+
+    // Called once the server has replied to every capability request. Starts
+    // the SASL exchange when it's configured, otherwise ends the negotiation.
+    fn settle(&mut self) -> Result<(), Error> {
+        let start = match self.authenticator {
+            Some(ref authenticator) => {
+                let capabilities = self.transport.as_ref().unwrap().capabilities();
+
+                if !capabilities.is_enabled("sasl") {
+                    return Err(ErrorKind::SaslUnavailable.into());
+                }
+
+                let mechanism = authenticator.mechanism();
+
+                // NOTE: Servers implementing CAP 302 advertise the supported mechanisms
+                // as the value of the capability, so an unsupported mechanism can fail early.
+                if let Some(mechanisms) = capabilities.value("sasl") {
+                    if !mechanisms.split(',').any(|supported| supported == mechanism) {
+                        return Err(ErrorKind::SaslMechanismUnsupported(mechanisms.to_owned()).into());
+                    }
+                }
+
+                authenticator.start()?
+            }
+            None => {
+                self.negotiating = false;
+                self.outgoing.push_back(Message::cap("END", None)?);
+
+                return Ok(());
+            }
+        };
+
+        self.outgoing.push_back(start);
+        self.authenticating = true;
+
+        Ok(())
+    }
+
+    fn authenticate(&mut self, message: &Message) -> Result<(), Error> {
+        match self.authenticator.as_mut().unwrap().handle(message)? {
+            Step::Respond(messages) => self.outgoing.extend(messages),
+            Step::Success => {
+                self.authenticating = false;
+                self.authenticator = None;
+                self.settle()?;
+            }
+            Step::Pending => {}
+        }
+
+        Ok(())
+    }
+
+    fn handle(&mut self, message: &Message) -> Result<bool, Error> {
+        if message.command::<Welcome>().is_some() {
+            // NOTE: A server that registers us before the SASL exchange has completed either
+            // doesn't support CAP or ignored it, so we would silently end up unauthenticated.
+            if self.authenticator.is_some() {
+                return Err(ErrorKind::SaslUnavailable.into());
+            }
+
+            return Ok(true);
+        }
+
+        if message.command::<NicknameInUse>().is_some() {
+            let rejected = ErrorKind::NicknameInUse(self.nick.clone()).into();
+            self.next_nick(rejected)?;
+        } else if message.command::<ErroneousNickname>().is_some() {
+            let rejected = ErrorKind::ErroneousNickname(self.nick.clone()).into();
+            self.next_nick(rejected)?;
+        } else if message.command::<PasswordMismatch>().is_some() {
+            return Err(ErrorKind::PasswordMismatch.into());
+        } else if let Some(Banned(_, reason)) = message.command::<Banned>() {
+            return Err(ErrorKind::Banned(reason.to_owned()).into());
+        }
+
+        Ok(false)
+    }
+}
+
+impl<T> Future for Registration<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    type Item = IrcTransport<T>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            self.flush()?;
+
+            let message = {
+                let transport = self.transport.as_mut().expect("polled Registration after completion");
+
+                match transport.poll() {
+                    Ok(Async::Ready(Some(message))) => message,
+                    Ok(Async::Ready(None)) => return Err(ErrorKind::ConnectionReset.into()),
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(ref error) if error.is_recoverable() => continue,
+                    Err(error) => return Err(error),
+                }
+            };
+
+            let registered = self.handle(&message)?;
+
+            if self.authenticating {
+                self.authenticate(&message)?;
+            } else if self.negotiating && self.transport.as_ref().unwrap().capabilities().is_settled() {
+                self.settle()?;
+            }
+
+            self.received.push(message);
+
+            if registered {
+                let mut transport = self.transport.take().unwrap();
+
+                for message in self.received.drain(..) {
+                    transport.requeue(message);
+                }
+
+                return Ok(Async::Ready(transport));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate base64;
+    extern crate hmac;
+    extern crate sha2;
+
+    use std::cell::RefCell;
+    use std::io::{self, Read, Write};
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    use self::base64::Engine;
+    use self::base64::engine::general_purpose::STANDARD;
+    use self::hmac::{Hmac, KeyInit, Mac};
+    use self::sha2::{Digest, Sha256};
+    use futures::executor::{self, Notify, Spawn};
+    use tokio_core::reactor::Core;
+
+    use irc::codec::IrcCodec;
+    use irc::sasl::Sasl;
+    use super::*;
+
+    // One end of an in-memory connection. Lines received by the client are
+    // queued with `receive`, and lines sent by the client are taken with `sent`.
+    #[derive(Clone, Default)]
+    struct Connection {
+        buffers: Rc<RefCell<(Vec<u8>, Vec<u8>)>>,
+    }
+
+    impl Connection {
+        fn receive(&self, line: &str) {
+            let mut buffers = self.buffers.borrow_mut();
+            buffers.0.extend_from_slice(line.as_bytes());
+            buffers.0.extend_from_slice(b"\r\n");
+        }
+
+        fn sent(&self) -> Vec<String> {
+            let outgoing: Vec<u8> = self.buffers.borrow_mut().1.drain(..).collect();
+
+            String::from_utf8(outgoing)
+                .unwrap()
+                .split_terminator("\r\n")
+                .map(|line| line.to_owned())
+                .collect()
+        }
+    }
+
+    impl Read for Connection {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            let mut buffers = self.buffers.borrow_mut();
+
+            if buffers.0.is_empty() {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+
+            let length = buffer.len().min(buffers.0.len());
+            buffer[..length].copy_from_slice(&buffers.0[..length]);
+            buffers.0.drain(..length);
+
+            Ok(length)
+        }
+    }
+
+    impl Write for Connection {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.buffers.borrow_mut().1.extend_from_slice(buffer);
+            Ok(buffer.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl AsyncRead for Connection {}
+
+    impl AsyncWrite for Connection {
+        fn shutdown(&mut self) -> Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    struct Noop;
+
+    impl Notify for Noop {
+        fn notify(&self, _: usize) {}
+    }
+
+    // A registration in progress over an in-memory connection. The `Core` is
+    // only kept alive for the timers of the transport and is never run.
+    struct Server {
+        _core: Core,
+        connection: Connection,
+        registration: Spawn<Registration<Connection>>,
+    }
+
+    impl Server {
+        fn new(config: Config) -> Server {
+            let core = Core::new().unwrap();
+            let connection = Connection::default();
+            let framed = connection.clone().framed(IrcCodec::default());
+            let transport = IrcTransport::new(framed, &core.handle(), &config).unwrap();
+
+            Server {
+                _core: core,
+                connection: connection,
+                registration: executor::spawn(Registration::new(transport, &config).unwrap()),
+            }
+        }
+
+        fn poll(&mut self) -> Poll<IrcTransport<Connection>, Error> {
+            self.registration.poll_future_notify(&Arc::new(Noop), 0)
+        }
+
+        // Sends `line` to the client and returns the lines it sent in response.
+        fn reply(&mut self, line: &str) -> Vec<String> {
+            self.connection.receive(line);
+
+            match self.poll() {
+                Ok(Async::NotReady) => self.connection.sent(),
+                Ok(Async::Ready(_)) => panic!("the registration completed after {:?}", line),
+                Err(error) => panic!("the registration failed after {:?}: {}", line, error),
+            }
+        }
+
+        // Sends `line` to the client and returns the error the registration failed with.
+        fn fail(&mut self, line: &str) -> ErrorKind {
+            self.connection.receive(line);
+
+            match self.poll() {
+                Err(error) => error.0,
+                Ok(_) => panic!("the registration did not fail after {:?}", line),
+            }
+        }
+
+        // Sends the WELCOME numeric and returns the registered transport.
+        fn welcome(&mut self) -> IrcTransport<Connection> {
+            self.connection.receive(":irc.test 001 nick :Welcome");
+
+            match self.poll() {
+                Ok(Async::Ready(transport)) => transport,
+                Ok(Async::NotReady) => panic!("the registration did not complete"),
+                Err(error) => panic!("the registration failed: {}", error),
+            }
+        }
+    }
+
+    fn config(sasl: Option<Sasl>) -> Config {
+        Config {
+            capabilities: vec!["multi-prefix".to_owned(), "server-time".to_owned()],
+            sasl: sasl,
+            ..Config::new("nick")
+        }
+    }
+
+    fn decode(line: &str) -> String {
+        let payload = line.trim_start_matches("AUTHENTICATE ");
+        String::from_utf8(STANDARD.decode(payload).unwrap()).unwrap()
+    }
+
+    fn authenticate(payload: &str) -> String {
+        format!("AUTHENTICATE {}", STANDARD.encode(payload))
+    }
+
+    fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+        mac.update(data);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    // Negotiates the `sasl` capability and returns the line starting the exchange.
+    fn start_sasl(server: &mut Server) -> Vec<String> {
+        assert!(server.poll().unwrap().is_not_ready());
+        assert_eq!(server.connection.sent(), vec!["CAP LS 302", "NICK nick", "USER nick 0 * nick"]);
+        assert_eq!(server.reply(":irc.test CAP * LS :sasl=PLAIN,EXTERNAL,SCRAM-SHA-256"), vec!["CAP REQ sasl"]);
+
+        server.reply(":irc.test CAP nick ACK sasl")
+    }
+
+    #[test]
+    fn registers_without_capabilities() {
+        let mut server = Server::new(Config::new("nick"));
+
+        assert!(server.poll().unwrap().is_not_ready());
+        assert_eq!(server.connection.sent(), vec!["NICK nick", "USER nick 0 * nick"]);
+
+        let transport = server.welcome();
+        let mut transport = executor::spawn(transport);
+
+        match transport.poll_stream_notify(&Arc::new(Noop), 0).unwrap() {
+            Async::Ready(Some(message)) => assert_eq!(message.raw_command(), "001"),
+            _ => panic!("the WELCOME numeric was not handed back"),
+        }
+
+        assert_eq!(transport.get_ref().state().nick(), Some("nick"));
+    }
+
+    #[test]
+    fn falls_back_on_alternate_nicknames() {
+        let mut server = Server::new(Config {
+            alt_nicks: vec!["nick_".to_owned()],
+            ..Config::new("nick")
+        });
+
+        assert!(server.poll().unwrap().is_not_ready());
+        server.connection.sent();
+
+        assert_eq!(server.reply(":irc.test 433 * nick :Nickname is already in use"), vec!["NICK nick_"]);
+
+        match server.fail(":irc.test 433 * nick_ :Nickname is already in use") {
+            ErrorKind::NicknameInUse(ref nick) => assert_eq!(nick, "nick_"),
+            error => panic!("unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn fails_on_password_mismatch_and_ban() {
+        let mut server = Server::new(Config::new("nick"));
+
+        match server.fail(":irc.test 464 * :Password incorrect") {
+            ErrorKind::PasswordMismatch => {}
+            error => panic!("unexpected error: {}", error),
+        }
+
+        let mut server = Server::new(Config::new("nick"));
+
+        match server.fail(":irc.test 465 * :You are banned") {
+            ErrorKind::Banned(ref reason) => assert_eq!(reason, "You are banned"),
+            error => panic!("unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn negotiates_capabilities() {
+        let mut server = Server::new(config(None));
+
+        assert!(server.poll().unwrap().is_not_ready());
+        assert_eq!(server.connection.sent(), vec!["CAP LS 302", "NICK nick", "USER nick 0 * nick"]);
+
+        assert!(server.reply(":irc.test CAP * LS * :multi-prefix away-notify").is_empty());
+        assert_eq!(server.reply(":irc.test CAP * LS :server-time"), vec!["CAP REQ :multi-prefix server-time"]);
+        assert!(server.reply(":irc.test CAP nick NAK :server-time").is_empty());
+        assert_eq!(server.reply(":irc.test CAP nick ACK :multi-prefix"), vec!["CAP END"]);
+
+        let transport = server.welcome();
+
+        assert!(transport.capabilities().is_enabled("multi-prefix"));
+        assert!(!transport.capabilities().is_enabled("server-time"));
+    }
+
+    #[test]
+    fn authenticates_with_plain() {
+        let mut server = Server::new(config(Some(Sasl::Plain {
+            username: "user".to_owned(),
+            password: "secret".to_owned(),
+        })));
+
+        assert_eq!(start_sasl(&mut server), vec!["AUTHENTICATE PLAIN"]);
+
+        let response = server.reply("AUTHENTICATE +");
+        assert_eq!(response.len(), 1);
+        assert_eq!(decode(&response[0]), "user\0user\0secret");
+
+        assert!(server.reply(":irc.test 900 nick nick!user@host user :You are now logged in as user").is_empty());
+        assert_eq!(server.reply(":irc.test 903 nick :SASL authentication successful"), vec!["CAP END"]);
+
+        server.welcome();
+    }
+
+    #[test]
+    fn authenticates_with_external() {
+        let mut server = Server::new(config(Some(Sasl::External)));
+
+        assert_eq!(start_sasl(&mut server), vec!["AUTHENTICATE EXTERNAL"]);
+        assert_eq!(server.reply("AUTHENTICATE +"), vec!["AUTHENTICATE +"]);
+        assert_eq!(server.reply(":irc.test 903 nick :SASL authentication successful"), vec!["CAP END"]);
+
+        server.welcome();
+    }
+
+    #[test]
+    fn fails_when_registered_before_sasl() {
+        let mut server = Server::new(config(Some(Sasl::External)));
+
+        assert!(server.poll().unwrap().is_not_ready());
+
+        match server.fail(":irc.test 001 nick :Welcome") {
+            ErrorKind::SaslUnavailable => {}
+            error => panic!("unexpected error: {}", error),
+        }
+    }
+
+    // Runs a SCRAM-SHA-256 exchange up to the server's final message, which is
+    // built with `server_key` in place of the key derived from the password.
+    fn scram(server_key: Option<&[u8]>) -> Server {
+        let mut server = Server::new(config(Some(Sasl::ScramSha256 {
+            username: "user".to_owned(),
+            password: "secret".to_owned(),
+        })));
+
+        assert_eq!(start_sasl(&mut server), vec!["AUTHENTICATE SCRAM-SHA-256"]);
+
+        let client_first = decode(&server.reply("AUTHENTICATE +")[0]);
+        assert!(client_first.starts_with("n,,n=user,r="));
+
+        let client_first_bare = &client_first[3..];
+        let nonce = format!("{}server", &client_first_bare[9..]);
+        let salt = b"salt";
+        let server_first = format!("r={},s={},i=4096", nonce, STANDARD.encode(salt));
+
+        let client_final = decode(&server.reply(&authenticate(&server_first))[0]);
+        let without_proof = format!("c=biws,r={}", nonce);
+        assert!(client_final.starts_with(&format!("{},p=", without_proof)));
+
+        // The salted password is the `Hi` function of RFC 5802.
+        let mut block = salt.to_vec();
+        block.extend_from_slice(&[0, 0, 0, 1]);
+
+        let mut previous = hmac(b"secret", &block);
+        let mut salted_password = previous.clone();
+
+        for _ in 1..4096 {
+            previous = hmac(b"secret", &previous);
+
+            for (salted, previous) in salted_password.iter_mut().zip(previous.iter()) {
+                *salted ^= *previous;
+            }
+        }
+
+        let auth_message = format!("{},{},{}", client_first_bare, server_first, without_proof);
+
+        // The proof sent by the client has to match the stored key.
+        let client_key = hmac(&salted_password, b"Client Key");
+        let client_signature = hmac(&Sha256::digest(&client_key), auth_message.as_bytes());
+        let proof: Vec<u8> = client_key.iter().zip(client_signature.iter()).map(|(a, b)| a ^ b).collect();
+        assert_eq!(&client_final[without_proof.len() + 3..], STANDARD.encode(&proof));
+
+        let derived_key = hmac(&salted_password, b"Server Key");
+        let server_signature = hmac(server_key.unwrap_or(&derived_key), auth_message.as_bytes());
+        let server_final = format!("v={}", STANDARD.encode(&server_signature));
+
+        server.connection.receive(&authenticate(&server_final));
+        server
+    }
+
+    #[test]
+    fn authenticates_with_scram() {
+        let mut server = scram(None);
+
+        let logged_in = ":irc.test 900 nick nick!user@host user :You are now logged in as user";
+
+        assert_eq!(server.reply(logged_in), vec!["AUTHENTICATE +"]);
+        assert_eq!(server.reply(":irc.test 903 nick :SASL authentication successful"), vec!["CAP END"]);
+
+        server.welcome();
+    }
+
+    #[test]
+    fn rejects_wrong_scram_server_signature() {
+        let mut server = scram(Some(b"wrong key"));
+
+        match server.fail(":irc.test 903 nick :SASL authentication successful") {
+            ErrorKind::SaslFailed(_) => {}
+            error => panic!("unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn rejects_success_before_scram_server_signature() {
+        let mut server = Server::new(config(Some(Sasl::ScramSha256 {
+            username: "user".to_owned(),
+            password: "secret".to_owned(),
+        })));
+
+        assert_eq!(start_sasl(&mut server), vec!["AUTHENTICATE SCRAM-SHA-256"]);
+        server.reply("AUTHENTICATE +");
+
+        match server.fail(":irc.test 903 nick :SASL authentication successful") {
+            ErrorKind::SaslFailed(_) => {}
+            error => panic!("unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn rejects_excessive_scram_iterations() {
+        let mut server = Server::new(config(Some(Sasl::ScramSha256 {
+            username: "user".to_owned(),
+            password: "secret".to_owned(),
+        })));
+
+        assert_eq!(start_sasl(&mut server), vec!["AUTHENTICATE SCRAM-SHA-256"]);
+
+        let client_first = decode(&server.reply("AUTHENTICATE +")[0]);
+        let server_first = format!("r={}server,s=c2FsdA==,i=4294967295", &client_first[12..]);
+
+        match server.fail(&authenticate(&server_first)) {
+            ErrorKind::SaslFailed(_) => {}
+            error => panic!("unexpected error: {}", error),
+        }
+    }
+}
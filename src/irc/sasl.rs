@@ -0,0 +1,334 @@
+//! The sasl module contains everything needed to authenticate with a remote
+//! IRC host using SASL during connection registration.
+//!
+//! The PLAIN, EXTERNAL and SCRAM-SHA-256 mechanisms are supported. EXTERNAL
+//! relies on the server identifying the client by other means, usually a
+//! client certificate presented during the TLS handshake.
+
+extern crate base64;
+extern crate hmac;
+extern crate rand;
+extern crate sha2;
+
+use self::base64::Engine;
+use self::base64::engine::general_purpose::STANDARD;
+use self::hmac::{Hmac, KeyInit, Mac};
+use self::sha2::{Digest, Sha256};
+
+use irc::command::{Authenticate, LoggedIn, NickLocked, SaslAborted, SaslAlready, SaslFail,
+                   SaslMechanisms, SaslSuccess, SaslTooLong};
+use irc::error::{Error, ErrorKind, Result};
+use irc::message::Message;
+
+// The largest chunk of base64 encoded data sent or received in a single AUTHENTICATE message.
+const CHUNK_LENGTH: usize = 400;
+
+// The number of random bytes used for the SCRAM client nonce.
+const NONCE_LENGTH: usize = 18;
+
+// The largest SCRAM iteration count accepted from the server, so that a malicious
+// server can't keep the client busy hashing the password.
+const MAX_ITERATIONS: u32 = 100_000;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The SASL mechanism and credentials used to authenticate with the server.
+#[derive(Clone, Debug)]
+pub enum Sasl {
+    /// Authenticate with a plain text username and password.
+    Plain { username: String, password: String },
+    /// Authenticate using credentials established outside of SASL, such as a TLS client certificate.
+    External,
+    /// Authenticate with a username and password without sending the password to the server.
+    ScramSha256 { username: String, password: String },
+}
+
+impl Sasl {
+    /// Provides the name of the mechanism as sent in the `AUTHENTICATE` command.
+    pub fn mechanism(&self) -> &'static str {
+        match *self {
+            Sasl::Plain { .. } => "PLAIN",
+            Sasl::External => "EXTERNAL",
+            Sasl::ScramSha256 { .. } => "SCRAM-SHA-256",
+        }
+    }
+}
+
+/// The result of feeding a message into an `Authenticator`.
+#[derive(Debug)]
+pub enum Step {
+    /// The message was not part of the exchange, or more data is needed from the server.
+    Pending,
+    /// The given messages should be sent to the server.
+    Respond(Vec<Message>),
+    /// The server has accepted the authentication.
+    Success,
+}
+
+enum Scram {
+    Initial,
+    ClientFirst { client_first_bare: String, nonce: String },
+    ClientFinal { server_signature: Vec<u8> },
+    Verified,
+}
+
+/// Drives a SASL exchange with the server for a configured mechanism.
+///
+/// The `Authenticator` reassembles challenges that the server splits across
+/// several `AUTHENTICATE` messages and splits responses into 400 byte chunks,
+/// as required by the IRCv3 SASL specification.
+pub struct Authenticator {
+    sasl: Sasl,
+    challenge: String,
+    scram: Scram,
+    mechanisms: Option<String>,
+}
+
+impl Authenticator {
+    /// Create a new `Authenticator` for the given mechanism and credentials.
+    pub fn new(sasl: Sasl) -> Authenticator {
+        Authenticator {
+            sasl: sasl,
+            challenge: String::new(),
+            scram: Scram::Initial,
+            mechanisms: None,
+        }
+    }
+
+    /// Provides the name of the mechanism used by this exchange.
+    pub fn mechanism(&self) -> &'static str {
+        self.sasl.mechanism()
+    }
+
+    /// Constructs the `AUTHENTICATE` message that starts the exchange.
+    pub fn start(&self) -> Result<Message> {
+        Message::authenticate(self.sasl.mechanism())
+    }
+
+    /// Feeds a message received from the server into the exchange.
+    pub fn handle(&mut self, message: &Message) -> Result<Step> {
+        if let Some(Authenticate(chunk)) = message.command::<Authenticate>() {
+            if chunk != "+" {
+                self.challenge.push_str(chunk);
+            }
+
+            if chunk.len() == CHUNK_LENGTH {
+                return Ok(Step::Pending);
+            }
+
+            let challenge = STANDARD
+                .decode(self.challenge.as_bytes())
+                .map_err(|_| Error::from(ErrorKind::SaslFailed("The server sent an invalid challenge.".to_owned())))?;
+            self.challenge.clear();
+
+            let response = self.respond(&challenge)?;
+
+            return Ok(Step::Respond(encode(&response)?));
+        }
+
+        if message.command::<SaslSuccess>().is_some() {
+            // NOTE: With SCRAM the server has to prove that it knows the password as well,
+            // so its word alone isn't enough to consider the exchange successful.
+            let verified = match (&self.sasl, &self.scram) {
+                (&Sasl::ScramSha256 { .. }, &Scram::Verified) => true,
+                (&Sasl::ScramSha256 { .. }, _) => false,
+                _ => true,
+            };
+
+            if !verified {
+                return Err(ErrorKind::SaslFailed("The server did not prove its identity.".to_owned()).into());
+            }
+
+            return Ok(Step::Success);
+        }
+
+        if message.command::<SaslAlready>().is_some() {
+            return Ok(Step::Success);
+        }
+
+        if message.command::<LoggedIn>().is_some() {
+            return Ok(Step::Pending);
+        }
+
+        if let Some(SaslMechanisms(_, mechanisms, _)) = message.command::<SaslMechanisms>() {
+            self.mechanisms = Some(mechanisms.to_owned());
+            return Ok(Step::Pending);
+        }
+
+        if let Some(SaslFail(_, reason)) = message.command::<SaslFail>() {
+            return Err(match self.mechanisms.take() {
+                Some(mechanisms) => ErrorKind::SaslMechanismUnsupported(mechanisms).into(),
+                None => ErrorKind::SaslFailed(reason.to_owned()).into(),
+            });
+        }
+
+        if message.command::<SaslTooLong>().is_some() {
+            return Err(ErrorKind::SaslFailed("The authentication message was too long.".to_owned()).into());
+        }
+
+        if message.command::<SaslAborted>().is_some() {
+            return Err(ErrorKind::SaslAborted.into());
+        }
+
+        if message.command::<NickLocked>().is_some() {
+            return Err(ErrorKind::NickLocked.into());
+        }
+
+        Ok(Step::Pending)
+    }
+
+    fn respond(&mut self, challenge: &[u8]) -> Result<Vec<u8>> {
+        match self.sasl {
+            Sasl::Plain { ref username, ref password } => {
+                Ok(format!("{}\0{}\0{}", username, username, password).into_bytes())
+            }
+            Sasl::External => Ok(Vec::new()),
+            Sasl::ScramSha256 { ref username, ref password } => {
+                let (response, scram) = scram_step(&self.scram, username, password, challenge)?;
+                self.scram = scram;
+
+                Ok(response)
+            }
+        }
+    }
+}
+
+// Splits a response into base64 encoded AUTHENTICATE messages. An empty response,
+// or a response that is an exact multiple of the chunk length, is terminated by `+`.
+fn encode(response: &[u8]) -> Result<Vec<Message>> {
+    let encoded = STANDARD.encode(response);
+    let mut messages = Vec::new();
+
+    for chunk in encoded.as_bytes().chunks(CHUNK_LENGTH) {
+        // The base64 alphabet is ASCII, so every chunk boundary is a valid str boundary.
+        messages.push(Message::authenticate(::std::str::from_utf8(chunk).unwrap())?);
+    }
+
+    if encoded.len().is_multiple_of(CHUNK_LENGTH) {
+        messages.push(Message::authenticate("+")?);
+    }
+
+    Ok(messages)
+}
+
+fn scram_step(state: &Scram, username: &str, password: &str, challenge: &[u8]) -> Result<(Vec<u8>, Scram)> {
+    match *state {
+        Scram::Initial => {
+            let mut random = [0u8; NONCE_LENGTH];
+            rand::fill(&mut random[..]);
+
+            let nonce = STANDARD.encode(&random);
+            let client_first_bare = format!("n={},r={}", scram_escape(username), nonce);
+            let response = format!("n,,{}", client_first_bare);
+
+            Ok((
+                response.into_bytes(),
+                Scram::ClientFirst {
+                    client_first_bare: client_first_bare,
+                    nonce: nonce,
+                },
+            ))
+        }
+        Scram::ClientFirst { ref client_first_bare, ref nonce } => {
+            let server_first = ::std::str::from_utf8(challenge).map_err(|_| scram_error())?;
+
+            let mut server_nonce = None;
+            let mut salt = None;
+            let mut iterations = None;
+
+            for attribute in server_first.split(',') {
+                if let Some(value) = attribute.strip_prefix("r=") {
+                    server_nonce = Some(value);
+                } else if let Some(value) = attribute.strip_prefix("s=") {
+                    salt = STANDARD.decode(value).ok();
+                } else if let Some(value) = attribute.strip_prefix("i=") {
+                    iterations = value.parse::<u32>().ok();
+                }
+            }
+
+            let server_nonce = match server_nonce {
+                Some(server_nonce) if server_nonce.starts_with(nonce.as_str()) => server_nonce,
+                _ => return Err(scram_error()),
+            };
+
+            let (salt, iterations) = match (salt, iterations) {
+                (Some(salt), Some(iterations)) if iterations > 0 && iterations <= MAX_ITERATIONS => (salt, iterations),
+                _ => return Err(scram_error()),
+            };
+
+            let salted_password = hi(password.as_bytes(), &salt, iterations);
+            let client_key = hmac(&salted_password, b"Client Key");
+            let stored_key = Sha256::digest(&client_key);
+            let client_final_without_proof = format!("c=biws,r={}", server_nonce);
+            let auth_message = format!("{},{},{}", client_first_bare, server_first, client_final_without_proof);
+
+            let client_signature = hmac(&stored_key, auth_message.as_bytes());
+            let proof: Vec<u8> = client_key
+                .iter()
+                .zip(client_signature.iter())
+                .map(|(key, signature)| key ^ signature)
+                .collect();
+
+            let server_key = hmac(&salted_password, b"Server Key");
+            let server_signature = hmac(&server_key, auth_message.as_bytes());
+
+            let response = format!("{},p={}", client_final_without_proof, STANDARD.encode(&proof));
+
+            Ok((
+                response.into_bytes(),
+                Scram::ClientFinal {
+                    server_signature: server_signature,
+                },
+            ))
+        }
+        Scram::ClientFinal { ref server_signature } => {
+            let server_final = ::std::str::from_utf8(challenge).map_err(|_| scram_error())?;
+
+            let verifier = server_final
+                .split(',')
+                .find_map(|attribute| attribute.strip_prefix("v="))
+                .and_then(|verifier| STANDARD.decode(verifier).ok());
+
+            match verifier {
+                Some(ref verifier) if verifier == server_signature => Ok((Vec::new(), Scram::Verified)),
+                _ => Err(ErrorKind::SaslFailed("The server signature did not match.".to_owned()).into()),
+            }
+        }
+        Scram::Verified => Ok((Vec::new(), Scram::Verified)),
+    }
+}
+
+fn scram_error() -> Error {
+    ErrorKind::SaslFailed("The server sent an invalid SCRAM challenge.".to_owned()).into()
+}
+
+// Escapes the characters that are reserved in SCRAM attribute values.
+fn scram_escape(value: &str) -> String {
+    value.replace('=', "=3D").replace(',', "=2C")
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+
+    mac.finalize().into_bytes().to_vec()
+}
+
+// The PBKDF2 based `Hi` function from RFC 5802, using HMAC-SHA-256.
+fn hi(password: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
+    let mut block = salt.to_vec();
+    block.extend_from_slice(&[0, 0, 0, 1]);
+
+    let mut previous = hmac(password, &block);
+    let mut result = previous.clone();
+
+    for _ in 1..iterations {
+        previous = hmac(password, &previous);
+
+        for (result, previous) in result.iter_mut().zip(previous.iter()) {
+            *result ^= *previous;
+        }
+    }
+
+    result
+}
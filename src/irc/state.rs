@@ -0,0 +1,783 @@
+//! The state module contains the `State` type which keeps track of the channels
+//! the client is in and the users it shares them with, from the messages received
+//! from the server.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Values;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use irc::casemap::{Channel, Nick};
+use irc::command::{AccountChange, Away, AwayReply, ChannelModeIs, ChgHost, EndNamesReply, Join, Kick,
+                   LoggedIn, LoggedOut, Mode, NamesReply, NoTopic, NowAway, Part, Quit, SetName,
+                   Topic as TopicCommand, TopicReply, TopicWhoTime, UnAway, Welcome, WhoReply};
+use irc::command;
+use irc::isupport::ISupport;
+use irc::message::Message;
+use irc::mode::{self, ChannelModes, ModeKind};
+use irc::tag::Account;
+use command_match;
+
+/// The topic of a channel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Topic {
+    /// The text of the topic.
+    pub text: String,
+    /// The nickname or mask of whoever set the topic, if known.
+    pub setter: Option<String>,
+    /// When the topic was set, in seconds since the Unix epoch, if known.
+    pub time: Option<u64>,
+}
+
+/// A user in a channel, along with their membership modes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    nick: String,
+    modes: String,
+}
+
+impl Member {
+    /// Get the nickname of the member.
+    pub fn nick(&self) -> &str {
+        &self.nick
+    }
+
+    /// Get the membership modes of the member, such as `ov`, in order of rank.
+    pub fn modes(&self) -> &str {
+        &self.modes
+    }
+
+    /// Returns true if the member has the membership mode `mode`.
+    pub fn has_mode(&self, mode: char) -> bool {
+        self.modes.contains(mode)
+    }
+
+    /// Get the prefix of the member's highest ranking membership mode, such as `@`.
+    pub fn prefix(&self, isupport: &ISupport) -> Option<char> {
+        let prefixes = isupport.prefix();
+
+        self.modes
+            .chars()
+            .next()
+            .and_then(|mode| prefixes.iter().find(|&&(prefix_mode, _)| prefix_mode == mode))
+            .map(|&(_, prefix)| prefix)
+    }
+
+    // Adds or removes a membership mode, keeping the modes in order of rank.
+    fn set_mode(&mut self, isupport: &ISupport, mode: char, adding: bool) {
+        let mut modes: Vec<char> = self.modes.chars().filter(|&existing| existing != mode).collect();
+
+        if adding {
+            modes.push(mode);
+        }
+
+        let prefixes = isupport.prefix();
+        modes.sort_by_key(|&mode| prefixes.iter().position(|&(prefix_mode, _)| prefix_mode == mode));
+
+        self.modes = modes.into_iter().collect();
+    }
+}
+
+/// A channel the client has joined.
+#[derive(Clone, Debug)]
+pub struct ChannelState {
+    name: String,
+    topic: Option<Topic>,
+    modes: ChannelModes,
+    members: HashMap<Nick, Member>,
+    synced: bool,
+}
+
+impl ChannelState {
+    fn new(name: &str) -> ChannelState {
+        ChannelState {
+            name: name.to_owned(),
+            topic: None,
+            modes: ChannelModes::new(),
+            members: HashMap::new(),
+            synced: false,
+        }
+    }
+
+    /// Get the name of the channel.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the topic of the channel, or `None` if there is no topic.
+    pub fn topic(&self) -> Option<&Topic> {
+        self.topic.as_ref()
+    }
+
+    /// Get the modes set on the channel.
+    pub fn modes(&self) -> &ChannelModes {
+        &self.modes
+    }
+
+    /// Iterates over the members of the channel.
+    pub fn members(&self) -> Values<Nick, Member> {
+        self.members.values()
+    }
+
+    /// Returns true once the server has sent the full member list of the channel.
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+}
+
+/// A user sharing a channel with the client, or the client itself.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct User {
+    /// The nickname of the user.
+    pub nick: String,
+    /// The username of the user, if known.
+    pub username: Option<String>,
+    /// The host of the user, if known.
+    pub host: Option<String>,
+    /// The real name of the user, if known.
+    pub realname: Option<String>,
+    /// The account the user is logged in to, if known.
+    pub account: Option<String>,
+    /// The away message of the user, or `None` if they are not known to be away.
+    pub away: Option<String>,
+}
+
+impl User {
+    fn new(nick: &str) -> User {
+        User {
+            nick: nick.to_owned(),
+            ..User::default()
+        }
+    }
+}
+
+/// Keeps track of our own nickname, the channels we're in, their topics, modes and
+/// members, and the users we share them with, by handling every message received
+/// from the server.
+///
+/// Names are compared using the case mapping advertised by the server, so a lookup
+/// of `#Rust` finds the channel joined as `#rust`.
+#[derive(Clone, Debug, Default)]
+pub struct State {
+    nick: Option<String>,
+    isupport: ISupport,
+    channels: HashMap<Channel, ChannelState>,
+    users: HashMap<Nick, User>,
+}
+
+impl State {
+    /// Create a new `State` that doesn't know about any channels or users.
+    pub fn new() -> State {
+        State::default()
+    }
+
+    /// Get our current nickname, once the server has accepted the registration.
+    pub fn nick(&self) -> Option<&str> {
+        self.nick.as_ref().map(|nick| nick.as_str())
+    }
+
+    /// Returns true if `nick` is our own nickname.
+    pub fn is_me(&self, nick: &str) -> bool {
+        self.nick.as_ref().map(|own| self.nick_key(own) == self.nick_key(nick)).unwrap_or(false)
+    }
+
+    /// Get the features advertised by the server with `RPL_ISUPPORT`.
+    pub fn isupport(&self) -> &ISupport {
+        &self.isupport
+    }
+
+    /// Iterates over the channels we're in.
+    pub fn channels(&self) -> Values<Channel, ChannelState> {
+        self.channels.values()
+    }
+
+    /// Get the channel with the given name, if we're in it.
+    pub fn channel(&self, name: &str) -> Option<&ChannelState> {
+        self.channels.get(&self.channel_key(name))
+    }
+
+    /// Get the member of `channel` with the given nickname.
+    pub fn member(&self, channel: &str, nick: &str) -> Option<&Member> {
+        self.channel(channel).and_then(|channel| channel.members.get(&self.nick_key(nick)))
+    }
+
+    /// Get the user with the given nickname, if we share a channel with them.
+    pub fn user(&self, nick: &str) -> Option<&User> {
+        self.users.get(&self.nick_key(nick))
+    }
+
+    /// Iterates over the users we share a channel with, including ourselves.
+    pub fn users(&self) -> Values<Nick, User> {
+        self.users.values()
+    }
+
+    /// Updates the state from a message received from the server.
+    pub fn handle(&mut self, message: &Message) {
+        if self.isupport.handle(message) {
+            self.rekey();
+            return;
+        }
+
+        let source = message.prefix().map(|(nick, username, host)| {
+            (nick.to_owned(), username.map(|username| username.to_owned()), host.map(|host| host.to_owned()))
+        });
+
+        if let Some((ref nick, _, _)) = source {
+            if let Some(Account(account)) = message.tag::<Account>() {
+                if let Some(user) = self.users.get_mut(&self.nick_key(nick)) {
+                    user.account = Some(account.into_owned());
+                }
+            }
+        }
+
+        if let Some(Welcome(nick, _)) = message.command::<Welcome>() {
+            self.nick = Some(nick.to_owned());
+            return;
+        }
+
+        if let Some((nick, username, host)) = source {
+            if self.handle_from(message, &nick) {
+                return;
+            }
+
+            if let Some(join) = message.command::<Join>() {
+                if self.is_me(&nick) {
+                    for channel in join.channels() {
+                        self.channels.insert(self.channel_key(channel), ChannelState::new(channel));
+                    }
+                }
+
+                for channel in join.channels() {
+                    self.add_member(channel, &nick);
+                }
+
+                let user = self.user_mut(&nick);
+                user.username = username.or_else(|| user.username.take());
+                user.host = host.or_else(|| user.host.take());
+
+                if join.2.is_some() {
+                    user.account = join.account().map(|account| account.to_owned());
+                    user.realname = join.2.map(|realname| realname.to_owned());
+                }
+
+                return;
+            }
+        }
+
+        command_match! {message => {
+            NamesReply(_, channel, names) => self.names(channel, &names),
+            EndNamesReply(channel, _) => {
+                let key = self.channel_key(channel);
+
+                if let Some(channel) = self.channels.get_mut(&key) {
+                    channel.synced = true;
+                }
+
+                // NOTE: A member list that replaced an earlier one may have left some users out.
+                self.forget_strangers();
+            },
+            TopicReply(_, channel, topic) => self.set_topic(channel, Some(topic), None, None),
+            TopicWhoTime(_, channel, setter, time) => {
+                let key = self.channel_key(channel);
+
+                if let Some(topic) = self.channels.get_mut(&key).and_then(|channel| channel.topic.as_mut()) {
+                    topic.setter = Some(setter.to_owned());
+                    topic.time = time.parse().ok();
+                }
+            },
+            NoTopic(_, channel, _) => self.set_topic(channel, None, None, None),
+            ChannelModeIs(_, channel, _) => self.channel_modes(message, channel),
+            AwayReply(_, nick, away) => {
+                if let Some(user) = self.users.get_mut(&self.nick_key(nick)) {
+                    user.away = Some(away.to_owned());
+                }
+            },
+            NowAway(_, _) => self.set_own_away(Some("")),
+            UnAway(_, _) => self.set_own_away(None),
+            LoggedIn(_, _, account, _) => self.set_own_account(Some(account)),
+            LoggedOut(_, _, _) => self.set_own_account(None),
+            WhoReply(_, _, username, host, _, nick, flags, realname) => {
+                if let Some(user) = self.users.get_mut(&self.nick_key(nick)) {
+                    user.username = Some(username.to_owned());
+                    user.host = Some(host.to_owned());
+
+                    // NOTE: The real name is preceded by the hop count.
+                    user.realname = realname.split_once(' ').map(|(_, realname)| realname.to_owned());
+
+                    if flags.starts_with('H') {
+                        user.away = None;
+                    } else if flags.starts_with('G') && user.away.is_none() {
+                        user.away = Some(String::new());
+                    }
+                }
+            },
+            _ => {}
+        }};
+    }
+
+    // Handles the commands whose effect depends on who sent them. Returns true if the
+    // message was handled.
+    fn handle_from(&mut self, message: &Message, nick: &str) -> bool {
+        command_match! {message => {
+            command::Nick(new_nick) => self.rename(nick, new_nick),
+            Part(channels, _) => {
+                for channel in command::list(channels) {
+                    self.remove_member(channel, nick);
+                }
+            },
+            kick @ Kick(..) => {
+                for (channel, kicked) in kick.kicks() {
+                    self.remove_member(channel, kicked);
+                }
+            },
+            Quit(_) => {
+                let key = self.nick_key(nick);
+
+                for channel in self.channels.values_mut() {
+                    channel.members.remove(&key);
+                }
+
+                self.users.remove(&key);
+            },
+            TopicCommand(channel, topic) => {
+                let time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_secs())
+                    .ok();
+
+                self.set_topic(channel, topic, Some(nick), time);
+            },
+            mode @ Mode(..) => self.mode(&mode),
+            Away(away) => {
+                if let Some(user) = self.users.get_mut(&self.nick_key(nick)) {
+                    user.away = away.map(|away| away.to_owned());
+                }
+            },
+            change @ AccountChange(..) => {
+                if let Some(user) = self.users.get_mut(&self.nick_key(nick)) {
+                    user.account = change.account().map(|account| account.to_owned());
+                }
+            },
+            ChgHost(username, host) => {
+                if let Some(user) = self.users.get_mut(&self.nick_key(nick)) {
+                    user.username = Some(username.to_owned());
+                    user.host = Some(host.to_owned());
+                }
+            },
+            SetName(realname) => {
+                if let Some(user) = self.users.get_mut(&self.nick_key(nick)) {
+                    user.realname = Some(realname.to_owned());
+                }
+            },
+            _ => return false
+        }};
+
+        true
+    }
+
+    fn nick_key(&self, nick: &str) -> Nick {
+        Nick::new(nick, &self.isupport.casemapping())
+    }
+
+    fn channel_key(&self, channel: &str) -> Channel {
+        Channel::new(channel, &self.isupport.casemapping())
+    }
+
+    // Rebuilds the keys of every map, in case the server changed its case mapping.
+    fn rekey(&mut self) {
+        let casemapping = self.isupport.casemapping();
+
+        let channels: Vec<ChannelState> = self.channels.drain().map(|(_, channel)| channel).collect();
+        for mut channel in channels {
+            let members: Vec<Member> = channel.members.drain().map(|(_, member)| member).collect();
+            for member in members {
+                channel.members.insert(Nick::new(&member.nick, &casemapping), member);
+            }
+
+            self.channels.insert(Channel::new(&channel.name, &casemapping), channel);
+        }
+
+        let users: Vec<User> = self.users.drain().map(|(_, user)| user).collect();
+        for user in users {
+            self.users.insert(Nick::new(&user.nick, &casemapping), user);
+        }
+    }
+
+    fn user_mut(&mut self, nick: &str) -> &mut User {
+        let key = self.nick_key(nick);
+        self.users.entry(key).or_insert_with(|| User::new(nick))
+    }
+
+    fn add_member(&mut self, channel: &str, nick: &str) {
+        let channel_key = self.channel_key(channel);
+        let nick_key = self.nick_key(nick);
+
+        if let Some(channel) = self.channels.get_mut(&channel_key) {
+            channel.members.entry(nick_key).or_insert_with(|| Member {
+                nick: nick.to_owned(),
+                modes: String::new(),
+            });
+        }
+    }
+
+    fn remove_member(&mut self, channel: &str, nick: &str) {
+        let channel_key = self.channel_key(channel);
+        let nick_key = self.nick_key(nick);
+
+        if self.is_me(nick) {
+            self.channels.remove(&channel_key);
+        } else if let Some(channel) = self.channels.get_mut(&channel_key) {
+            channel.members.remove(&nick_key);
+        }
+
+        self.forget_strangers();
+    }
+
+    // Forgets the users we no longer share a channel with.
+    fn forget_strangers(&mut self) {
+        let own = self.nick.as_ref().map(|nick| self.nick_key(nick));
+        let channels = &self.channels;
+
+        self.users.retain(|key, _| {
+            Some(key) == own.as_ref() || channels.values().any(|channel| channel.members.contains_key(key))
+        });
+    }
+
+    fn rename(&mut self, old: &str, new: &str) {
+        let old_key = self.nick_key(old);
+        let new_key = self.nick_key(new);
+
+        if self.is_me(old) {
+            self.nick = Some(new.to_owned());
+        }
+
+        if let Some(mut user) = self.users.remove(&old_key) {
+            user.nick = new.to_owned();
+            self.users.insert(new_key.clone(), user);
+        }
+
+        for channel in self.channels.values_mut() {
+            if let Some(mut member) = channel.members.remove(&old_key) {
+                member.nick = new.to_owned();
+                channel.members.insert(new_key.clone(), member);
+            }
+        }
+    }
+
+    fn names(&mut self, channel: &str, names: &[&str]) {
+        let prefixes = self.isupport.prefix();
+        let key = self.channel_key(channel);
+
+        // NOTE: A NAMES reply received after the list was complete replaces the member list.
+        // The users left out of the new list are forgotten once it is complete.
+        if let Some(channel) = self.channels.get_mut(&key) {
+            if channel.synced {
+                channel.members.clear();
+                channel.synced = false;
+            }
+        } else {
+            return;
+        }
+
+        for name in names {
+            let modes: String = name
+                .chars()
+                .take_while(|&c| prefixes.iter().any(|&(_, prefix)| prefix == c))
+                .filter_map(|c| prefixes.iter().find(|&&(_, prefix)| prefix == c).map(|&(mode, _)| mode))
+                .collect();
+
+            // NOTE: With `userhost-in-names` the names are full `nick!user@host` masks.
+            let mask = name.trim_start_matches(|c| prefixes.iter().any(|&(_, prefix)| prefix == c));
+            let (nick, username, host) = split_mask(mask);
+
+            let member = Member {
+                nick: nick.to_owned(),
+                modes: String::new(),
+            };
+
+            let nick_key = self.nick_key(nick);
+
+            if let Some(channel) = self.channels.get_mut(&key) {
+                let member = channel.members.entry(nick_key).or_insert(member);
+
+                for mode in modes.chars() {
+                    member.set_mode(&self.isupport, mode, true);
+                }
+            }
+
+            let user = self.user_mut(nick);
+
+            if username.is_some() {
+                user.username = username.map(|username| username.to_owned());
+                user.host = host.map(|host| host.to_owned());
+            }
+        }
+    }
+
+    fn set_topic(&mut self, channel: &str, topic: Option<&str>, setter: Option<&str>, time: Option<u64>) {
+        let key = self.channel_key(channel);
+
+        if let Some(channel) = self.channels.get_mut(&key) {
+            channel.topic = match topic {
+                Some(text) if !text.is_empty() => Some(Topic {
+                    text: text.to_owned(),
+                    setter: setter.map(|setter| setter.to_owned()),
+                    time: time,
+                }),
+                _ => None,
+            };
+        }
+    }
+
+    fn mode(&mut self, mode: &Mode) {
+        let key = self.channel_key(mode.0);
+        let changes = mode.changes(&self.isupport);
+
+        if let Some(channel) = self.channels.get_mut(&key) {
+            for change in &changes {
+                if ModeKind::of(&self.isupport, change.mode) != ModeKind::Prefix {
+                    channel.modes.apply(&self.isupport, change);
+                    continue;
+                }
+
+                let nick_key = match change.argument {
+                    Some(ref nick) => Nick::new(nick, &self.isupport.casemapping()),
+                    None => continue,
+                };
+
+                if let Some(member) = channel.members.get_mut(&nick_key) {
+                    member.set_mode(&self.isupport, change.mode, change.adding);
+                }
+            }
+        }
+    }
+
+    fn channel_modes(&mut self, message: &Message, channel: &str) {
+        let key = self.channel_key(channel);
+        let mut arguments = message.raw_args().skip(2);
+
+        let changes = match arguments.next() {
+            Some(modes) => mode::parse_channel_modes(&self.isupport, modes, arguments),
+            None => return,
+        };
+
+        if let Some(channel) = self.channels.get_mut(&key) {
+            channel.modes.clear_settings();
+
+            for change in &changes {
+                channel.modes.apply(&self.isupport, change);
+            }
+        }
+    }
+
+    fn set_own_away(&mut self, away: Option<&str>) {
+        if let Some(nick) = self.nick.clone() {
+            self.user_mut(&nick).away = away.map(|away| away.to_owned());
+        }
+    }
+
+    fn set_own_account(&mut self, account: Option<&str>) {
+        if let Some(nick) = self.nick.clone() {
+            self.user_mut(&nick).account = account.map(|account| account.to_owned());
+        }
+    }
+}
+
+// Splits a `nick!user@host` mask into its parts.
+fn split_mask(mask: &str) -> (&str, Option<&str>, Option<&str>) {
+    let (nick, rest) = match mask.find('!') {
+        Some(index) => (&mask[..index], Some(&mask[index + 1..])),
+        None => (mask, None),
+    };
+
+    match rest.and_then(|rest| rest.find('@').map(|index| (rest, index))) {
+        Some((rest, index)) => (nick, Some(&rest[..index]), Some(&rest[index + 1..])),
+        None => (nick, rest, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(lines: &[&str]) -> State {
+        let mut state = State::new();
+        handle(&mut state, &[":server 001 me :Welcome"]);
+        handle(&mut state, lines);
+        state
+    }
+
+    fn handle(state: &mut State, lines: &[&str]) {
+        for line in lines {
+            state.handle(&Message::try_from(line.to_string()).unwrap());
+        }
+    }
+
+    fn members(state: &State, channel: &str) -> Vec<String> {
+        let mut members: Vec<String> = state.channel(channel)
+            .unwrap()
+            .members()
+            .map(|member| format!("{}:{}", member.nick(), member.modes()))
+            .collect();
+
+        members.sort();
+        members
+    }
+
+    #[test]
+    fn tracks_joins_and_names() {
+        let state = state(&[
+            ":me!u@host JOIN #chan",
+            ":server 353 me = #chan :@+me +voiced",
+            ":server 353 me = #chan :plain @op!o@op.host",
+            ":server 366 me #chan :End of /NAMES list.",
+            ":late!l@late.host JOIN #chan",
+        ]);
+
+        assert_eq!(state.nick(), Some("me"));
+        assert!(state.channel("#chan").unwrap().is_synced());
+        assert_eq!(members(&state, "#chan"), vec!["late:", "me:ov", "op:o", "plain:", "voiced:v"]);
+        assert_eq!(state.member("#chan", "ME").unwrap().prefix(state.isupport()), Some('@'));
+        assert_eq!(state.member("#chan", "voiced").unwrap().prefix(state.isupport()), Some('+'));
+        assert_eq!(state.member("#chan", "plain").unwrap().prefix(state.isupport()), None);
+
+        let op = state.user("op").unwrap();
+        assert_eq!((op.username.as_ref().unwrap().as_str(), op.host.as_ref().unwrap().as_str()), ("o", "op.host"));
+
+        let late = state.user("late").unwrap();
+        assert_eq!(late.host, Some("late.host".to_owned()));
+    }
+
+    #[test]
+    fn renames_users_in_every_channel() {
+        let mut state = state(&[
+            ":me!u@host JOIN #a",
+            ":me!u@host JOIN #b",
+            ":friend!f@host JOIN #a",
+            ":friend!f@host JOIN #b",
+            ":server MODE #a +v friend",
+        ]);
+
+        handle(&mut state, &[":friend!f@host NICK Pal"]);
+
+        assert!(state.user("friend").is_none());
+        assert_eq!(state.user("pal").unwrap().nick, "Pal");
+        assert_eq!(members(&state, "#a"), vec!["Pal:v", "me:"]);
+        assert_eq!(members(&state, "#b"), vec!["Pal:", "me:"]);
+
+        handle(&mut state, &[":me!u@host NICK me2"]);
+
+        assert_eq!(state.nick(), Some("me2"));
+        assert!(state.is_me("ME2"));
+        assert_eq!(members(&state, "#b"), vec!["Pal:", "me2:"]);
+    }
+
+    #[test]
+    fn prunes_users_that_leave() {
+        let mut state = state(&[
+            ":me!u@host JOIN #a",
+            ":me!u@host JOIN #b",
+            ":friend!f@host JOIN #a",
+            ":friend!f@host JOIN #b",
+            ":other!o@host JOIN #a",
+            ":quitter!q@host JOIN #b",
+        ]);
+
+        handle(&mut state, &[":other!o@host PART #a :bye"]);
+        assert!(state.user("other").is_none());
+
+        handle(&mut state, &[":op!o@host KICK #a friend :out"]);
+        assert_eq!(members(&state, "#a"), vec!["me:"]);
+        assert!(state.user("friend").is_some());
+
+        handle(&mut state, &[":quitter!q@host QUIT :gone"]);
+        assert!(state.user("quitter").is_none());
+        assert_eq!(members(&state, "#b"), vec!["friend:", "me:"]);
+
+        handle(&mut state, &[":me!u@host PART #b"]);
+        assert!(state.channel("#b").is_none());
+        assert!(state.user("friend").is_none());
+        assert!(state.user("me").is_some());
+
+        handle(&mut state, &[":op!o@host KICK #a me"]);
+        assert_eq!(state.channels().count(), 0);
+    }
+
+    #[test]
+    fn prunes_users_missing_from_a_later_names_reply() {
+        let mut state = state(&[
+            ":me!u@host JOIN #a",
+            ":server 353 me = #a :me friend gone",
+            ":server 366 me #a :End of /NAMES list.",
+        ]);
+
+        handle(&mut state, &[":server 353 me = #a :me @friend"]);
+        assert_eq!(members(&state, "#a"), vec!["friend:o", "me:"]);
+        assert!(!state.channel("#a").unwrap().is_synced());
+
+        handle(&mut state, &[":server 366 me #a :End of /NAMES list."]);
+        assert!(state.user("gone").is_none());
+        assert!(state.user("friend").is_some());
+    }
+
+    #[test]
+    fn tracks_topics() {
+        let mut state = state(&[
+            ":me!u@host JOIN #a",
+            ":server 332 me #a :Hello there",
+            ":server 333 me #a setter!s@host 1700000000",
+        ]);
+
+        let expected = Topic {
+            text: "Hello there".to_owned(),
+            setter: Some("setter!s@host".to_owned()),
+            time: Some(1_700_000_000),
+        };
+        assert_eq!(state.channel("#a").unwrap().topic(), Some(&expected));
+
+        handle(&mut state, &[":op!o@host TOPIC #a :New topic"]);
+        let topic = state.channel("#a").unwrap().topic().unwrap().clone();
+        assert_eq!((topic.text.as_str(), topic.setter), ("New topic", Some("op".to_owned())));
+        assert!(topic.time.is_some());
+
+        handle(&mut state, &[":op!o@host TOPIC #a :"]);
+        assert_eq!(state.channel("#a").unwrap().topic(), None);
+
+        handle(&mut state, &[":server 332 me #a :Back", ":server 331 me #a :No topic is set."]);
+        assert_eq!(state.channel("#a").unwrap().topic(), None);
+    }
+
+    #[test]
+    fn tracks_membership_modes() {
+        let mut state = state(&[
+            ":me!u@host JOIN #a",
+            ":server 353 me = #a :me +friend",
+            ":server 366 me #a :End of /NAMES list.",
+        ]);
+
+        handle(&mut state, &[":op!o@host MODE #a +o-v friend friend"]);
+        assert_eq!(members(&state, "#a"), vec!["friend:o", "me:"]);
+
+        handle(&mut state, &[":op!o@host MODE #a +vk FRIEND secret"]);
+        assert_eq!(members(&state, "#a"), vec!["friend:ov", "me:"]);
+        assert_eq!(state.member("#a", "friend").unwrap().prefix(state.isupport()), Some('@'));
+        assert_eq!(state.channel("#a").unwrap().modes().argument('k'), Some("secret"));
+
+        handle(&mut state, &[":op!o@host MODE #a -o friend"]);
+        assert_eq!(state.member("#a", "friend").unwrap().prefix(state.isupport()), Some('+'));
+    }
+
+    #[test]
+    fn rekeys_when_the_case_mapping_changes() {
+        let mut state = state(&[":me!u@host JOIN #Foo[]", ":Nick[]!n@host JOIN #Foo[]"]);
+
+        assert!(state.channel("#foo{}").is_some());
+        assert!(state.user("nick{}").is_some());
+
+        handle(&mut state, &[":server 005 me CASEMAPPING=ascii :are supported by this server"]);
+
+        assert!(state.channel("#foo{}").is_none());
+        assert!(state.channel("#FOO[]").is_some());
+        assert!(state.user("nick{}").is_none());
+        assert!(state.member("#foo[]", "NICK[]").is_some());
+    }
+}
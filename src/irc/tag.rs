@@ -1,38 +1,114 @@
 //! The tag module contains everything needed to perform strongly typed access
 //! to tags associated with a message.
 
-use std::ops::Range;
+use std::borrow::Cow;
+use std::fmt;
 use std::slice::Iter;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use irc::message::TagRange;
+
+/// The key of a tag, which may be marked as client-only with a `+` and may be
+/// qualified with the vendor that defined it, as in `+example.com/name`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TagKey<'a> {
+    key: &'a str,
+}
+
+impl<'a> TagKey<'a> {
+    /// Create a new `TagKey` from the key as it appears in a message.
+    pub fn new(key: &'a str) -> TagKey<'a> {
+        TagKey { key: key }
+    }
+
+    /// Get the full key, including any `+` and vendor.
+    pub fn as_str(&self) -> &'a str {
+        self.key
+    }
+
+    /// Returns true if the tag was sent by another client rather than the server.
+    pub fn is_client_only(&self) -> bool {
+        self.key.starts_with('+')
+    }
+
+    /// Get the vendor that defined the tag, if it isn't a standard tag.
+    pub fn vendor(&self) -> Option<&'a str> {
+        let key = self.key.trim_start_matches('+');
+
+        key.rfind('/').map(|index| &key[..index])
+    }
+
+    /// Get the name of the tag, without any `+` or vendor.
+    pub fn name(&self) -> &'a str {
+        let key = self.key.trim_start_matches('+');
+
+        match key.rfind('/') {
+            Some(index) => &key[index + 1..],
+            None => key,
+        }
+    }
+}
+
+impl<'a> PartialEq<str> for TagKey<'a> {
+    fn eq(&self, other: &str) -> bool {
+        self.key == other
+    }
+}
+
+impl<'a, 'b> PartialEq<&'b str> for TagKey<'a> {
+    fn eq(&self, other: &&'b str) -> bool {
+        self.key == *other
+    }
+}
+
+impl<'a> fmt::Display for TagKey<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.key)
+    }
+}
 
 /// An implementation of Iterator that iterates over the key/value pairs
 /// (in the form of a tuple) of the tags of a `Message`.
+///
+/// Values are unescaped, so they only borrow from the message when they
+/// contained no escape sequences. A tag without a value, or with an empty
+/// value, has a value of `None`.
 #[derive(Clone)]
 pub struct TagIter<'a> {
     source: &'a str,
-    iter: Iter<'a, (Range<usize>, Option<Range<usize>>)>,
+    iter: Iter<'a, TagRange>,
+}
+
+impl<'a> TagIter<'a> {
+    /// Create a new `TagIter` over the tags of `source` found at the given ranges.
+    pub fn new(source: &'a str, tags: &'a [TagRange]) -> TagIter<'a> {
+        TagIter {
+            source: source,
+            iter: tags.iter(),
+        }
+    }
+
+    fn item(&self, &(ref key, ref value): &TagRange) -> (TagKey<'a>, Option<Cow<'a, str>>) {
+        let source = self.source;
+
+        (
+            TagKey::new(&source[key.clone()]),
+            value.clone().map(|value| unescape(&source[value])),
+        )
+    }
 }
 
 impl<'a> Iterator for TagIter<'a> {
-    type Item = (&'a str, Option<&'a str>);
+    type Item = (TagKey<'a>, Option<Cow<'a, str>>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next().map(|&(ref key, ref value)| {
-            (
-                &self.source[key.clone()],
-                value.clone().map(|value| &self.source[value]),
-            )
-        })
+        self.iter.next().map(|range| self.item(range))
     }
 }
 
 impl<'a> DoubleEndedIterator for TagIter<'a> {
     fn next_back(&mut self) -> Option<Self::Item> {
-        self.iter.next_back().map(|&(ref key, ref value)| {
-            (
-                &self.source[key.clone()],
-                value.clone().map(|value| &self.source[value]),
-            )
-        })
+        self.iter.next_back().map(|range| self.item(range))
     }
 }
 
@@ -44,7 +120,7 @@ pub trait Tag<'a> {
 
     /// This method attempts to parse the tag input into a strongly typed representation.
     /// If parsing failes, it returns `None`.
-    fn parse(tag: Option<&'a str>) -> Option<Self>
+    fn parse(tag: Option<Cow<'a, str>>) -> Option<Self>
     where
         Self: Sized;
 
@@ -58,3 +134,389 @@ pub trait Tag<'a> {
             .and_then(|(_, value)| Self::parse(value))
     }
 }
+
+/// Reverses the escaping of a tag value. Backslashes before any other
+/// character are dropped, as is a backslash at the end of the value.
+pub fn unescape(value: &str) -> Cow<str> {
+    if !value.contains('\\') {
+        return Cow::Borrowed(value);
+    }
+
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some(':') => unescaped.push(';'),
+            Some('s') => unescaped.push(' '),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+
+    Cow::Owned(unescaped)
+}
+
+/// Escapes a tag value so that it can be sent in a message, as described in the
+/// IRCv3 message tags specification.
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            ';' => escaped.push_str("\\:"),
+            ' ' => escaped.push_str("\\s"),
+            '\\' => escaped.push_str("\\\\"),
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// A macro for creating implementations of tags whose value is a string.
+macro_rules! tag {
+    ($(#[$meta:meta])* ($tag:expr => $tag_name:ident)) => {
+        $(#[$meta])*
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        pub struct $tag_name<'a>(pub Cow<'a, str>);
+
+        impl<'a> Tag<'a> for $tag_name<'a> {
+            fn name() -> &'static str {
+                $tag
+            }
+
+            fn parse(tag: Option<Cow<'a, str>>) -> Option<$tag_name<'a>> {
+                tag.map($tag_name)
+            }
+        }
+    };
+}
+
+tag! {
+    /// Represents the `msgid` tag. The first element is the unique ID of the message.
+    ("msgid" => MsgId)
+}
+
+tag! {
+    /// Represents the `account` tag. The first element is the account the sender is logged in to.
+    ("account" => Account)
+}
+
+tag! {
+    /// Represents the `label` tag. The first element is the label of the command this message responds to.
+    ("label" => Label)
+}
+
+tag! {
+    /// Represents the `batch` tag. The first element is the reference of the batch the message belongs to.
+    ("batch" => Batch)
+}
+
+tag! {
+    /// Represents the `+draft/reply` tag. The first element is the ID of the message being replied to.
+    ("+draft/reply" => Reply)
+}
+
+tag! {
+    /// Represents the `+draft/react` tag. The first element is the reaction, usually an emoji.
+    ("+draft/react" => React)
+}
+
+/// Represents the `bot` tag, which marks a message sent by a bot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bot;
+
+impl<'a> Tag<'a> for Bot {
+    fn name() -> &'static str {
+        "bot"
+    }
+
+    fn parse(_: Option<Cow<'a, str>>) -> Option<Bot> {
+        Some(Bot)
+    }
+}
+
+/// Represents the `+typing` tag, which reports whether the sender is typing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Typing {
+    /// The sender is typing.
+    Active,
+    /// The sender has typed something but stopped for now.
+    Paused,
+    /// The sender has stopped typing without sending anything.
+    Done,
+}
+
+impl<'a> Tag<'a> for Typing {
+    fn name() -> &'static str {
+        "+typing"
+    }
+
+    fn parse(tag: Option<Cow<'a, str>>) -> Option<Typing> {
+        match tag.as_ref().map(|tag| tag.as_ref()) {
+            Some("active") => Some(Typing::Active),
+            Some("paused") => Some(Typing::Paused),
+            Some("done") => Some(Typing::Done),
+            _ => None,
+        }
+    }
+}
+
+/// Represents the `time` tag. The first element is the time the server received the message.
+///
+/// The tag is formatted as `YYYY-MM-DDThh:mm:ss.sssZ`, which is also how the
+/// time is displayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ServerTime(pub SystemTime);
+
+impl<'a> Tag<'a> for ServerTime {
+    fn name() -> &'static str {
+        "time"
+    }
+
+    fn parse(tag: Option<Cow<'a, str>>) -> Option<ServerTime> {
+        tag.and_then(|tag| parse_time(&tag)).map(ServerTime)
+    }
+}
+
+impl fmt::Display for ServerTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (seconds, millis) = match self.0.duration_since(UNIX_EPOCH) {
+            Ok(since) => (since.as_secs() as i64, since.subsec_millis()),
+            Err(error) => {
+                let before = error.duration();
+                let millis = before.subsec_millis();
+
+                if millis == 0 {
+                    (-(before.as_secs() as i64), 0)
+                } else {
+                    (-(before.as_secs() as i64) - 1, 1000 - millis)
+                }
+            }
+        };
+
+        let days = seconds.div_euclid(86_400);
+        let time = seconds.rem_euclid(86_400);
+        let (year, month, day) = civil_from_days(days);
+
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year,
+            month,
+            day,
+            time / 3600,
+            time % 3600 / 60,
+            time % 60,
+            millis
+        )
+    }
+}
+
+// Parses a timestamp in the `YYYY-MM-DDThh:mm:ss.sssZ` format. The fractional
+// seconds may have any number of digits, or be left out.
+fn parse_time(value: &str) -> Option<SystemTime> {
+    // NOTE: The fields are sliced by byte position, which is only safe for ASCII text.
+    if !value.is_ascii() {
+        return None;
+    }
+
+    let bytes = value.as_bytes();
+
+    if bytes.len() < 20 || bytes[4] != b'-' || bytes[7] != b'-' || bytes[10] != b'T' || bytes[13] != b':'
+        || bytes[16] != b':' || !value.ends_with('Z')
+    {
+        return None;
+    }
+
+    let number = |range: ::std::ops::Range<usize>| -> Option<u32> {
+        let digits = &value[range];
+
+        if digits.bytes().all(|b| b.is_ascii_digit()) {
+            digits.parse().ok()
+        } else {
+            None
+        }
+    };
+
+    let year = number(0..4)?;
+    let month = number(5..7)?;
+    let day = number(8..10)?;
+    let hour = number(11..13)?;
+    let minute = number(14..16)?;
+    let second = number(17..19)?;
+
+    let fraction = &value[19..value.len() - 1];
+    let nanos = if fraction.is_empty() {
+        0
+    } else if fraction.starts_with('.') && fraction.len() > 1 && fraction[1..].bytes().all(|b| b.is_ascii_digit()) {
+        // Only the first nine digits are significant.
+        let digits: String = fraction[1..].chars().chain("000000000".chars()).take(9).collect();
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+
+    // NOTE: A second of 60 is allowed for leap seconds.
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) || hour > 23 || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    let days = days_from_civil(year as i64, month, day);
+    let seconds = days * 86_400 + i64::from(hour * 3600 + minute * 60 + second);
+
+    if seconds >= 0 {
+        Some(UNIX_EPOCH + Duration::new(seconds as u64, nanos))
+    } else {
+        Some(UNIX_EPOCH - Duration::from_secs((-seconds) as u64) + Duration::new(0, nanos))
+    }
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Converts a date to the number of days since 1970-01-01, using the algorithm
+// described by Howard Hinnant in "chrono-Compatible Low-Level Date Algorithms".
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+// Converts the number of days since 1970-01-01 to a date, the inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use irc::message::Message;
+    use super::*;
+
+    #[test]
+    fn escapes_values() {
+        assert_eq!(escape("plain"), "plain");
+        assert_eq!(escape("a b;c\\d\r\n"), "a\\sb\\:c\\\\d\\r\\n");
+    }
+
+    #[test]
+    fn unescapes_values() {
+        assert_eq!(unescape("plain"), Cow::Borrowed("plain"));
+        assert_eq!(unescape("a\\sb\\:c\\\\d\\r\\n"), "a b;c\\d\r\n");
+
+        // Unknown escapes drop the backslash, as does a trailing backslash.
+        assert_eq!(unescape("\\a\\b\\"), "ab");
+        assert_eq!(unescape(&escape("; \\\r\n")), "; \\\r\n");
+    }
+
+    #[test]
+    fn iterates_over_unescaped_tags() {
+        let message = Message::try_from("@a=1;+example.com/b=x\\sy;c;d= PING x".to_owned()).unwrap();
+        let tags: Vec<_> = message.tags().map(|(key, value)| (key.as_str(), value)).collect();
+
+        assert_eq!(
+            tags,
+            vec![
+                ("a", Some(Cow::Borrowed("1"))),
+                ("+example.com/b", Some(Cow::Owned("x y".to_owned()))),
+                ("c", None),
+                ("d", None),
+            ]
+        );
+
+        let (key, _) = message.tags().nth(1).unwrap();
+        assert!(key.is_client_only());
+        assert_eq!(key.vendor(), Some("example.com"));
+        assert_eq!(key.name(), "b");
+    }
+
+    fn time(line: &str) -> Option<ServerTime> {
+        Message::try_from(line.to_owned()).unwrap().tag::<ServerTime>()
+    }
+
+    #[test]
+    fn parses_server_time() {
+        let ServerTime(parsed) = time("@time=2011-10-19T16:40:51.620Z PING x").unwrap();
+        assert_eq!(parsed, UNIX_EPOCH + Duration::from_millis(1_319_042_451_620));
+
+        let ServerTime(parsed) = time("@time=1970-01-01T00:00:00Z PING x").unwrap();
+        assert_eq!(parsed, UNIX_EPOCH);
+
+        let ServerTime(parsed) = time("@time=2000-02-29T23:59:59.123456789Z PING x").unwrap();
+        assert_eq!(parsed, UNIX_EPOCH + Duration::new(951_868_799, 123_456_789));
+
+        let ServerTime(parsed) = time("@time=1969-12-31T23:59:59.500Z PING x").unwrap();
+        assert_eq!(parsed, UNIX_EPOCH - Duration::from_millis(500));
+    }
+
+    #[test]
+    fn rejects_invalid_server_time() {
+        assert!(time("PING x").is_none());
+        assert!(time("@time PING x").is_none());
+        assert!(time("@time=2011-10-19 PING x").is_none());
+        assert!(time("@time=2011-10-19T16:40:51.620 PING x").is_none());
+        assert!(time("@time=2011-13-19T16:40:51Z PING x").is_none());
+        assert!(time("@time=2001-02-29T16:40:51Z PING x").is_none());
+        assert!(time("@time=2011-10-19T24:40:51Z PING x").is_none());
+        assert!(time("@time=2011-10-19T16:40:51.Z PING x").is_none());
+        assert!(time("@time=+011-10-19T16:40:51Z PING x").is_none());
+    }
+
+    #[test]
+    fn rejects_non_ascii_and_truncated_server_time() {
+        assert!(time("@time=2011-10-19T16:40:5éZ PING x").is_none());
+        assert!(time("@time=2é11-10-19T16:40:51Z PING x").is_none());
+        assert!(time("@time=2011-10-19T16:40:51.6é0Z PING x").is_none());
+        assert!(time("@time=2011-10-19T16:40:5Z PING x").is_none());
+        assert!(time("@time=2011-10-19T16:4 PING x").is_none());
+        assert!(time("@time=Z PING x").is_none());
+    }
+
+    #[test]
+    fn displays_server_time() {
+        let cases = [
+            "2011-10-19T16:40:51.620Z",
+            "1970-01-01T00:00:00.000Z",
+            "2000-02-29T23:59:59.123Z",
+            "1969-12-31T23:59:59.500Z",
+        ];
+
+        for case in &cases {
+            let line = format!("@time={} PING x", case);
+            assert_eq!(time(&line).unwrap().to_string(), *case);
+        }
+    }
+}
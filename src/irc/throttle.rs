@@ -0,0 +1,215 @@
+//! The throttle module contains the `Throttle` type which limits the rate at
+//! which messages are sent to avoid being disconnected for flooding.
+
+extern crate tokio_core;
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use futures::Future;
+use tokio_core::reactor::{Handle, Timeout};
+
+use irc::error::Result;
+use irc::message::Message;
+
+// The cost in bytes charged for every message on top of its length, so that a
+// burst of short messages is throttled as well as a few long ones.
+const MESSAGE_OVERHEAD_IN_BYTES: usize = 64;
+
+/// A send queue that releases messages according to a token bucket.
+///
+/// The bucket holds up to `burst` bytes and refills at `rate` bytes per
+/// second. Sending a message costs its length including the line ending, plus
+/// a fixed overhead per message. Messages wait in the queue until the bucket
+/// holds enough to pay for them, in the order they were queued.
+pub struct Throttle {
+    queue: VecDeque<Message>,
+    timer: Timeout,
+    burst: usize,
+    rate: usize,
+    available: f64,
+    refilled: Instant,
+}
+
+impl Throttle {
+    /// Create a new `Throttle` that allows bursts of `burst` bytes and then
+    /// `rate` bytes per second. A `rate` of zero disables throttling.
+    pub fn new(handle: &Handle, burst: usize, rate: usize) -> Result<Throttle> {
+        Ok(Throttle {
+            queue: VecDeque::new(),
+            timer: Timeout::new(Duration::from_secs(0), handle)?,
+            burst: burst,
+            rate: rate,
+            available: burst as f64,
+            refilled: Instant::now(),
+        })
+    }
+
+    /// Get the number of messages waiting to be sent.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns true if no messages are waiting to be sent.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Get the number of bytes waiting to be sent.
+    pub fn queued_bytes(&self) -> usize {
+        self.queue.iter().map(|message| message.raw_message().len() + 2).sum()
+    }
+
+    /// Adds a message to the back of the queue.
+    pub fn push(&mut self, message: Message) {
+        self.queue.push_back(message);
+    }
+
+    /// Takes the next message off the queue if it may be sent now.
+    ///
+    /// When the bucket doesn't hold enough for the next message, `None` is
+    /// returned and the current task is woken once it does.
+    pub fn pop(&mut self) -> Result<Option<Message>> {
+        let cost = match self.queue.front() {
+            Some(message) => cost(message),
+            None => return Ok(None),
+        };
+
+        if self.rate == 0 {
+            return Ok(self.queue.pop_front());
+        }
+
+        self.refill();
+
+        // NOTE: A message that costs more than the whole bucket is sent once the bucket is full.
+        let required = ::std::cmp::min(cost, self.burst) as f64;
+
+        if self.available >= required {
+            self.available -= cost as f64;
+            return Ok(self.queue.pop_front());
+        }
+
+        let wait = (required - self.available) / self.rate as f64;
+        let millis = (wait * 1000.0).ceil() as u64;
+
+        self.timer.reset(Instant::now() + Duration::from_millis(millis));
+
+        // NOTE: The timer has to be polled for the task to be woken when it fires.
+        self.timer.poll()?;
+
+        Ok(None)
+    }
+
+    /// Puts back a message taken with `pop` that could not be sent after all,
+    /// refunding its cost.
+    pub fn unpop(&mut self, message: Message) {
+        if self.rate != 0 {
+            self.available += cost(&message) as f64;
+        }
+
+        self.queue.push_front(message);
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1_000_000_000.0;
+
+        self.available = (self.available + elapsed * self.rate as f64).min(self.burst as f64);
+        self.refilled = now;
+    }
+}
+
+fn cost(message: &Message) -> usize {
+    message.raw_message().len() + 2 + MESSAGE_OVERHEAD_IN_BYTES
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    fn message(text: &str) -> Message {
+        Message::try_from(format!("PRIVMSG #a :{}", text)).unwrap()
+    }
+
+    // Pops from within a task, as the timer requires.
+    fn pop(core: &mut Core, throttle: &mut Throttle) -> Option<Message> {
+        core.run(future::lazy(|| Ok::<_, ()>(throttle.pop()))).unwrap().unwrap()
+    }
+
+    fn sleep(core: &mut Core, milliseconds: u64) {
+        let timeout = Timeout::new(Duration::from_millis(milliseconds), &core.handle()).unwrap();
+        core.run(timeout).unwrap();
+    }
+
+    #[test]
+    fn sends_a_burst_then_refills() {
+        let mut core = Core::new().unwrap();
+        let size = cost(&message("1"));
+
+        // The bucket holds two messages and refills one every 100 milliseconds.
+        let mut throttle = Throttle::new(&core.handle(), size * 2, size * 10).unwrap();
+
+        for text in &["1", "2", "3", "4"] {
+            throttle.push(message(text));
+        }
+
+        assert_eq!(throttle.queued_bytes(), 4 * (size - MESSAGE_OVERHEAD_IN_BYTES));
+        assert_eq!(pop(&mut core, &mut throttle), Some(message("1")));
+        assert_eq!(pop(&mut core, &mut throttle), Some(message("2")));
+        assert_eq!(pop(&mut core, &mut throttle), None);
+        assert_eq!(throttle.len(), 2);
+
+        sleep(&mut core, 120);
+        assert_eq!(pop(&mut core, &mut throttle), Some(message("3")));
+        assert_eq!(pop(&mut core, &mut throttle), None);
+
+        sleep(&mut core, 120);
+        assert_eq!(pop(&mut core, &mut throttle), Some(message("4")));
+        assert!(throttle.is_empty());
+    }
+
+    #[test]
+    fn unpop_refunds_the_cost() {
+        let mut core = Core::new().unwrap();
+        let size = cost(&message("1"));
+        let mut throttle = Throttle::new(&core.handle(), size, 1).unwrap();
+
+        throttle.push(message("1"));
+        throttle.push(message("2"));
+
+        let first = pop(&mut core, &mut throttle).unwrap();
+        assert_eq!(pop(&mut core, &mut throttle), None);
+
+        throttle.unpop(first);
+        assert_eq!(throttle.len(), 2);
+        assert_eq!(pop(&mut core, &mut throttle), Some(message("1")));
+        assert_eq!(pop(&mut core, &mut throttle), None);
+    }
+
+    #[test]
+    fn sends_oversized_messages_with_a_full_bucket() {
+        let mut core = Core::new().unwrap();
+        let mut throttle = Throttle::new(&core.handle(), 10, 1).unwrap();
+
+        throttle.push(message("longer than the whole bucket"));
+        assert!(pop(&mut core, &mut throttle).is_some());
+    }
+
+    #[test]
+    fn zero_rate_disables_throttling() {
+        let mut core = Core::new().unwrap();
+        let mut throttle = Throttle::new(&core.handle(), 0, 0).unwrap();
+
+        for text in &["1", "2", "3"] {
+            throttle.push(message(text));
+        }
+
+        for text in &["1", "2", "3"] {
+            assert_eq!(pop(&mut core, &mut throttle), Some(message(text)));
+        }
+    }
+}
@@ -0,0 +1,438 @@
+//! The tls module contains the types needed to establish encrypted
+//! connections to a remote IRC host using rustls.
+
+extern crate futures;
+extern crate rustls;
+extern crate sha2;
+extern crate tokio_io;
+extern crate webpki_roots;
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use futures::{Async, Future, Poll};
+use self::rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use self::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use self::rustls::crypto::{self, CryptoProvider};
+use self::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use self::rustls::pki_types::pem::PemObject;
+use self::sha2::{Digest, Sha256};
+use self::tokio_io::{AsyncRead, AsyncWrite};
+
+use irc::error::{Error, ErrorKind, Result};
+
+/// The options used when establishing an encrypted connection.
+///
+/// By default the server certificate is verified against the bundled Mozilla
+/// root certificates, using the host being connected to as the server name.
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    /// PEM files containing root certificates to trust in addition to the bundled ones.
+    pub root_certificates: Vec<PathBuf>,
+    /// The hex encoded SHA-256 fingerprint of a server certificate to accept, such as
+    /// a self-signed certificate. When set, the certificate chain is not verified.
+    pub fingerprint: Option<String>,
+    /// The server name sent with SNI and checked against the certificate, if it
+    /// differs from the host being connected to.
+    pub server_name: Option<String>,
+    /// A PEM file containing the client certificate chain presented for CertFP.
+    pub client_certificate: Option<PathBuf>,
+    /// A PEM file containing the private key of the client certificate.
+    pub client_key: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    /// Builds the rustls configuration described by these options.
+    pub fn client_config(&self) -> Result<Arc<ClientConfig>> {
+        let provider = Arc::new(crypto::ring::default_provider());
+
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(tls_error)?;
+
+        let builder = match self.fingerprint {
+            Some(ref fingerprint) => {
+                let verifier = FingerprintVerifier {
+                    fingerprint: parse_fingerprint(fingerprint)?,
+                    provider: provider,
+                };
+
+                builder
+                    .dangerous()
+                    .with_custom_certificate_verifier(Arc::new(verifier))
+            }
+            None => {
+                let mut roots = RootCertStore {
+                    roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+                };
+
+                for path in &self.root_certificates {
+                    for certificate in CertificateDer::pem_file_iter(path).map_err(tls_error)? {
+                        roots.add(certificate.map_err(tls_error)?).map_err(tls_error)?;
+                    }
+                }
+
+                builder.with_root_certificates(roots)
+            }
+        };
+
+        let config = match (&self.client_certificate, &self.client_key) {
+            (&Some(ref certificate), &Some(ref key)) => {
+                let chain = CertificateDer::pem_file_iter(certificate)
+                    .map_err(tls_error)?
+                    .collect::<::std::result::Result<Vec<_>, _>>()
+                    .map_err(tls_error)?;
+                let key = PrivateKeyDer::from_pem_file(key).map_err(tls_error)?;
+
+                builder.with_client_auth_cert(chain, key).map_err(tls_error)?
+            }
+            (&None, &None) => builder.with_no_client_auth(),
+            _ => {
+                return Err(ErrorKind::Tls("A client certificate requires both a certificate and a key.".to_owned()).into())
+            }
+        };
+
+        Ok(Arc::new(config))
+    }
+
+    /// Determines the server name to use for SNI and certificate verification.
+    pub fn server_name(&self, host: &str) -> Result<ServerName<'static>> {
+        let name = self.server_name.as_ref().map(|name| name.as_str()).unwrap_or(host);
+
+        ServerName::try_from(name.to_owned()).map_err(tls_error)
+    }
+}
+
+/// Starts a TLS handshake over `stream`, returning a future that resolves to
+/// a `TlsStream` once the handshake has completed.
+pub fn connect<S>(config: Arc<ClientConfig>, server_name: ServerName<'static>, stream: S) -> Result<TlsConnect<S>>
+where
+    S: Read + Write,
+{
+    let session = ClientConnection::new(config, server_name).map_err(tls_error)?;
+
+    Ok(TlsConnect {
+        inner: Some(TlsStream {
+            session: session,
+            io: stream,
+        }),
+    })
+}
+
+/// Represents a future, that when resolved provides a `TlsStream` that has
+/// completed the TLS handshake.
+pub struct TlsConnect<S> {
+    inner: Option<TlsStream<S>>,
+}
+
+impl<S> Future for TlsConnect<S>
+where
+    S: Read + Write,
+{
+    type Item = TlsStream<S>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        {
+            let stream = self.inner.as_mut().expect("polled TlsConnect after completion");
+
+            while stream.session.is_handshaking() {
+                match stream.session.complete_io(&mut stream.io) {
+                    Ok((0, 0)) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                    Ok(_) => {}
+                    Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+                    Err(error) => return Err(error.into()),
+                }
+            }
+        }
+
+        Ok(Async::Ready(self.inner.take().unwrap()))
+    }
+}
+
+/// An encrypted stream over an underlying transport such as a `TcpStream`.
+pub struct TlsStream<S> {
+    session: ClientConnection,
+    io: S,
+}
+
+impl<S> TlsStream<S>
+where
+    S: Read + Write,
+{
+    /// Retrieves the certificate chain presented by the server.
+    pub fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]> {
+        self.session.peer_certificates()
+    }
+
+    // Writes any buffered TLS records to the underlying transport.
+    fn write_tls(&mut self) -> io::Result<()> {
+        while self.session.wants_write() {
+            self.session.write_tls(&mut self.io)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<S> Read for TlsStream<S>
+where
+    S: Read + Write,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.session.reader().read(buf) {
+                Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => {}
+                result => return result,
+            }
+
+            self.write_tls()?;
+
+            if self.session.read_tls(&mut self.io)? == 0 {
+                return Ok(0);
+            }
+
+            self.session
+                .process_new_packets()
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        }
+    }
+}
+
+impl<S> Write for TlsStream<S>
+where
+    S: Read + Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // NOTE: Records still waiting from a previous write are flushed first so that
+        // back pressure from the underlying transport limits the buffered plaintext.
+        self.write_tls()?;
+
+        let written = self.session.writer().write(buf)?;
+
+        match self.write_tls() {
+            Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => Ok(written),
+            result => result.map(|_| written),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.session.writer().flush()?;
+        self.write_tls()?;
+        self.io.flush()
+    }
+}
+
+impl<S> AsyncRead for TlsStream<S>
+where
+    S: AsyncRead + AsyncWrite,
+{
+}
+
+impl<S> AsyncWrite for TlsStream<S>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.session.send_close_notify();
+
+        match self.write_tls() {
+            Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+            result => result?,
+        }
+
+        self.io.shutdown()
+    }
+}
+
+// Accepts a server certificate only if its SHA-256 fingerprint matches the pinned one.
+struct FingerprintVerifier {
+    fingerprint: Vec<u8>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl fmt::Debug for FingerprintVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FingerprintVerifier").finish()
+    }
+}
+
+impl ServerCertVerifier for FingerprintVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer,
+        _intermediates: &[CertificateDer],
+        _server_name: &ServerName,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> ::std::result::Result<ServerCertVerified, rustls::Error> {
+        if Sha256::digest(end_entity.as_ref())[..] == self.fingerprint[..] {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General("The certificate fingerprint did not match.".to_owned()))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> ::std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> ::std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+// Parses a hex encoded fingerprint, ignoring any `:` separators.
+fn parse_fingerprint(fingerprint: &str) -> Result<Vec<u8>> {
+    let digits: Vec<u8> = fingerprint.bytes().filter(|&b| b != b':').collect();
+
+    let invalid = || Error::from(ErrorKind::Tls(format!("The fingerprint {} is not a valid SHA-256 fingerprint.", fingerprint)));
+
+    // NOTE: `from_str_radix` accepts a leading `+`, so the digits are checked up front.
+    if digits.len() != 64 || !digits.iter().all(u8::is_ascii_hexdigit) {
+        return Err(invalid());
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| {
+            ::std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
+
+fn tls_error<E: fmt::Display>(error: E) -> Error {
+    ErrorKind::Tls(error.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use self::rustls::{ServerConfig, ServerConnection, StreamOwned};
+    use super::*;
+
+    // NOTE: The certificates were generated with OpenSSL and are valid for a hundred years.
+    const CA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/ca.pem");
+    const CERTIFICATE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/localhost.pem");
+    const KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/localhost.key");
+
+    // Accepts a single connection with the `localhost` certificate, which is signed by the
+    // test CA, and greets the client once the handshake has completed.
+    fn serve() -> TcpListener {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.try_clone().unwrap();
+
+        let chain = CertificateDer::pem_file_iter(CERTIFICATE)
+            .unwrap()
+            .collect::<::std::result::Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_file(KEY).unwrap();
+        let config = ServerConfig::builder_with_provider(Arc::new(crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(chain, key)
+            .unwrap();
+
+        thread::spawn(move || {
+            let (socket, _) = server.accept().unwrap();
+            let connection = ServerConnection::new(Arc::new(config)).unwrap();
+            let mut stream = StreamOwned::new(connection, socket);
+
+            // NOTE: The handshake fails when the client rejects the certificate.
+            let _ = stream.write_all(b"hello\r\n").and_then(|_| stream.flush());
+        });
+
+        listener
+    }
+
+    fn handshake(config: &TlsConfig) -> Result<String> {
+        let listener = serve();
+        let socket = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut stream = connect(config.client_config()?, config.server_name("localhost")?, socket)?.wait()?;
+
+        let mut greeting = [0; 7];
+        stream.read_exact(&mut greeting)?;
+
+        Ok(String::from_utf8(greeting.to_vec()).unwrap())
+    }
+
+    fn fingerprint(path: &str) -> String {
+        let certificate = CertificateDer::from_pem_file(path).unwrap();
+
+        Sha256::digest(certificate.as_ref())
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+
+    #[test]
+    fn accepts_pinned_fingerprint() {
+        let config = TlsConfig {
+            fingerprint: Some(fingerprint(CERTIFICATE)),
+            ..TlsConfig::default()
+        };
+
+        assert_eq!(handshake(&config).unwrap(), "hello\r\n");
+    }
+
+    #[test]
+    fn rejects_wrong_fingerprint() {
+        let config = TlsConfig {
+            fingerprint: Some(fingerprint(CA)),
+            ..TlsConfig::default()
+        };
+
+        assert!(handshake(&config).is_err());
+    }
+
+    #[test]
+    fn trusts_custom_root_certificates() {
+        let config = TlsConfig {
+            root_certificates: vec![PathBuf::from(CA)],
+            ..TlsConfig::default()
+        };
+
+        assert_eq!(handshake(&config).unwrap(), "hello\r\n");
+        assert!(handshake(&TlsConfig::default()).is_err());
+    }
+
+    #[test]
+    fn parses_fingerprints() {
+        let plain = "00112233445566778899aabbccddeeff00112233445566778899AABBCCDDEEFF";
+        let separated = "00:11:22:33:44:55:66:77:88:99:AA:BB:CC:DD:EE:FF:00:11:22:33:44:55:66:77:88:99:aa:bb:cc:dd:ee:ff";
+        let expected: Vec<u8> = (0..32).map(|index| (index % 16) as u8 * 0x11).collect();
+
+        assert_eq!(parse_fingerprint(plain).unwrap(), expected);
+        assert_eq!(parse_fingerprint(separated).unwrap(), expected);
+
+        assert!(parse_fingerprint("").is_err());
+        assert!(parse_fingerprint(&plain[2..]).is_err());
+        assert!(parse_fingerprint(&format!("{}00", plain)).is_err());
+        assert!(parse_fingerprint(&plain.replace("ff", "fg")).is_err());
+        assert!(parse_fingerprint(&plain.replace("00", "+0")).is_err());
+    }
+}
@@ -3,25 +3,44 @@ extern crate tokio_io;
 extern crate tokio_core;
 extern crate futures;
 
-use futures::{Sink, Stream, Poll, StartSend, Async};
+use futures::{Sink, Stream, Poll, StartSend, Async, AsyncSink};
+use tokio_core::reactor::Handle;
 use self::tokio_io::{AsyncRead, AsyncWrite};
 use self::tokio_io::codec::Framed;
 
+use irc::capabilities::Capabilities;
 use irc::codec;
+use irc::command::Cap;
+use irc::config::Config;
+use irc::ctcp::CtcpResponder;
+use irc::isupport::ISupport;
+use irc::keepalive::Keepalive;
 use irc::message::{Message};
-use irc::error::{Error, ErrorKind};
+use irc::state::State;
+use irc::throttle::Throttle;
+use irc::error::{Error};
 
-use std;
-use std::time;
-use std::io::Write;
-
-const PING_TIMEOUT_IN_SECONDS: u64 = 10 * 60;
+use std::collections::VecDeque;
+use std::time::Duration;
 
 
 /// `IrcTransport` represents a framed IRC stream returned from the connection
 /// methods when their given futures are resolved. It internally handles the
-/// processing of PING requests and timing out the connection when no PINGs
-/// have been recently received from the server.
+/// processing of PING requests from the server, and sends its own PINGs when
+/// the connection goes quiet, failing with a `PingTimeout` error when the
+/// server doesn't respond within the configured deadline. It also keeps track of the
+/// IRCv3 capabilities enabled for the connection, requesting wanted
+/// capabilities as the server advertises them with `CAP NEW`, of the
+/// server features advertised with `RPL_ISUPPORT`, and of the channels
+/// joined and the users in them.
+///
+/// Unless disabled with the `ctcp_replies` setting, common CTCP requests such as
+/// VERSION and PING are answered automatically.
+///
+/// Outgoing messages are held in a send queue and released at the rate
+/// allowed by the `send_burst` and `send_rate` settings, so that the server
+/// doesn't disconnect the client for flooding. PONG and QUIT messages bypass
+/// the queue and are sent immediately.
 ///
 /// It is possible to split `IrcTransport` into `Stream` and `Sink` via the
 /// the `split` method.
@@ -29,17 +48,109 @@ pub struct IrcTransport<T>
     where T: AsyncRead + AsyncWrite
 {
     pub inner: Framed<T, codec::IrcCodec>,
-    last_ping: time::Instant,
+    keepalive: Keepalive,
+    throttle: Throttle,
+    urgent: VecDeque<Message>,
+    buffered: VecDeque<Message>,
+    capabilities: Capabilities,
+    state: State,
+    ctcp: Option<CtcpResponder>,
 }
 
 impl<T> IrcTransport<T>
     where T: AsyncRead + AsyncWrite
 {
-    pub fn new(inner: Framed<T, codec::IrcCodec>) -> IrcTransport<T> {
-        IrcTransport {
+    pub fn new(inner: Framed<T, codec::IrcCodec>, handle: &Handle, config: &Config) -> Result<IrcTransport<T>, Error> {
+        Ok(IrcTransport {
             inner: inner,
-            last_ping: time::Instant::now(),
+            keepalive: Keepalive::new(handle, config.ping_interval, config.ping_timeout)?,
+            throttle: Throttle::new(handle, config.send_burst, config.send_rate)?,
+            urgent: VecDeque::new(),
+            buffered: VecDeque::new(),
+            capabilities: Capabilities::default(),
+            state: State::new(),
+            ctcp: if config.ctcp_replies {
+                Some(CtcpResponder::new(&config.ctcp_version, config.ctcp_source.as_ref().map(|source| source.as_str())))
+            } else {
+                None
+            },
+        })
+    }
+
+    /// Get the round-trip time of the most recent PING sent to the server,
+    /// or `None` if no PING has been answered yet.
+    pub fn lag(&self) -> Option<Duration> {
+        self.keepalive.lag()
+    }
+
+    /// Get the number of messages waiting in the send queue.
+    pub fn queued(&self) -> usize {
+        self.throttle.len()
+    }
+
+    /// Get the number of bytes waiting in the send queue.
+    pub fn queued_bytes(&self) -> usize {
+        self.throttle.queued_bytes()
+    }
+
+    /// Retrieves the capabilities advertised by the server and enabled for this connection.
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// Retrieves the features advertised by the server with `RPL_ISUPPORT`.
+    pub fn isupport(&self) -> &ISupport {
+        self.state.isupport()
+    }
+
+    /// Retrieves the channels, users and nickname tracked from the messages received so far.
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    // This is intended for internal usage and thus hidden.
+    #[doc(hidden)]
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    // This is intended for internal usage and thus hidden.
+    // Messages that were read from the server while the connection was being
+    // set up are handed back here so that the stream yields them first.
+    #[doc(hidden)]
+    pub fn requeue(&mut self, message: Message) {
+        self.buffered.push_back(message);
+    }
+
+    // Sends a message generated by the transport itself, such as a PONG reply, ahead
+    // of the send queue. When the framed buffer is full, the message is kept until
+    // there is room for it again.
+    fn send_now(&mut self, message: Message) -> Result<(), Error> {
+        self.urgent.push_back(message);
+        self.flush_queue()?;
+        self.inner.poll_complete()?;
+
+        Ok(())
+    }
+
+    // Moves as many messages from the send queue into the framed buffer as the throttle
+    // allows, after the messages generated by the transport itself.
+    fn flush_queue(&mut self) -> Result<(), Error> {
+        while let Some(message) = self.urgent.pop_front() {
+            if let AsyncSink::NotReady(message) = self.inner.start_send(message)? {
+                self.urgent.push_front(message);
+                return Ok(());
+            }
         }
+
+        while let Some(message) = self.throttle.pop()? {
+            if let AsyncSink::NotReady(message) = self.inner.start_send(message)? {
+                self.throttle.unpop(message);
+                break;
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -50,30 +161,63 @@ impl<T> Stream for IrcTransport<T>
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        if self.last_ping.elapsed().as_secs() >= PING_TIMEOUT_IN_SECONDS {
-            self.close()?;
-            return Err(ErrorKind::ConnectionReset.into());
+        // NOTE: Messages handed back with `requeue` were already tracked when they were first read.
+        if let Some(message) = self.buffered.pop_front() {
+            return Ok(Async::Ready(Some(message)));
         }
 
+        // NOTE: The send queue is drained here as well, so that it keeps moving when
+        // only the stream half of a split transport is being polled.
+        self.poll_complete()?;
+
         loop {
-            match try_ready!(self.inner.poll()) {
-                Some(ref message) if message.raw_command() == "PING" => {
-                    self.last_ping = time::Instant::now();
+            match self.keepalive.poll() {
+                Ok(Some(ping)) => self.send_now(ping)?,
+                Ok(None) => {}
+                Err(error) => {
+                    self.close()?;
+                    return Err(error);
+                }
+            }
 
-                    if let Some(host) = message.raw_args().next() {
-                        let result = self.inner.start_send(Message::pong(host)?)?;
+            let message = try_ready!(self.inner.poll());
+
+            if let Some(ref message) = message {
+                if self.keepalive.received(message) {
+                    continue;
+                }
+            }
 
-                        assert!(result.is_ready());
+            match message {
+                Some(ref message) if message.raw_command() == "PING" => {
+                    if let Some(host) = message.raw_args().next() {
+                        self.send_now(Message::pong(host)?)?;
+                    }
+                }
+                Some(message) => {
+                    if let Some(cap) = message.command::<Cap>() {
+                        for reply in self.capabilities.handle(&cap)? {
+                            self.send_now(reply)?;
+                        }
+                    }
 
-                        self.inner.poll_complete()?;
+                    if let Some(ref mut ctcp) = self.ctcp {
+                        if let Some(reply) = ctcp.handle(&message, self.state.isupport()) {
+                            self.throttle.push(reply);
+                        }
                     }
+
+                    self.state.handle(&message);
+
+                    return Ok(Async::Ready(Some(message)));
                 }
-                message => return Ok(Async::Ready(message)),
+                None => return Ok(Async::Ready(None)),
             }
         }
     }
 }
 
+
 impl<T> Sink for IrcTransport<T>
     where T: AsyncRead + AsyncWrite
 {
@@ -81,12 +225,77 @@ impl<T> Sink for IrcTransport<T>
     type SinkError = Error;
 
     fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
-        Ok(self.inner.start_send(item)?)
+        match item.raw_command() {
+            "PONG" | "QUIT" => Ok(self.inner.start_send(item)?),
+            _ => {
+                self.throttle.push(item);
+                Ok(AsyncSink::Ready)
+            }
+        }
     }
 
     fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
-        Ok(self.inner.poll_complete()?)
+        self.flush_queue()?;
+        try_ready!(self.inner.poll_complete());
+
+        if self.throttle.is_empty() && self.urgent.is_empty() {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
     }
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    use futures::future;
+    use tokio_core::net::TcpStream;
+    use tokio_core::reactor::Core;
+
+    use irc::encoding::Encodings;
+
+    use super::*;
+
+    #[test]
+    fn pong_and_quit_skip_the_send_queue() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = core.run(TcpStream::connect(&listener.local_addr().unwrap(), &handle)).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+
+        // The bucket only holds enough for the first message, and barely refills.
+        let config = Config {
+            send_burst: 1,
+            send_rate: 1,
+            ..Config::new("me")
+        };
+        let encodings = Encodings::new(config.encoding, &config.target_encodings);
+        let framed = stream.framed(codec::IrcCodec::new(config.max_line_length, encodings));
+        let mut transport = IrcTransport::new(framed, &handle, &config).unwrap();
+
+        core.run(future::lazy(|| -> Result<(), Error> {
+            for line in &["PRIVMSG #a :one", "PRIVMSG #a :two", "PONG :server", "QUIT :bye"] {
+                assert!(transport.start_send(Message::try_from(line.to_string())?)?.is_ready());
+            }
+
+            assert!(transport.poll_complete()?.is_not_ready());
+
+            Ok(())
+        })).unwrap();
+
+        assert_eq!(transport.queued(), 1);
+
+        let expected = "PONG :server\r\nQUIT :bye\r\nPRIVMSG #a :one\r\n";
+        let mut received = vec![0; expected.len()];
+        server.read_exact(&mut received).unwrap();
+
+        assert_eq!(String::from_utf8(received).unwrap(), expected);
+    }
+}
@@ -4,64 +4,450 @@ extern crate futures;
 #[macro_use]
 extern crate error_chain;
 extern crate tokio_core;
+extern crate tokio_io;
 
-use std::net::ToSocketAddrs;
-use std::io;
-use std::sync::Arc;
-use std::io::prelude::*;
-use std::io::BufWriter;
+use std::cell::{Ref, RefCell};
+use std::env;
+use std::io::{self, BufRead};
+use std::process;
+use std::rc::Rc;
+use std::thread;
+use std::time::SystemTime;
 
-use tokio_core::reactor::Core;
+use tokio_core::reactor::{Core, Handle};
+use tokio_io::{AsyncRead, AsyncWrite};
+use futures::{Poll, Sink, StartSend};
 use futures::future::Future;
-use futures::Stream;
-use futures::Sink;
-use futures::stream;
+use futures::stream::{self, Stream};
+use futures::sync::mpsc::{self, UnboundedSender};
 
 //use tokio_irc_client::Client;
+use irc::ctcp::Ctcp;
+use irc::error::Error;
+use irc::event::Event;
+use irc::formatting;
 use irc::message::Message;
-use irc::command::PrivMsg;
-//use irc::error::{Error, ErrorKind};
+use irc::state::State;
+use irc::tag::ServerTime;
+use irc::tls::TlsConfig;
+use irc::transport::IrcTransport;
 
 
 mod irc;
 
-fn main() {
-    // Create the event loop
-    let mut ev = Core::new().unwrap();
-    let handle = ev.handle();
+const USAGE: &str = "Usage: tokio_irc_client [--tls] <host:port> <nick> [channel...]";
+
+// What the client reacts to: a message from the server, a line typed by the
+// user, or the end of the connection.
+enum Input {
+    Server(Message),
+    Line(String),
+    Closed,
+}
+
+// The transport, shared between the futures reading from and writing to the server
+// and the user interface, which looks up what it tracked about the connection.
+struct Shared<T>(Rc<RefCell<IrcTransport<T>>>)
+    where T: AsyncRead + AsyncWrite;
+
+impl<T> Clone for Shared<T>
+    where T: AsyncRead + AsyncWrite
+{
+    fn clone(&self) -> Shared<T> {
+        Shared(self.0.clone())
+    }
+}
+
+impl<T> Stream for Shared<T>
+    where T: AsyncRead + AsyncWrite
+{
+    type Item = Message;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Message>, Error> {
+        self.0.borrow_mut().poll()
+    }
+}
+
+impl<T> Sink for Shared<T>
+    where T: AsyncRead + AsyncWrite
+{
+    type SinkItem = Message;
+    type SinkError = Error;
+
+    fn start_send(&mut self, item: Message) -> StartSend<Message, Error> {
+        self.0.borrow_mut().start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Error> {
+        self.0.borrow_mut().poll_complete()
+    }
+}
+
+// The state of the user interface. What the client knows about the connection,
+// such as our nickname and the channels we're in, is tracked by the transport.
+struct Ui<T>
+    where T: AsyncRead + AsyncWrite
+{
+    transport: Shared<T>,
+    current: Option<String>,
+    outgoing: UnboundedSender<Message>,
+}
+
+impl<T> Ui<T>
+    where T: AsyncRead + AsyncWrite
+{
+    fn send(&self, message: Message) {
+        // NOTE: Sending only fails once the connection is gone, which ends the client anyway.
+        let _ = self.outgoing.unbounded_send(message);
+    }
+
+    fn state(&self) -> Ref<'_, State> {
+        Ref::map((self.transport.0).borrow(), |transport| transport.state())
+    }
+
+    fn nick(&self) -> String {
+        self.state().nick().unwrap_or("*").to_owned()
+    }
+
+    // Handles a line typed by the user: either a command starting with `/` or
+    // text to send to the current window.
+    fn line(&mut self, line: &str) -> Result<(), Error> {
+        let line = line.trim_end();
 
-    // Do a DNS query and get the first socket address for Freenode
-    let addr = "irc.freenode.org:6667".to_socket_addrs().unwrap().next().unwrap();
-
-    // Create the client future and connect to the server
-    // In order to connect we need to send a NICK message,
-    // followed by a USER message
-    let client = irc::client::Client::new(addr)
-        .connect(&handle)
-        .and_then(|mut irc| {
-            let connect_sequence = vec! [
-                Message::nick("RustChatBot"),
-                Message::user("RustChatBot", "Example of a chat bot written in Rust"),
-                Message::join("#rustic",Some("password"))
-            ];
-
-            irc.send_all(stream::iter(connect_sequence))
-            
-        }).and_then(|(irc, _)| {
- 
-            // We iterate over the IRC connection, giving us all the packets
-            // Checking if the command is PRIVMSG allows us to print just the
-            // messages
-            irc.for_each(|incoming_message| {
-                if let Some(PrivMsg(_, message)) = incoming_message.command::<PrivMsg>() {
-                    if let Some((nick, _, _)) = incoming_message.prefix() {
-                        println!("<{}> {}", nick, message)
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        if !line.starts_with('/') || line.starts_with("//") {
+            let text = if line.starts_with("//") { &line[1..] } else { line };
+            return self.say(text);
+        }
+
+        let mut parts = line[1..].splitn(2, ' ');
+        let command = parts.next().unwrap_or("").to_ascii_lowercase();
+        let rest = parts.next().map(|rest| rest.trim()).filter(|rest| !rest.is_empty());
+
+        match (command.as_str(), rest) {
+            ("join", Some(rest)) => {
+                let mut args = rest.splitn(2, ' ');
+                let channels = args.next().unwrap_or("");
+
+                self.send(Message::join(channels, args.next())?);
+                self.current = channels.split(',').next().map(|channel| channel.to_owned());
+            }
+            ("part", rest) => {
+                let (channel, reason) = match rest {
+                    Some(rest) if self.state().isupport().is_channel(rest) => {
+                        let mut args = rest.splitn(2, ' ');
+                        (args.next().map(|channel| channel.to_owned()), args.next())
                     }
+                    rest => (self.current.clone(), rest),
+                };
+
+                match channel {
+                    Some(channel) => self.send(Message::part(&channel, reason)?),
+                    None => status("You are not in a channel."),
                 }
+            }
+            ("msg", Some(rest)) => {
+                let mut args = rest.splitn(2, ' ');
+
+                match (args.next(), args.next()) {
+                    (Some(target), Some(text)) => self.message(target, text)?,
+                    _ => status("Usage: /msg <target> <text>"),
+                }
+            }
+            ("me", Some(text)) => match self.current.clone() {
+                Some(target) => {
+                    self.send(Message::action(&target, text)?);
+                    println!("{} {} * {} {}", timestamp(None), target, self.nick(), render(text));
+                }
+                None => status("There is no window to send to. Use /join or /query first."),
+            },
+            ("nick", Some(nick)) => self.send(Message::nick(nick)?),
+            ("query", Some(target)) => {
+                self.current = Some(target.to_owned());
+                status(&format!("Now talking to {}.", target));
+            }
+            ("query", None) => match self.current {
+                Some(ref target) => status(&format!("Now talking to {}.", target)),
+                None => status("There is no current window."),
+            },
+            ("raw", Some(raw)) => self.send(Message::try_from(raw.to_owned())?),
+            ("quit", reason) => self.send(Message::quit(reason)?),
+            ("join", None) | ("msg", None) | ("me", None) | ("nick", None) | ("raw", None) => {
+                status(&format!("/{} needs an argument.", command))
+            }
+            _ => status(&format!(
+                "Unknown command /{}. Commands: /join, /part, /msg, /me, /nick, /query, /raw, /quit",
+                command
+            )),
+        }
+
+        Ok(())
+    }
 
+    // Sends text to the current window.
+    fn say(&mut self, text: &str) -> Result<(), Error> {
+        match self.current.clone() {
+            Some(target) => self.message(&target, text),
+            None => {
+                status("There is no window to send to. Use /join or /query first.");
                 Ok(())
-            })
+            }
+        }
+    }
+
+    fn message(&mut self, target: &str, text: &str) -> Result<(), Error> {
+        for message in Message::split_priv_msg(target, text, None)? {
+            self.send(message);
+        }
+
+        println!("{} {} <{}> {}", timestamp(None), target, self.nick(), render(text));
+
+        Ok(())
+    }
+
+    // Prints a message received from the server.
+    fn server(&mut self, message: &Message) {
+        let time = timestamp(message.tag::<ServerTime>());
+
+        match Event::new(message) {
+            Event::Message { source, target, text } => {
+                let source = source.unwrap_or("*");
+
+                if self.state().is_me(target) {
+                    println!("{} *{}* {}", time, source, render(text));
+                } else {
+                    println!("{} {} <{}> {}", time, target, source, render(text));
+                }
+            }
+            Event::Notice { source, text, .. } => println!("{} -{}- {}", time, source.unwrap_or("*"), render(text)),
+            Event::Ctcp { source, target, command, params } => {
+                let source = source.unwrap_or("*");
+
+                if Ctcp(command, params).is_action() {
+                    let place = if self.state().is_me(target) { String::new() } else { format!("{} ", target) };
+                    println!("{} {}* {} {}", time, place, source, render(params.unwrap_or("")));
+                } else {
+                    println!("{} -- {} sent a CTCP {} request", time, source, command);
+                }
+            }
+            Event::CtcpReply { source, command, params, .. } => {
+                println!("{} -- CTCP {} reply from {}: {}", time, command, source.unwrap_or("*"), params.unwrap_or(""))
+            }
+            Event::Join { nick: joined, channel } => {
+                if self.state().is_me(joined) {
+                    self.current = Some(channel.to_owned());
+                }
+
+                println!("{} {} -- {} joined", time, channel, joined);
+            }
+            Event::Part { nick: parted, channel, reason } => {
+                println!("{} {} -- {} left{}", time, channel, parted, reason_suffix(reason))
+            }
+            Event::Kick { source, kicks, reason } => {
+                for (channel, kicked) in kicks {
+                    println!(
+                        "{} {} -- {} was kicked by {}{}",
+                        time,
+                        channel,
+                        kicked,
+                        source.unwrap_or("*"),
+                        reason_suffix(reason)
+                    );
+                }
+            }
+            Event::NickChange { old, new } => println!("{} -- {} is now known as {}", time, old, new),
+            Event::Quit { nick: quit, reason } => println!("{} -- {} quit{}", time, quit, reason_suffix(reason)),
+            Event::Topic { source, channel, topic } => println!(
+                "{} {} -- {} changed the topic to: {}",
+                time,
+                channel,
+                source.unwrap_or("*"),
+                render(topic)
+            ),
+            Event::Mode { source, target, modes, arguments } => println!(
+                "{} -- {} sets mode {} {} on {}",
+                time,
+                source.unwrap_or("*"),
+                modes,
+                arguments.join(" "),
+                target
+            ),
+            Event::Invite { source, channel, .. } => {
+                println!("{} -- {} invited you to {}", time, source.unwrap_or("*"), channel)
+            }
+            Event::Numeric { arguments, .. } => println!("{} -- {}", time, render(&arguments.join(" "))),
+            Event::Raw(message) => match message.raw_command() {
+                "PONG" | "CAP" | "AUTHENTICATE" => {}
+                _ => println!("{} -- {}", time, message.raw_message()),
+            },
+        }
+    }
+}
+
+// Prints a status line from the client itself.
+fn status(text: &str) {
+    println!("{} -- {}", timestamp(None), text);
+}
+
+// Formats the time a message was sent as `[hh:mm:ss]` in UTC, using the
+// `server-time` tag when the server provides it.
+fn timestamp(time: Option<ServerTime>) -> String {
+    let time = time.unwrap_or(ServerTime(SystemTime::now())).to_string();
+
+    // NOTE: The time is displayed as `YYYY-MM-DDThh:mm:ss.sssZ`.
+    format!("[{}]", &time[11..19])
+}
+
+// Renders the formatting codes of a message for the terminal.
+fn render(text: &str) -> String {
+    formatting::to_ansi(&formatting::parse(text))
+}
+
+fn reason_suffix(reason: Option<&str>) -> String {
+    match reason {
+        Some(reason) if !reason.is_empty() => format!(" ({})", render(reason)),
+        _ => String::new(),
+    }
+}
+
+// Runs the user interface on a registered connection until the server closes it.
+fn run<T>(transport: IrcTransport<T>, handle: &Handle, channels: Vec<String>) -> Box<dyn Future<Item = (), Error = Error>>
+where
+    T: AsyncRead + AsyncWrite + 'static,
+{
+    let transport = Shared(Rc::new(RefCell::new(transport)));
+    let (outgoing, outgoing_queue) = mpsc::unbounded::<Message>();
+
+    // The messages queued by the user interface are forwarded to the server as they come in.
+    handle.spawn(
+        outgoing_queue
+            .map_err(|()| -> Error { unreachable!("unbounded receivers never fail") })
+            .forward(transport.clone())
+            .map(|_| ())
+            .map_err(|error| eprintln!("Failed to send to the server: {}", error)),
+    );
+
+    // Stdin is read on its own thread, since reading it blocks.
+    let (lines, typed) = mpsc::unbounded::<String>();
+
+    thread::spawn(move || {
+        let stdin = io::stdin();
+
+        for line in stdin.lock().lines() {
+            match line {
+                Ok(line) => if lines.unbounded_send(line).is_err() {
+                    break;
+                },
+                Err(_) => break,
+            }
+        }
+    });
+
+    let mut ui = Ui {
+        transport: transport.clone(),
+        current: None,
+        outgoing: outgoing,
+    };
+
+    for channel in &channels {
+        if let Err(error) = Message::join(channel, None).map(|message| ui.send(message)) {
+            status(&format!("Could not join {}: {}", channel, error));
+        }
+    }
+
+    // When stdin is closed, we quit.
+    let typed = typed
+        .map(Input::Line)
+        .chain(stream::once(Ok(Input::Line("/quit".to_owned()))))
+        .map_err(|()| -> Error { unreachable!("unbounded receivers never fail") });
+
+    // Errors the connection recovers from, such as a line that isn't a valid message, are
+    // only reported.
+    let server = transport
+        .then(|result| match result {
+            Err(ref error) if error.is_recoverable() => {
+                status(&error.to_string());
+                Ok(None)
+            }
+            result => result.map(Some),
+        })
+        .filter_map(|message| message);
+
+    let inputs = server
+        .map(Input::Server)
+        .chain(stream::once(Ok(Input::Closed)))
+        .select(typed)
+        .take_while(|input| match *input {
+            Input::Closed => Ok(false),
+            _ => Ok(true),
         });
 
-    ev.run(client).unwrap();
-}
\ No newline at end of file
+    Box::new(inputs.for_each(move |input| {
+        match input {
+            Input::Server(message) => ui.server(&message),
+            Input::Line(line) => if let Err(error) = ui.line(&line) {
+                status(&error.to_string());
+            },
+            Input::Closed => {}
+        }
+
+        Ok(())
+    }))
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let tls = match args.iter().position(|arg| arg == "--tls") {
+        Some(index) => {
+            args.remove(index);
+            true
+        }
+        None => false,
+    };
+
+    if args.len() < 2 {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+
+    let host = args.remove(0);
+    let nick = args.remove(0);
+    let channels = args;
+
+    // Create the event loop
+    let mut ev = Core::new().unwrap();
+    let handle = ev.handle();
+
+    // The registration details sent to the server once connected.
+    let config = irc::config::Config {
+        realname: "tokio_irc_client".to_owned(),
+        capabilities: vec!["server-time".to_owned(), "multi-prefix".to_owned(), "away-notify".to_owned()],
+        ..irc::config::Config::new(&nick)
+    };
+
+    status(&format!("Connecting to {}...", host));
+
+    // Create the client future, resolve the host name and connect to the server.
+    // The future resolves once the server has accepted our NICK and USER
+    // messages, so it's safe to start joining channels.
+    let client = irc::client::Client::new(host, config);
+    let run_handle = handle.clone();
+
+    let session: Box<dyn Future<Item = (), Error = Error>> = if tls {
+        Box::new(client.connect_tls(&handle, &TlsConfig::default()).and_then(move |irc| run(irc, &run_handle, channels)))
+    } else {
+        Box::new(client.connect(&handle).and_then(move |irc| run(irc, &run_handle, channels)))
+    };
+
+    match ev.run(session) {
+        Ok(()) => status("Disconnected."),
+        Err(error) => {
+            status(&format!("Error: {}", error));
+            process::exit(1);
+        }
+    }
+}